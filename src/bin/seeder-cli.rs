@@ -0,0 +1,156 @@
+//! Command-line client for the seeder's JSON-RPC interface, like `bitcoin-cli`.
+//!
+//! ```text
+//! seeder-cli getinfo
+//! seeder-cli addnode 1.2.3.4:8333
+//! seeder-cli setban 10.0.0.0/24 add 12h spam
+//! seeder-cli listpeers status=connected_recently limit=20
+//! seeder-cli setloglevel filter=dns=trace
+//! ```
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::Parser;
+use serde_json::{json, Map, Value};
+
+/// Sends one JSON-RPC call to a running seeder and prints the result
+#[derive(Parser, Debug)]
+#[command(name = "seeder-cli")]
+#[command(version)]
+struct Args {
+    /// Address given to the seeder's --rpc-listen, which has no default of its own
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8337")]
+    rpc_connect: SocketAddr,
+
+    /// RPC user; without it the cookie file is used
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+
+    /// RPC password for --rpc-user
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+
+    /// Cookie written by the seeder; the default is where a seeder started in the
+    /// current directory without --db-path or --rpc-cookie-file writes it
+    #[arg(long, value_name = "PATH", default_value = ".cookie")]
+    rpc_cookie_file: PathBuf,
+
+    /// Seconds to wait for the answer
+    #[arg(long, default_value_t = 30)]
+    rpc_timeout: u64,
+
+    /// Method to call, e.g. getinfo
+    method: String,
+
+    /// Positional arguments, or NAME=VALUE pairs for named ones; values are read as JSON when they parse
+    params: Vec<String>,
+}
+
+/// A JSON literal when it parses as one (`12`, `true`, `[..]`), a string otherwise.
+fn parse_value(arg: &str) -> Value {
+    serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.to_string()))
+}
+
+fn named_pair(arg: &str) -> Option<(&str, &str)> {
+    arg.split_once('=')
+        .filter(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+/// `a b` -> `["a","b"]`, `k=v x=1` -> `{"k":"v","x":1}`; mixing both isn't allowed.
+fn build_params(args: &[String]) -> Result<Value, String> {
+    let pairs: Vec<_> = args.iter().filter_map(|a| named_pair(a)).collect();
+    if pairs.is_empty() {
+        return Ok(Value::Array(args.iter().map(|a| parse_value(a)).collect()));
+    }
+    if pairs.len() != args.len() {
+        return Err("não misture argumentos posicionais com NOME=VALOR".into());
+    }
+    let map: Map<String, Value> = pairs.into_iter().map(|(k, v)| (k.to_string(), parse_value(v))).collect();
+    Ok(Value::Object(map))
+}
+
+fn credentials(args: &Args) -> Result<(String, String), String> {
+    if let (Some(user), Some(password)) = (&args.rpc_user, &args.rpc_password) {
+        return Ok((user.clone(), password.clone()));
+    }
+    let cookie = fs::read_to_string(&args.rpc_cookie_file).map_err(|e| {
+        format!("não foi possível ler o cookie {}: {} (use --rpc-cookie-file ou --rpc-user)", args.rpc_cookie_file.display(), e)
+    })?;
+    cookie
+        .trim()
+        .split_once(':')
+        .map(|(user, password)| (user.to_string(), password.to_string()))
+        .ok_or_else(|| format!("cookie inválido: {}", args.rpc_cookie_file.display()))
+}
+
+/// POSTs `body` and returns the HTTP status code and the response body.
+fn post(args: &Args, user: &str, password: &str, body: &str) -> io::Result<(u16, String)> {
+    let mut stream = TcpStream::connect_timeout(&args.rpc_connect, Duration::from_secs(5))?;
+    stream.set_read_timeout(Some(Duration::from_secs(args.rpc_timeout.max(1))))?;
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: {}\r\nAuthorization: Basic {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        args.rpc_connect,
+        BASE64.encode(format!("{}:{}", user, password)),
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "resposta HTTP malformada"))?;
+    Ok((status, body.to_string()))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let params = build_params(&args.params)?;
+    let (user, password) = credentials(args)?;
+    let request = json!({ "jsonrpc": "2.0", "method": args.method, "params": params, "id": 1 });
+    let (status, body) = post(args, &user, &password, &request.to_string()).map_err(|e| {
+        format!("não foi possível falar com o seeder em {}: {} (ele está rodando com --rpc-listen?)", args.rpc_connect, e)
+    })?;
+    match status {
+        200 => {}
+        401 => return Err("credenciais RPC incorretas".into()),
+        403 => return Err(format!("este endereço não pode chamar o RPC em {} (veja --rpc-allowip)", args.rpc_connect)),
+        other => return Err(format!("resposta HTTP {} do seeder: {}", other, body.trim())),
+    }
+
+    let answer: Value = serde_json::from_str(&body).map_err(|e| format!("resposta inválida do seeder: {}", e))?;
+    if let Some(error) = answer.get("error") {
+        return Err(format!(
+            "error code: {}\nerror message:\n{}",
+            error["code"],
+            error["message"].as_str().unwrap_or_default()
+        ));
+    }
+    // Como o bitcoin-cli: strings saem sem aspas e null não imprime nada
+    match &answer["result"] {
+        Value::Null => {}
+        Value::String(s) => println!("{}", s),
+        result => println!("{}", serde_json::to_string_pretty(result).unwrap_or_default()),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
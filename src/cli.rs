@@ -1,5 +1,16 @@
-use clap::{Parser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::p2p::peer_store::DbBackend;
+use crate::p2p::multhread::FlushPolicy;
+use crate::p2p::retention::RetentionPolicy;
+use crate::p2p::banlist::{parse_duration, Subnet};
+use crate::p2p::database::PeerStatus;
+use crate::p2p::log::{LogFileConfig, LogFilter, LogFormat, LogLevel};
+use crate::p2p::rpc_auth::{Credential, RpcAuth};
+use crate::p2p::peer_export::{parse_services, parse_status, ExportFormat, NetworkKind, PeerFilter};
 
 /// A Bitcoin P2P client for connecting to and exploring the Bitcoin network
 #[derive(Parser, Debug)]
@@ -12,9 +23,10 @@ pub struct Cli {
     #[arg(long, default_value = "seed.bitcoin.sipa.be")]
     pub host: String,
 
-    /// Verbosity level for logging (trace, debug, info, warn, error)
-    #[arg(long, default_value = "info")]
-    pub verbosity: String,
+    /// Log level (trace, debug, info, warn, error), optionally per component:
+    /// info,dns=trace,crawler=warn (components: client, crawler, db, dns, cli, rpc)
+    #[arg(long, default_value = "info", value_parser = LogFilter::parse)]
+    pub verbosity: LogFilter,
 
     /// Bitcoin node port to connect to
     #[arg(long, default_value_t = 8333)]
@@ -24,10 +36,38 @@ pub struct Cli {
     #[arg(long, default_value_t = 1)]
     pub threads: usize,
 
-    /// Path to log file for output (future feature)
+    /// Write timestamped log lines to this file instead of the terminal
     #[arg(long)]
     pub logfile: Option<PathBuf>,
 
+    /// Rotate the log file once it reaches this size (bytes, or 512K, 10M, 1G; 0 = never)
+    #[arg(long, value_parser = parse_size, default_value = "10M")]
+    pub log_max_size: u64,
+
+    /// Rotate the log file once it is this old (3600, 30m, 12h, 7d; 0 = never)
+    #[arg(long, value_parser = parse_age, default_value = "1d")]
+    pub log_rotate: Duration,
+
+    /// Rotated log files to keep (logfile.1 is the newest)
+    #[arg(long, default_value_t = 5)]
+    pub log_keep: usize,
+
+    /// Render log lines as text or as one JSON object per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Keep logging to the terminal when --logfile is set
+    #[arg(long)]
+    pub log_stdout: bool,
+
+    /// Log lines kept in memory for the interactive `logs` command (0 = none)
+    #[arg(long, default_value_t = 1000)]
+    pub log_buffer: usize,
+
+    /// Lowest level kept in the `logs` buffer, whatever --verbosity prints
+    #[arg(long, value_parser = parse_log_level, default_value = "debug")]
+    pub log_buffer_level: LogLevel,
+
     /// Enable verbose output
     #[arg(short, long)]
     pub verbose: bool,
@@ -47,12 +87,189 @@ pub struct Cli {
     /// Protocol version to advertise
     #[arg(long, default_value_t = 70015)]
     pub protocol_version: u32,
+
+    /// Peer database backend
+    #[arg(long, value_enum, default_value_t = DbBackend::Json)]
+    pub db: DbBackend,
+
+    /// Path of the peer database (defaults to peers.json / peers.sqlite)
+    #[arg(long)]
+    pub db_path: Option<PathBuf>,
+
+    /// Bitcoin Core peers.dat or anchors.dat to import on startup (repeatable)
+    #[arg(long, value_name = "PATH")]
+    pub import_core: Vec<PathBuf>,
+
+    /// Path of the ban list (defaults to banlist.json next to the database)
+    #[arg(long)]
+    pub banlist: Option<PathBuf>,
+
+    /// Seconds between database flushes while crawling
+    #[arg(long, default_value_t = 5)]
+    pub db_flush_interval: u64,
+
+    /// Flush the database early once this many changes are pending
+    #[arg(long, default_value_t = 1000)]
+    pub db_flush_threshold: usize,
+
+    /// Append-only crawl history (defaults to history.log next to the database)
+    #[arg(long)]
+    pub history: Option<PathBuf>,
+
+    /// Forget peers after this many failed attempts in a row (0 keeps them)
+    #[arg(long, default_value_t = 10)]
+    pub prune_attempts: u32,
+
+    /// Forget peers not reached for this long (3600, 30m, 12h, 7d; 0 keeps them)
+    #[arg(long, value_parser = parse_age, default_value = "30d")]
+    pub prune_max_age: Duration,
+
+    /// Keep at most this many peers per /16 (IPv4) or /32 (IPv6), also as addresses arrive (0 = unlimited)
+    #[arg(long, default_value_t = 64)]
+    pub netgroup_limit: usize,
+
+    /// Bitcoin Core asmap file; groups peers by autonomous system
+    #[arg(long, value_name = "PATH")]
+    pub asmap: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on http://ADDR/metrics (e.g. 127.0.0.1:9332)
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+
+    /// Serve the JSON-RPC control interface on http://ADDR/; seeder-cli connects
+    /// to 127.0.0.1:8337 by default. Non-loopback needs --rpc-allowip
+    #[arg(long, value_name = "ADDR")]
+    pub rpc_listen: Option<SocketAddr>,
+
+    /// RPC user name; without --rpc-password a cookie file is used instead
+    #[arg(long, requires = "rpc_password")]
+    pub rpc_user: Option<String>,
+
+    /// RPC password for --rpc-user
+    #[arg(long, requires = "rpc_user")]
+    pub rpc_password: Option<String>,
+
+    /// Extra RPC user as USER:SALT$HASH, as made by Bitcoin Core's rpcauth.py (repeatable)
+    #[arg(long, value_name = "USER:SALT$HASH", value_parser = Credential::parse_rpcauth)]
+    pub rpc_auth: Vec<Credential>,
+
+    /// Also accept RPC calls from this IP or subnet; loopback always can (repeatable)
+    #[arg(long, value_name = "SUBNET")]
+    pub rpc_allowip: Vec<Subnet>,
+
+    /// Where the RPC cookie is written (defaults to .cookie next to the database)
+    #[arg(long, value_name = "PATH")]
+    pub rpc_cookie_file: Option<PathBuf>,
+
+    /// Write a bitcoin-seeder compatible dnsseed.dump here periodically
+    #[arg(long)]
+    pub dump_file: Option<PathBuf>,
+
+    /// Seconds between dnsseed.dump rewrites
+    #[arg(long, default_value_t = 100)]
+    pub dump_interval: u64,
+
+    /// Run a one-off database command instead of the interactive shell
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Write the peer database as CSV or JSON lines
+    Export {
+        /// Output file (stdout if omitted)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Defaults to csv for *.csv outputs, jsonl otherwise
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Merge peers from a CSV or JSON lines file into the database
+    Import {
+        /// Input file (`-` for stdin)
+        input: PathBuf,
+        /// Defaults to csv for *.csv inputs, jsonl otherwise
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Census of the peer database: networks, user agents, versions, services and ports
+    Report {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+        /// Rows shown per table before the rest is summed up
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct FilterArgs {
+    /// Only peers with this status (e.g. connected-recently)
+    #[arg(long, value_parser = parse_status)]
+    pub status: Option<PeerStatus>,
+    /// Only IPv4 or IPv6 peers
+    #[arg(long, value_enum)]
+    pub network: Option<NetworkKind>,
+    /// Only peers advertising all these service bits (decimal or 0x hex)
+    #[arg(long, value_parser = parse_services)]
+    pub services: Option<u64>,
+    /// Only peers seen within this long (3600, 30m, 12h, 7d)
+    #[arg(long, value_parser = parse_age)]
+    pub max_age: Option<Duration>,
+}
+
+fn parse_age(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| format!("invalid duration: {}", s))
+}
+
+fn parse_log_level(s: &str) -> Result<LogLevel, String> {
+    LogLevel::from_str(s).ok_or_else(|| format!("invalid log level: {}", s))
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (num, mult) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1 << 10),
+        Some('M') => (&s[..s.len() - 1], 1 << 20),
+        Some('G') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    num.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(mult))
+        .ok_or_else(|| format!("invalid size: {}", s))
+}
+
+impl From<FilterArgs> for PeerFilter {
+    fn from(a: FilterArgs) -> Self {
+        PeerFilter { status: a.status, network: a.network, services: a.services, max_age: a.max_age }
+    }
 }
 
 impl Cli {
     /// Parse command line arguments
     pub fn parse() -> Self {
-        Parser::parse()
+        let cli: Cli = Parser::parse();
+        if let Err(e) = cli.validate() {
+            Cli::command().error(ErrorKind::ArgumentConflict, e).exit();
+        }
+        cli
+    }
+
+    /// Checks the combinations clap can't express on its own.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(addr) = self.rpc_listen {
+            // Sem --rpc-allowip ninguém de fora passaria mesmo; escutar fora da loopback só expõe a porta
+            if !addr.ip().is_loopback() && self.rpc_allowip.is_empty() {
+                return Err(format!("--rpc-listen {} is not a loopback address; add --rpc-allowip for the clients that may call it", addr));
+            }
+        }
+        Ok(())
     }
 
     /// Get the socket address as a string
@@ -60,6 +277,97 @@ impl Cli {
         format!("{}:{}", self.host, self.port)
     }
 
+    /// Resolve the peer database path for the selected backend
+    pub fn db_path(&self) -> PathBuf {
+        self.db_path.clone().unwrap_or_else(|| self.db.default_path())
+    }
+
+    /// Resolve where bans are persisted; the memory backend keeps them in memory too
+    pub fn banlist_path(&self) -> Option<PathBuf> {
+        if self.banlist.is_some() {
+            return self.banlist.clone();
+        }
+        match self.db {
+            DbBackend::Memory => None,
+            _ => Some(self.db_path().with_file_name("banlist.json")),
+        }
+    }
+
+    /// Resolve where the crawl history is appended;
+    /// `None` keeps the history in memory only (memory backend).
+    pub fn history_path(&self) -> Option<PathBuf> {
+        if self.history.is_some() {
+            return self.history.clone();
+        }
+        match self.db {
+            DbBackend::Memory => None,
+            _ => Some(self.db_path().with_file_name("history.log")),
+        }
+    }
+
+    /// Secret key for addrman bucket placement; kept next to the database so
+    /// buckets survive restarts. The memory backend uses a fresh key each run.
+    pub fn addrman_key_path(&self) -> Option<PathBuf> {
+        match self.db {
+            DbBackend::Memory => None,
+            _ => Some(self.db_path().with_file_name("addrman.key")),
+        }
+    }
+
+    /// Cookie file of the RPC server, written on startup unless `--rpc-password` is set.
+    pub fn rpc_cookie_path(&self) -> Option<PathBuf> {
+        match self.rpc_password {
+            Some(_) => None,
+            None => Some(self.rpc_cookie_file.clone().unwrap_or_else(|| self.db_path().with_file_name(".cookie"))),
+        }
+    }
+
+    /// Allowed subnets and configured users of the RPC server, cookie not included.
+    pub fn rpc_auth(&self) -> RpcAuth {
+        let mut auth = RpcAuth::default();
+        for subnet in &self.rpc_allowip {
+            auth.allow(*subnet);
+        }
+        if let (Some(user), Some(password)) = (&self.rpc_user, &self.rpc_password) {
+            auth.add(Credential::Password { user: user.clone(), password: password.clone() });
+        }
+        for credential in &self.rpc_auth {
+            auth.add(credential.clone());
+        }
+        auth
+    }
+
+    /// File logging settings, if `--logfile` was given.
+    pub fn log_file_config(&self) -> Option<LogFileConfig> {
+        self.logfile.clone().map(|path| LogFileConfig {
+            path,
+            max_size: self.log_max_size,
+            max_age: self.log_rotate,
+            keep: self.log_keep,
+        })
+    }
+
+    /// The terminal only gets log lines when they have nowhere else to go, or on request.
+    pub fn log_to_stdout(&self) -> bool {
+        self.logfile.is_none() || self.log_stdout
+    }
+
+    /// Flush policy for the crawler's database task
+    pub fn flush_policy(&self) -> FlushPolicy {
+        FlushPolicy {
+            interval: Duration::from_secs(self.db_flush_interval.max(1)),
+            max_dirty: self.db_flush_threshold.max(1),
+        }
+    }
+
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_attempts: self.prune_attempts,
+            max_age: self.prune_max_age,
+            max_per_netgroup: self.netgroup_limit,
+        }
+    }
+
     /// Print the current configuration
     pub fn print_config(&self) {
         println!("🔧 Configuration:");
@@ -72,9 +380,52 @@ impl Cli {
         println!("   Protocol version: {}", self.protocol_version);
         println!("   Verbose: {}", self.verbose);
         println!("   Discover peers: {}", self.discover_peers);
+        println!("   Database: {:?} ({})", self.db, self.db_path().display());
+        match self.banlist_path() {
+            Some(path) => println!("   Ban list: {}", path.display()),
+            None => println!("   Ban list: in memory"),
+        }
+        println!("   DB flush: every {}s or {} changes", self.db_flush_interval, self.db_flush_threshold);
+        match self.history_path() {
+            Some(path) => println!("   History: {}", path.display()),
+            None => println!("   History: in memory"),
+        }
+        println!(
+            "   Retention: {} attempts, {}s max age, {} per netgroup",
+            self.prune_attempts, self.prune_max_age.as_secs(), self.netgroup_limit
+        );
+        match &self.dump_file {
+            Some(path) => println!("   Dump: {} every {}s", path.display(), self.dump_interval),
+            None => println!("   Dump: disabled"),
+        }
+        match &self.asmap {
+            Some(path) => println!("   Asmap: {}", path.display()),
+            None => println!("   Asmap: none"),
+        }
+        match &self.metrics_listen {
+            Some(addr) => println!("   Metrics: http://{}/metrics", addr),
+            None => println!("   Metrics: disabled"),
+        }
+        match &self.rpc_listen {
+            Some(addr) => {
+                println!("   RPC: http://{}/", addr);
+                let mut auth: Vec<String> = self.rpc_cookie_path().map(|p| format!("cookie {}", p.display())).into_iter().collect();
+                auth.extend(self.rpc_user.iter().cloned());
+                auth.extend(self.rpc_auth.iter().map(|c| c.user().to_string()));
+                println!("   RPC auth: {}", auth.join(", "));
+                if !self.rpc_allowip.is_empty() {
+                    let subnets: Vec<String> = self.rpc_allowip.iter().map(|s| s.to_string()).collect();
+                    println!("   RPC allow: loopback, {}", subnets.join(", "));
+                }
+            }
+            None => println!("   RPC: disabled"),
+        }
         
         if let Some(ref logfile) = self.logfile {
-            println!("   Log file: {} (stubbed)", logfile.display());
+            println!(
+                "   Log file: {} (rotate at {} bytes or {}s, keep {})",
+                logfile.display(), self.log_max_size, self.log_rotate.as_secs(), self.log_keep
+            );
         } else {
             println!("   Log file: None");
         }
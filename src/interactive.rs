@@ -3,14 +3,43 @@ use crate::p2p::p2p_client::BitcoinClient;
 use crate::cli::Cli;
 use colored::*;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc,Mutex, RwLock};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::net::SocketAddr;
-use std::sync::mpsc::{self, Receiver, Sender};
-use crate::p2p::log::{LogLevel, Event, log, LogMessage};
-use crate::p2p::multhread::{multhread_db, run_crawlers_with_log};
+use crate::p2p::log::{log, Event, LogLevel, LogQuery};
 use crate::p2p::dns_server::run_dns_server;
-use std::collections::HashSet;
+use crate::p2p::services::{Services, DEFAULT_CRAWL_PEERS};
+use crate::p2p::banlist::{ban_subnet, clear_banned, parse_duration, unban_subnet, Subnet, DEFAULT_BAN_TIME};
+use crate::p2p::core_import::import_into;
+use crate::p2p::dnsseed_dump::write_dump;
+use crate::p2p::retention::prune_and_log;
+use crate::p2p::history::{hour_of, plot_reachable};
+use crate::p2p::database::now_secs;
+use crate::p2p::census::{build_census, render_table};
+use crate::p2p::peer_export::{export_to_file, import_from_file, ExportFormat, PeerFilter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How often the prompt and the DNS server check whether the seeder is stopping.
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Lines typed on stdin, read on their own thread; the channel closes at end of input.
+fn spawn_stdin_reader() -> Receiver<io::Result<String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        let mut input = String::new();
+        let line = match io::stdin().read_line(&mut input) {
+            Ok(0) => return,
+            Ok(_) => Ok(input),
+            Err(e) => Err(e),
+        };
+        if tx.send(line).is_err() {
+            return;
+        }
+    });
+    rx
+}
 
 pub enum Command {
     Start,
@@ -20,6 +49,22 @@ pub enum Command {
     SetHost(String),
     SetPort(u16),
     ListPeers,
+    PeerInfo(SocketAddr),
+    Ban(Subnet, Option<Duration>, String),
+    Unban(Subnet),
+    ListBanned,
+    ClearBanned,
+    ImportCore(PathBuf),
+    Dump(Option<PathBuf>),
+    Prune,
+    LogLevel(Option<String>),
+    Logs(LogQuery),
+    LogsFollow(LogQuery),
+    Report(bool),
+    History(u64),
+    PeerHistory(SocketAddr),
+    Export(PathBuf, PeerFilter),
+    Import(PathBuf, PeerFilter),
     Crawl, 
     Dns, // Novo comando
     Help,
@@ -51,6 +96,83 @@ impl Command {
                 }
             }
             Some("peers") => Command::ListPeers,
+            Some("peer") => {
+                if let Some(addr) = parts.get(1).and_then(|a| a.parse().ok()) {
+                    Command::PeerInfo(addr)
+                } else {
+                    Command::Unknown
+                }
+            }
+            Some("ban") => {
+                // ban <ip|subnet> [duração|permanent] [motivo...]
+                let Some(subnet) = parts.get(1).and_then(|s| s.parse().ok()) else {
+                    return Command::Unknown;
+                };
+                let (duration, reason_at) = match parts.get(2).copied() {
+                    Some("permanent") => (None, 3),
+                    Some(d) => match parse_duration(d) {
+                        Some(d) => (Some(d), 3),
+                        None => (Some(DEFAULT_BAN_TIME), 2),
+                    },
+                    None => (Some(DEFAULT_BAN_TIME), 2),
+                };
+                let reason = parts.get(reason_at..).map(|r| r.join(" ")).unwrap_or_default();
+                let reason = if reason.is_empty() { "manual".to_string() } else { reason };
+                Command::Ban(subnet, duration, reason)
+            }
+            Some("unban") => {
+                if let Some(subnet) = parts.get(1).and_then(|s| s.parse().ok()) {
+                    Command::Unban(subnet)
+                } else {
+                    Command::Unknown
+                }
+            }
+            Some("listbanned") => Command::ListBanned,
+            Some("clearbanned") => Command::ClearBanned,
+            Some("importcore") => match parts.get(1) {
+                Some(path) => Command::ImportCore(PathBuf::from(path)),
+                None => Command::Unknown,
+            },
+            Some(cmd @ ("export" | "import")) => {
+                let (Some(path), Some(filter)) = (parts.get(1), parse_filter(&parts[2..])) else {
+                    return Command::Unknown;
+                };
+                if cmd == "export" {
+                    Command::Export(PathBuf::from(path), filter)
+                } else {
+                    Command::Import(PathBuf::from(path), filter)
+                }
+            }
+            Some("prune") => Command::Prune,
+            Some("loglevel") => match parts.get(1) {
+                None => Command::LogLevel(None),
+                Some(_) => Command::LogLevel(Some(parts[1..].join(""))),
+            },
+            Some("logs") => {
+                let (follow, args) = match parts.get(1).copied() {
+                    Some("follow") => (true, &parts[2..]),
+                    _ => (false, &parts[1..]),
+                };
+                match LogQuery::parse(args) {
+                    Some(query) if follow => Command::LogsFollow(query),
+                    Some(query) => Command::Logs(query),
+                    None => Command::Unknown,
+                }
+            }
+            Some("report") => match parts.get(1).copied() {
+                None => Command::Report(false),
+                Some("json") => Command::Report(true),
+                Some(_) => Command::Unknown,
+            },
+            Some("history") => match parts.get(1) {
+                None => Command::History(24),
+                Some(arg) => match (arg.parse(), arg.parse()) {
+                    (Ok(addr), _) => Command::PeerHistory(addr),
+                    (_, Ok(hours)) => Command::History(hours),
+                    _ => Command::Unknown,
+                },
+            },
+            Some("dump") => Command::Dump(parts.get(1).map(PathBuf::from)),
             Some("crawl") => Command::Crawl,
             Some("dns") => Command::Dns, // Novo comando
             Some("help") => Command::Help,
@@ -61,34 +183,34 @@ impl Command {
     }
 }
 
+/// Parses `key=value` filters for `export`/`import`; `None` on anything unknown.
+fn parse_filter(args: &[&str]) -> Option<PeerFilter> {
+    let mut filter = PeerFilter::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=')?;
+        filter.set(key, value).ok()?;
+    }
+    Some(filter)
+}
+
 pub struct InteractiveCli {
     client: Option<BitcoinClient>,
     config: Cli,
     running: bool,
     client_thread: Option<JoinHandle<()>>,
-    client_running: Arc<AtomicBool>,
-    client_rx: Option<Receiver<String>>,
-    bg_printer: Option<JoinHandle<()>>,
-    log_tx: Sender<LogMessage>,
-    crawl_connected: Arc<Mutex<HashSet<SocketAddr>>>, 
     dns_thread: Option<JoinHandle<()>>,
-    peer_db_shared: Option<Arc<RwLock<crate::p2p::database::PeerDatabase>>>,
+    services: Services,
 }
 
 impl InteractiveCli {
-pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
+pub fn new_with_logger(config: Cli, services: Services) -> Self {
     Self {
             client: None,
             config,
             running: true,
             client_thread: None,
-            client_running: Arc::new(AtomicBool::new(false)),
-            client_rx: None,
-            bg_printer: None,
-            log_tx,
-            crawl_connected: Arc::new(Mutex::new(HashSet::new())),
             dns_thread: None,
-            peer_db_shared: None,
+            services,
     }
 }
 
@@ -96,27 +218,44 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
     println!("{}", "🚀 Bitcoin P2P Cliente Interativo".bold().green());
     println!("{}", "Digite 'help' para ver os comandos disponíveis".italic());
 
-    while self.running {
-        print!("\n> ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        if io::stdin().read_line(&mut input).is_err() {
-            println!("Erro ao ler entrada.");
-            continue;
+    // O stdin é lido em outra thread para o loop perceber um `stop` via RPC sem esperar uma linha
+    let lines = spawn_stdin_reader();
+    let mut prompt = true;
+    while self.running && !self.services.is_stopping() {
+        if prompt {
+            print!("\n> ");
+            io::stdout().flush()?;
+            prompt = false;
         }
 
-        let command = Command::from_str(&input);
+        let command = match lines.recv_timeout(STOP_POLL) {
+            Ok(Ok(input)) => Command::from_str(&input),
+            Ok(Err(_)) => {
+                println!("Erro ao ler entrada.");
+                prompt = true;
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                // Sem terminal (rodando como daemon): quem encerra é o `stop` via RPC
+                if self.config.rpc_listen.is_some() {
+                    log(&self.services.log_tx, LogLevel::Info, Event::StdinClosed);
+                    while !self.services.is_stopping() {
+                        thread::sleep(STOP_POLL);
+                    }
+                    break;
+                }
+                Command::Exit
+            }
+        };
+        prompt = true;
         if let Err(e) = self.handle_command(command) {
             println!("❌ Erro ao executar comando: {}", e);
         }
     }
 
-    self.client_running.store(false, Ordering::SeqCst);
+    self.services.client_running.store(false, Ordering::SeqCst);
 
-    if let Some(bg) = self.bg_printer.take() {
-        let _ = bg.join();
-    }
     if let Some(thread) = self.client_thread.take() {
         let _ = thread.join();
     }
@@ -127,18 +266,6 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
     Ok(())
 }
 
-    fn start_bg_printer(&mut self) {
-        if let Some(rx) = self.client_rx.take() {
-            self.bg_printer = Some(std::thread::spawn(move || {
-                while let Ok(msg) = rx.recv() {
-                    println!("\n{}", msg);
-                    print!("> ");
-                    let _ = std::io::stdout().flush();
-                }
-            }));
-        }
-    }
-
     fn handle_command(&mut self, command: Command) -> io::Result<()> {
         match command {
             Command::Help => self.show_help(),
@@ -155,6 +282,22 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
                 println!("✅ Porta atualizada para: {}", self.config.port);
             }
             Command::ListPeers => self.list_peers(),
+            Command::PeerInfo(addr) => self.show_peer(addr),
+            Command::Ban(subnet, duration, reason) => self.ban(subnet, duration, &reason)?,
+            Command::Unban(subnet) => self.unban(&subnet)?,
+            Command::ListBanned => self.list_banned()?,
+            Command::ClearBanned => self.clear_banned()?,
+            Command::ImportCore(path) => self.import_core(&path),
+            Command::Dump(path) => self.write_dump(path),
+            Command::Prune => self.prune(),
+            Command::LogLevel(spec) => self.set_log_level(spec),
+            Command::Logs(query) => self.show_logs(&query),
+            Command::LogsFollow(query) => self.follow_logs(&query),
+            Command::Report(json) => self.report(json)?,
+            Command::History(hours) => self.show_history(hours),
+            Command::PeerHistory(addr) => self.show_peer_history(addr)?,
+            Command::Export(path, filter) => self.export(&path, &filter),
+            Command::Import(path, filter) => self.import(&path, &filter),
             Command::Crawl => self.run_crawler_command()?, 
             Command::Dns => self.start_dns_server()?, // Novo comando
             Command::Clear => {
@@ -167,6 +310,7 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
                     self.stop_client()?;
                 }
                 println!("👋 Saindo...");
+                self.services.shutdown("exit");
                 self.running = false;
             }
             Command::Unknown => println!("❌ Comando desconhecido. Digite 'help' para ajuda."),
@@ -184,6 +328,24 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
         println!("   sethost <host>    - Define o host para conexão");
         println!("   setport <port>    - Define a porta para conexão");
         println!("   peers             - Lista os peers conhecidos");
+        println!("   peer <ip:porta>   - Mostra tudo que sabemos sobre um peer");
+        println!("   ban <ip|rede/n> [tempo|permanent] [motivo]");
+        println!("                     - Bane um IP ou sub-rede (tempo: 3600, 30m, 12h, 7d; padrão 24h)");
+        println!("   unban <ip|rede/n> - Remove um banimento");
+        println!("   listbanned        - Lista os banimentos ativos");
+        println!("   clearbanned       - Remove todos os banimentos");
+        println!("   importcore <arq>  - Importa peers.dat ou anchors.dat do Bitcoin Core");
+        println!("   report [json]     - Censo: redes, user agents, versões, serviços e portas");
+        println!("   history [horas]   - Gráfico de nós alcançáveis por hora (padrão 24h)");
+        println!("   history <ip:porta> - Histórico de tentativas de um peer");
+        println!("   prune             - Remove peers antigos ou que nunca responderam");
+        println!("   loglevel [filtro] - Mostra ou muda os níveis de log (ex.: info,dns=trace,crawler=warn)");
+        println!("   logs [nível] [componente] [n] - Mostra as últimas linhas de log (padrão 50)");
+        println!("   logs follow [nível] [componente] - Acompanha novas linhas até apertar Enter");
+        println!("   dump [arquivo]    - Escreve o dnsseed.dump (padrão: --dump-file ou dnsseed.dump)");
+        println!("   export <arq> [filtros] - Exporta peers (.csv ou JSON lines)");
+        println!("   import <arq> [filtros] - Mescla peers de um .csv ou JSON lines");
+        println!("                     - filtros: status=<s> net=ipv4|ipv6 services=<bits> age=<tempo>");
         println!("   crawl             - Faz crawl paralelo dos peers conhecidos");
         println!("   dns               - Inicia o servidor DNS (porta 1053)");
         println!("   clear             - Limpa a tela"); 
@@ -199,39 +361,40 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
             println!("⚠️  Cliente já está rodando!");
             return Ok(());
         }
-        log(&self.log_tx, LogLevel::Info, Event::Custom("Iniciando cliente Bitcoin P2P".into()));
-
-        let mut client = BitcoinClient::new_with_logger(self.log_tx.clone());
+        log(&self.services.log_tx, LogLevel::Info, Event::ClientStarting);
+
+        let mut client = BitcoinClient::new_with_logger(
+            self.services.log_tx.clone(),
+            self.services.peer_store.clone(),
+            self.services.ban_list.clone(),
+            self.services.client.clone(),
+        );
         match client.connect() {
             Ok(_) => {
                 match client.start_handshake() {
                     Ok(_) => {
                         println!("✅ Conexão estabelecida com sucesso!");
-                        self.client_running.store(true, Ordering::SeqCst);
-                        let running = self.client_running.clone();
+                        self.services.client_running.store(true, Ordering::SeqCst);
+                        let running = self.services.client_running.clone();
 
-                        let (tx, rx) = mpsc::channel::<String>();
                         let mut client_clone = client.clone();
 
                         let client_thread = thread::spawn(move || {
-                        let _ = client_clone.message_loop_with_channel(&tx, &running);
+                        let _ = client_clone.message_loop(&running);
                         });
                         self.client_thread = Some(client_thread);
                         self.client = Some(client);
-                        self.client_rx = Some(rx);
-
-                        self.start_bg_printer();
 
                         println!("✅ Cliente iniciado em background");
                     }
                     Err(e) => {
-                        log(&self.log_tx, LogLevel::Error, Event::Custom(format!("Erro no handshake: {}", e)));
+                        log(&self.services.log_tx, LogLevel::Error, Event::ClientStartFailed("handshake".into(), e.to_string()));
                         println!("❌ Erro no handshake: {}", e);
                     }
                 }
             }
             Err(e) => {
-                log(&self.log_tx, LogLevel::Error, Event::Custom(format!("Erro ao conectar: {}", e)));
+                log(&self.services.log_tx, LogLevel::Error, Event::ClientStartFailed("connect".into(), e.to_string()));
                 println!("❌ Erro ao conectar: {}", e);
             }
         }
@@ -240,7 +403,7 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
 
     fn stop_client(&mut self) -> io::Result<()> {
     if let Some(mut client) = self.client.take() {
-        self.client_running.store(false, Ordering::SeqCst);
+        self.services.client_running.store(false, Ordering::SeqCst);
 
             if let Some(thread) = self.client_thread.take() {
             if let Err(e) = thread.join() {
@@ -250,18 +413,10 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
             if let Err(e) = client.soft_stop() {
             println!("⚠️ Erro ao parar cliente: {}", e);
         }
-        self.client_rx = None;
 
             client.soft_stop()?;
-            self.client_rx = None;
 
-            if let Some(bg) = self.bg_printer.take() {
-            if let Err(e) = bg.join() {
-                println!("⚠️ Erro ao aguardar thread de impressão: {:?}", e);
-            }
-        }
-
-        log(&self.log_tx, LogLevel::Info, Event::Custom("Cliente Bitcoin P2P parado.".into()));
+        log(&self.services.log_tx, LogLevel::Info, Event::ClientStopped("parado pelo usuário".into()));
         println!("🛑 Cliente Bitcoin P2P parado.");
     } else {
         println!("⚠️  Cliente não está rodando.");
@@ -270,15 +425,19 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
     }
 
     fn show_status(&self) {
-    if self.client.is_some() && self.client_running.load(Ordering::SeqCst) {
+    if self.client.is_some() && self.services.client_running.load(Ordering::SeqCst) {
         println!("✅ Cliente está rodando");
         println!("   Host: {}", self.config.host);
         println!("   Porta: {}", self.config.port);
     } else {
         println!("❌ Cliente não está rodando");
     }
+    println!("💾 Peers no banco de dados: {}", self.services.peer_store.lock().unwrap().len());
+    if let Some(n) = self.services.history.lock().unwrap().reachable_at(now_secs()) {
+        println!("📈 Nós alcançáveis nesta hora: {}", n);
+    }
     // Mostrar peers conectados pelo crawl
-    let crawl_peers = self.crawl_connected.lock().unwrap();
+    let crawl_peers = self.services.crawl.connected.lock().unwrap();
     if !crawl_peers.is_empty() {
         println!("🌐 Peers conectados pelo crawl:");
         for addr in crawl_peers.iter() {
@@ -290,94 +449,279 @@ pub fn new_with_logger(config: Cli, log_tx: Sender<LogMessage>) -> Self {
     }
 
     fn list_peers(&self) {
-        match &self.client {
-            Some(client) => {
-                let peers = &client.peer_db.peers;
-                println!("📡 Peers conhecidos: {}", peers.len());
-                for (addr, info) in peers {
-                    println!("   {} (último contato: {:?})",
-                        addr,
-                        info.last_seen.map(|ts|
-                            chrono::DateTime::from_timestamp(ts as i64, 0)
-                        )
-                    );
-                }
-            }
-            None => println!("❌ Cliente não está rodando"),
+        let peers = self.services.peer_store.lock().unwrap().all_peers();
+        println!("📡 Peers conhecidos: {}", peers.len());
+        for info in &peers {
+            println!("   {} (último contato: {:?})",
+                info.address,
+                info.last_seen.map(|ts|
+                    chrono::DateTime::from_timestamp(ts as i64, 0)
+                )
+            );
         }
     }
     
-    fn run_crawler_command(&mut self) -> io::Result<()> {
-    let mut peers: Vec<SocketAddr> = self.client
-        .as_ref()
-        .map(|c| c.peer_db.peers.keys().cloned().collect())
-        .unwrap_or_default();
+    fn show_peer(&self, addr: SocketAddr) {
+        let Some(info) = self.services.peer_store.lock().unwrap().get_peer(&addr) else {
+            println!("❌ Peer {} não está no banco de dados", addr);
+            return;
+        };
+        let ts = |t: Option<u64>| {
+            t.and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0))
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "-".into())
+        };
+        println!("📡 Peer {}", info.address);
+        println!("   Status:           {:?}", info.status);
+        println!("   Primeira vez:     {}", ts(info.first_seen));
+        println!("   Último contato:   {}", ts(info.last_seen));
+        println!("   Última conexão:   {}", ts(info.last_connected));
+        println!("   Fonte:            {}", info.source.map(|s| s.to_string()).unwrap_or_else(|| "-".into()));
+        println!("   Serviços:         {}", info.services.map(|s| format!("{:#x}", s)).unwrap_or_else(|| "-".into()));
+        println!("   Versão:           {}", info.protocol_version.map(|v| v.to_string()).unwrap_or_else(|| "-".into()));
+        println!("   User agent:       {}", info.user_agent.as_deref().unwrap_or("-"));
+        println!("   Altura inicial:   {}", info.start_height.map(|h| h.to_string()).unwrap_or_else(|| "-".into()));
+        println!("   Sistema autônomo: {}", info.asn.map(|a| format!("AS{}", a)).unwrap_or_else(|| "-".into()));
+        println!("   Ping:             {}", info.ping_ms.map(|p| format!("{} ms", p)).unwrap_or_else(|| "-".into()));
+        println!("   Má conduta:       {}", info.misbehavior_score);
+        let w = info.uptime.windows();
+        println!("   Uptime:           2h {:.1}% | 8h {:.1}% | 1d {:.1}% | 7d {:.1}% | 30d {:.1}%",
+            100.0 * w[0].reliability, 100.0 * w[1].reliability, 100.0 * w[2].reliability,
+            100.0 * w[3].reliability, 100.0 * w[4].reliability);
+        match &info.last_failure {
+            Some(f) => println!("   Última falha:     {:?} em {}: {}", f.stage, ts(Some(f.at)), f.reason),
+            None => println!("   Última falha:     -"),
+        }
+    }
 
-    if peers.is_empty() {
-        println!("Nenhum peer conhecido para crawl.");
-        return Ok(());
+    fn ban(&mut self, subnet: Subnet, duration: Option<Duration>, reason: &str) -> io::Result<()> {
+        let affected = ban_subnet(&self.services.ban_list, &self.services.peer_store, subnet, duration, reason)?;
+        let until = match duration {
+            Some(d) => format!("por {}s", d.as_secs()),
+            None => "permanentemente".to_string(),
+        };
+        log(&self.services.log_tx, LogLevel::Info, Event::Banned(subnet.to_string(), duration.map(|d| d.as_secs()), reason.to_string()));
+        println!("🚫 {} banido {} ({} peers conhecidos afetados)", subnet, until, affected);
+        Ok(())
     }
 
-    if peers.len() > 4 {
-        peers.truncate(4);
+    fn unban(&mut self, subnet: &Subnet) -> io::Result<()> {
+        if unban_subnet(&self.services.ban_list, &self.services.peer_store, subnet)? {
+            log(&self.services.log_tx, LogLevel::Info, Event::Unbanned(subnet.to_string()));
+            println!("✅ Banimento de {} removido", subnet);
+        } else {
+            println!("⚠️  {} não está banido", subnet);
+        }
+        Ok(())
     }
 
-    println!("Iniciando crawl em {} peers (em background)...", peers.len());
+    fn list_banned(&mut self) -> io::Result<()> {
+        let bans = self.services.ban_list.lock().unwrap().list()?;
+        if bans.is_empty() {
+            println!("✅ Nenhum banimento ativo");
+            return Ok(());
+        }
+        println!("🚫 Banimentos ativos: {}", bans.len());
+        for ban in bans {
+            let until = ban
+                .until
+                .and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0))
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "permanente".into());
+            println!("   {:<24} até {:<26} {}", ban.subnet.to_string(), until, ban.reason);
+        }
+        Ok(())
+    }
 
-    let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
-    let db = self.client.as_ref().map(|c| c.peer_db.clone()).unwrap_or_default();
-    let db_path = "peers.json";
-    let log_tx = self.log_tx.clone();
+    fn clear_banned(&mut self) -> io::Result<()> {
+        let n = clear_banned(&self.services.ban_list, &self.services.peer_store)?;
+        println!("✅ {} banimentos removidos", n);
+        Ok(())
+    }
 
-    thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().expect("Falha ao criar runtime tokio");
-        rt.block_on(async move {
-            multhread_db(db, db_rx, db_path).await;
+    fn import_core(&mut self, path: &Path) {
+        match import_into(path, &self.services.peer_store, &self.services.ban_list) {
+            Ok(s) => {
+                log(&self.services.log_tx, LogLevel::Info, Event::CoreImported(
+                    path.display().to_string(), s.imported, s.skipped + s.banned,
+                ));
+                if s.anchors > 0 {
+                    println!("📥 {} âncoras lidas", s.anchors);
+                } else {
+                    println!("📥 {} endereços na tabela new, {} na tried", s.new, s.tried);
+                }
+                println!("   {} importados, {} de outras redes (Tor/I2P/CJDNS), {} banidos",
+                    s.imported, s.skipped, s.banned);
+            }
+            Err(e) => println!("❌ Falha ao importar {}: {}", path.display(), e),
+        }
+    }
+
+    fn write_dump(&mut self, path: Option<PathBuf>) {
+        let path = path
+            .or_else(|| self.config.dump_file.clone())
+            .unwrap_or_else(|| PathBuf::from("dnsseed.dump"));
+        match write_dump(&self.services.peer_store, &path) {
+            Ok(count) => {
+                log(&self.services.log_tx, LogLevel::Info, Event::DumpWritten(path.display().to_string(), count));
+                println!("💾 {} peers escritos em {}", count, path.display());
+            }
+            Err(e) => println!("❌ Falha ao escrever {}: {}", path.display(), e),
+        }
+    }
+
+    fn set_log_level(&self, spec: Option<String>) {
+        match self.services.set_log_filter(spec.as_deref()) {
+            Ok(filter) => println!("📝 Níveis de log: {}", filter),
+            Err(e) => println!("❌ {}", e),
+        }
+    }
+
+    fn show_logs(&self, query: &LogQuery) {
+        let records = self.services.log_buffer.lock().unwrap().query(query);
+        if records.is_empty() {
+            println!("📭 Nenhuma linha de log no buffer");
+        }
+        for record in records {
+            println!("{}", record);
+        }
+    }
+
+    fn follow_logs(&self, query: &LogQuery) {
+        println!("👀 Acompanhando logs (Enter para parar)");
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = stop.clone();
+        // stdin é lido em linha, então "uma tecla" aqui é o Enter
+        thread::spawn(move || {
+            let _ = io::stdin().read_line(&mut String::new());
+            stop_reader.store(true, Ordering::SeqCst);
         });
-    });
 
-    let db_tx_crawlers = db_tx.clone();
-    let crawl_connected = self.crawl_connected.clone();
-    thread::spawn(move || {
-        match tokio::runtime::Runtime::new() {
-            Ok(rt) => {
-                if let Err(e) = std::panic::catch_unwind(|| {
-                    rt.block_on(run_crawlers_with_log(peers, db_tx_crawlers, log_tx, crawl_connected));
-                }) {
-                    println!("❌ Erro ao executar crawler: {:?}", e);
+        // Com o log no terminal, as linhas liberadas pelo --verbosity já aparecem sozinhas
+        let echoed = self.config.log_to_stdout();
+        let mut next = self.services.log_buffer.lock().unwrap().next_seq();
+        while !stop.load(Ordering::SeqCst) {
+            {
+                let buffer = self.services.log_buffer.lock().unwrap();
+                let filter = self.services.log_filter.lock().unwrap();
+                for record in buffer.since(next, query) {
+                    if !(echoed && filter.enabled(record.component, record.level)) {
+                        println!("{}", record);
+                    }
                 }
+                next = buffer.next_seq();
             }
-            Err(e) => {
-                println!("❌ Erro ao criar runtime tokio: {}", e);
-            }
+            thread::sleep(Duration::from_millis(200));
         }
-        println!("Crawl finalizado.❗❗❗");
-    });
+    }
 
-    Ok(())
-}
+    fn report(&self, json: bool) -> io::Result<()> {
+        let census = build_census(&self.services.peer_store.lock().unwrap().all_peers());
+        if json {
+            println!("{}", serde_json::to_string_pretty(&census)?);
+        } else {
+            println!("📊 Censo da rede");
+            print!("{}", render_table(&census, 20));
+        }
+        Ok(())
+    }
+
+    fn show_history(&self, hours: u64) {
+        let now = now_secs();
+        let rollups = self.services.history.lock().unwrap().rollups(hour_of(now).saturating_sub(hours.saturating_sub(1) * 3600), now);
+        if rollups.is_empty() {
+            println!("📈 Nenhuma tentativa registrada nas últimas {} horas", hours);
+            return;
+        }
+        println!("📈 Nós alcançáveis por hora (últimas {} horas):", hours);
+        print!("{}", plot_reachable(&rollups, 40));
+    }
+
+    fn show_peer_history(&self, addr: SocketAddr) -> io::Result<()> {
+        let history = self.services.history.lock().unwrap();
+        let attempts = history.peer_history(&addr)?;
+        if attempts.is_empty() {
+            println!("❌ Nenhuma tentativa registrada para {}", addr);
+            return Ok(());
+        }
+        let ts = |t: u64| chrono::DateTime::from_timestamp(t as i64, 0).map(|d| d.to_string()).unwrap_or_default();
+        println!("📜 {} tentativas para {} (mostrando as últimas 20)", attempts.len(), addr);
+        for obs in attempts.iter().rev().take(20).rev() {
+            let detail = match (obs.latency_ms, obs.version, obs.height) {
+                (None, None, None) => String::new(),
+                (l, v, h) => format!(
+                    " ping {} versão {} altura {}",
+                    l.map_or("-".into(), |l| format!("{} ms", l)),
+                    v.map_or("-".into(), |v| v.to_string()),
+                    h.map_or("-".into(), |h| h.to_string()),
+                ),
+            };
+            println!("   {} {}{}", ts(obs.time), if obs.success { "✅" } else { "❌" }, detail);
+        }
+        match history.went_offline(&addr)? {
+            Some(t) => println!("   Offline desde a última resposta em {}", ts(t)),
+            None if attempts.last().is_some_and(|o| o.success) => println!("   Online na última tentativa"),
+            None => println!("   Nunca respondeu"),
+        }
+        Ok(())
+    }
+
+    fn prune(&mut self) {
+        match prune_and_log(&self.services.peer_store, &self.config.retention_policy(), &self.services.log_tx) {
+            Ok(s) => println!(
+                "🧹 {} removidos ({} sem sucesso, {} antigos, {} acima do limite por rede), {} restantes",
+                s.total(), s.failed, s.stale, s.over_netgroup, s.remaining
+            ),
+            Err(e) => println!("❌ Falha ao limpar banco de dados: {}", e),
+        }
+    }
+
+    fn export(&mut self, path: &Path, filter: &PeerFilter) {
+        match export_to_file(&self.services.peer_store, Some(path), ExportFormat::from_path(path), filter) {
+            Ok(count) => println!("📤 {} peers exportados para {}", count, path.display()),
+            Err(e) => println!("❌ Falha ao exportar {}: {}", path.display(), e),
+        }
+    }
+
+    fn import(&mut self, path: &Path, filter: &PeerFilter) {
+        match import_from_file(&self.services.peer_store, &self.services.ban_list, path, ExportFormat::from_path(path), filter) {
+            Ok(s) => println!("📥 {} peers novos, {} atualizados, {} ignorados", s.added, s.updated, s.skipped),
+            Err(e) => println!("❌ Falha ao importar {}: {}", path.display(), e),
+        }
+    }
+
+    fn run_crawler_command(&mut self) -> io::Result<()> {
+        match self.services.start_crawl(DEFAULT_CRAWL_PEERS) {
+            Ok(n) => println!("Iniciando crawl em {} peers (em background)...", n),
+            Err(e) => println!("⚠️  {}", e),
+        }
+        Ok(())
+    }
 
     fn start_dns_server(&mut self) -> io::Result<()> {
         if self.dns_thread.is_some() {
             println!("⚠️  Servidor DNS já está rodando!");
             return Ok(());
         }
-        // Compartilhe o PeerDatabase do cliente
-        let peer_db = match &self.client {
-            Some(client) => Arc::new(RwLock::new(client.peer_db.clone())),
-            None => {
-                println!("❌ Cliente não está rodando para compartilhar peers.");
-                return Ok(());
-            }
-        };
-        self.peer_db_shared = Some(peer_db.clone());
-        let log_tx = self.log_tx.clone();
+        // O servidor DNS lê direto do mesmo PeerStore usado pelo cliente e pelo crawler
+        let peer_db = self.services.peer_store.clone();
+        let bans = self.services.ban_list.clone();
+        let log_tx = self.services.log_tx.clone();
+        let metrics = self.services.metrics.clone();
+        let stopping = self.services.stopping.clone();
         let domain = "seed.example.com.".to_string(); // Troque pelo domínio desejado
 
         self.dns_thread = Some(std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().expect("Falha ao criar runtime tokio");
             rt.block_on(async move {
-                let _ = run_dns_server(peer_db, &domain, log_tx).await;
+                tokio::select! {
+                    _ = run_dns_server(peer_db, bans, &domain, log_tx, metrics) => {}
+                    _ = async {
+                        while !stopping.load(Ordering::SeqCst) {
+                            tokio::time::sleep(STOP_POLL).await;
+                        }
+                    } => {}
+                }
             });
         }));
 
@@ -4,17 +4,159 @@ mod tests;
 mod interactive;
 
 use interactive::InteractiveCli;
-use p2p::log::{Logger, LogLevel};
+use p2p::log::{log, log_as, Component, Event, Logger, LogBuffer, LogLevel, RotatingFile};
+use p2p::core_import::import_into;
+use p2p::dnsseed_dump::spawn_dump_writer;
+use p2p::retention::prune_and_log;
+use p2p::peer_export::{export_to_file, import_from_file, ExportFormat};
+use std::time::Duration;
+use p2p::peer_store::{open_store, shared, PeerStore};
+use p2p::banlist::BanList;
+use p2p::addrman::{AddrMan, AddrManStore};
+use p2p::asmap::Asmap;
+use p2p::history::HistoryLog;
+use p2p::census::{build_census, render_table};
+use p2p::metrics::serve_metrics;
+use p2p::rpc::{serve_rpc, RpcContext};
+use p2p::services::Services;
+use std::sync::{Arc, Mutex};
 
 fn main() -> std::io::Result<()> {
-    let config = cli::Cli::parse();
+    let mut config = cli::Cli::parse();
 
-    let min_level = LogLevel::from_str(&config.verbosity).unwrap_or(LogLevel::Trace);
+    let log_filter = Arc::new(Mutex::new(config.verbosity.clone()));
+    let log_buffer = Arc::new(Mutex::new(LogBuffer::new(config.log_buffer).with_min_level(config.log_buffer_level)));
 
     let (log_tx, log_rx) = std::sync::mpsc::channel();
 
-    let _logger_handle = Logger::spawn(min_level, log_rx);
+    let log_file = config.log_file_config().map(RotatingFile::open).transpose()?;
+    let _logger_handle = Logger::spawn(log_filter.clone(), log_rx, config.log_format, config.log_to_stdout(), log_file, log_buffer.clone());
 
-    let mut cli = InteractiveCli::new_with_logger(config, log_tx);
-    cli.run()
-}
\ No newline at end of file
+    let key = match config.addrman_key_path() {
+        Some(path) => AddrMan::load_or_create_key(&path)?,
+        None => rand::random(),
+    };
+    let asmap = config.asmap.as_deref().map(Asmap::load).transpose()?;
+    let (mut store, unindexed) = AddrManStore::new(open_store(config.db, &config.db_path(), &log_tx)?, key, asmap);
+    store.set_netgroup_limit(config.netgroup_limit);
+    log(&log_tx, LogLevel::Info, Event::AddrManLoaded(store.addrman().len_new(), store.addrman().len_tried(), unindexed));
+    if let Some(path) = &config.asmap {
+        let asns: Vec<u32> = store.all_peers().iter().filter_map(|p| p.asn).collect();
+        let distinct = asns.iter().collect::<std::collections::HashSet<_>>().len();
+        log(&log_tx, LogLevel::Info, Event::AsmapLoaded(path.display().to_string(), asns.len(), distinct));
+    }
+    let peer_store = shared(Box::new(store));
+
+    let ban_list = match config.banlist_path() {
+        Some(path) => BanList::load(&path)?,
+        None => BanList::default(),
+    };
+    let ban_list = Arc::new(Mutex::new(ban_list));
+
+    let history = match config.history_path() {
+        Some(path) => HistoryLog::open(&path)?,
+        None => HistoryLog::default(),
+    };
+    let history = Arc::new(Mutex::new(history));
+
+    // Subcomandos avulsos veem o banco como está no disco, antes de importar ou limpar
+    if let Some(command) = config.command.take() {
+        return run_command(command, &peer_store, &ban_list);
+    }
+
+    for path in &config.import_core {
+        match import_into(path, &peer_store, &ban_list) {
+            Ok(s) => log(&log_tx, LogLevel::Info, Event::CoreImported(
+                path.display().to_string(), s.imported, s.skipped + s.banned + s.refused,
+            )),
+            Err(e) => log_as(&log_tx, Component::Db, LogLevel::Error, Event::CoreImportFailed(
+                path.display().to_string(), e.to_string(),
+            )),
+        }
+    }
+
+    if let Err(e) = prune_and_log(&peer_store, &config.retention_policy(), &log_tx) {
+        log_as(&log_tx, Component::Db, LogLevel::Error, Event::PruneFailed(e.to_string()));
+    }
+
+    let services = Services::new(
+        peer_store,
+        ban_list,
+        history,
+        log_tx.clone(),
+        log_filter,
+        log_buffer,
+        config.flush_policy(),
+        config.retention_policy(),
+    );
+    if let Some(addr) = config.metrics_listen {
+        serve_metrics(addr, services.metrics.clone(), services.peer_store.clone())?;
+        log(&log_tx, LogLevel::Info, Event::MetricsListening(addr));
+    }
+    let rpc_auth = match config.rpc_listen {
+        Some(addr) => {
+            let mut auth = config.rpc_auth();
+            if let Some(path) = config.rpc_cookie_path() {
+                auth.write_cookie(&path)?;
+            }
+            serve_rpc(addr, RpcContext::new(services.clone(), auth.clone()))?;
+            log(&log_tx, LogLevel::Info, Event::RpcListening(addr));
+            Some(auth)
+        }
+        None => None,
+    };
+
+    let dump_writer = config.dump_file.as_ref().map(|path| {
+        spawn_dump_writer(
+            services.peer_store.clone(),
+            path.clone(),
+            Duration::from_secs(config.dump_interval.max(1)),
+            log_tx.clone(),
+            services.stopping.clone(),
+        )
+    });
+
+    let stopping = services.stopping.clone();
+    let mut cli = InteractiveCli::new_with_logger(config, services);
+    let result = cli.run();
+    if let Some(auth) = rpc_auth {
+        auth.remove_cookie();
+    }
+    // Sem `shutdown` (erro no prompt) o writer nunca pararia sozinho
+    stopping.store(true, std::sync::atomic::Ordering::SeqCst);
+    if let Some(handle) = dump_writer {
+        let _ = handle.join();
+    }
+    // Dá tempo do logger escrever as últimas linhas; os servidores HTTP ainda seguram o canal
+    std::thread::sleep(Duration::from_millis(200));
+    result
+}
+/// Runs an `export`/`import`/`report` subcommand. Summaries go to stderr so an
+/// export to stdout stays clean.
+fn run_command(
+    command: cli::Commands,
+    peer_store: &p2p::peer_store::SharedPeerStore,
+    ban_list: &p2p::banlist::SharedBanList,
+) -> std::io::Result<()> {
+    match command {
+        cli::Commands::Export { output, format, filter } => {
+            let format = format.unwrap_or_else(|| output.as_deref().map_or(ExportFormat::Jsonl, ExportFormat::from_path));
+            let count = export_to_file(peer_store, output.as_deref(), format, &filter.into())?;
+            eprintln!("{} peers exportados", count);
+        }
+        cli::Commands::Import { input, format, filter } => {
+            let format = format.unwrap_or_else(|| ExportFormat::from_path(&input));
+            let s = import_from_file(peer_store, ban_list, &input, format, &filter.into())?;
+            eprintln!("{} peers novos, {} atualizados, {} ignorados", s.added, s.updated, s.skipped);
+        }
+        cli::Commands::Report { json, limit } => {
+            let census = build_census(&peer_store.lock().unwrap().all_peers());
+            if json {
+                println!("{}", serde_json::to_string_pretty(&census)?);
+            } else {
+                print!("{}", render_table(&census, limit));
+            }
+        }
+    }
+    Ok(())
+}
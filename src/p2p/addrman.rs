@@ -0,0 +1,492 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use crate::p2p::asmap::{diversity_group, Asmap};
+use crate::p2p::atomic_file::write_atomic;
+use crate::p2p::banlist::Subnet;
+use crate::p2p::database::{now_secs, PeerInfo, PeerStatus};
+use crate::p2p::peer_store::PeerStore;
+use crate::p2p::retention::netgroup;
+use crate::p2p::utils::sha256d;
+
+// Mesmas constantes do addrman.h do Bitcoin Core
+pub const NEW_BUCKET_COUNT: usize = 1024;
+pub const TRIED_BUCKET_COUNT: usize = 256;
+pub const BUCKET_SIZE: usize = 64;
+/// Buckets a single source netgroup can spread its gossip over.
+pub const NEW_BUCKETS_PER_SOURCE_GROUP: u64 = 64;
+/// Tried buckets a single address netgroup can occupy.
+pub const TRIED_BUCKETS_PER_GROUP: u64 = 8;
+
+/// Pending moves to tried whose slot is taken, as Core's `ADDRMAN_SET_TRIED_COLLISION_SIZE`.
+pub const MAX_TRIED_COLLISIONS: usize = 10;
+/// An occupant that connected this recently keeps its tried slot.
+const RECENT_SUCCESS_SECS: u64 = 4 * 3600;
+/// An occupant still untested this long after a collision is assumed gone.
+const TEST_WINDOW_SECS: u64 = 40 * 60;
+
+const HORIZON_DAYS: u64 = 30;
+const MAX_RETRIES: u32 = 3;
+const MAX_FAILURES: u32 = 10;
+const MIN_FAIL_DAYS: u64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Table {
+    New,
+    Tried,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    table: Table,
+    index: usize,
+    source: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddOutcome {
+    Added,
+    /// Already in a table; nothing moved.
+    Known,
+    /// The slot was taken by a better entry.
+    Rejected,
+    /// The address took the slot of a terrible entry, which was dropped.
+    Replaced(SocketAddr),
+}
+
+/// Core's `IsTerrible`: entries that are safe to overwrite on a collision.
+pub fn is_terrible(peer: &PeerInfo, now: u64) -> bool {
+    // Acabou de ser tentado: dá uma chance
+    if peer.uptime.last_try.is_some_and(|t| now.saturating_sub(t) < 60) {
+        return false;
+    }
+    let Some(seen) = peer.last_seen else { return true };
+    if seen > now + 10 * 60 || now.saturating_sub(seen) > HORIZON_DAYS * 86400 {
+        return true;
+    }
+    match peer.last_connected {
+        None => peer.attempts >= MAX_RETRIES,
+        Some(ok) => now.saturating_sub(ok) > MIN_FAIL_DAYS * 86400 && peer.attempts >= MAX_FAILURES,
+    }
+}
+
+/// Where addresses may live, Bitcoin Core style.
+///
+/// Gossiped addresses go to the "new" table in a bucket picked by a keyed hash
+/// of the source's and the address' netgroups, so one source (or one /16 full
+/// of sources) can only ever fill `NEW_BUCKETS_PER_SOURCE_GROUP` buckets.
+/// Addresses we connected to move to "tried", where a netgroup is limited to
+/// `TRIED_BUCKETS_PER_GROUP` buckets. Unlike Core, an address sits in a single
+/// new bucket rather than up to eight.
+///
+/// With an asmap loaded the group is the autonomous system instead, so a
+/// flood of addresses (or sources) from one AS is held to the same limits.
+pub struct AddrMan {
+    key: [u8; 32],
+    asmap: Option<Asmap>,
+    new: Vec<Option<SocketAddr>>,
+    tried: Vec<Option<SocketAddr>>,
+    slots: HashMap<SocketAddr, Slot>,
+    /// New entries that proved good but found their tried slot taken, with when.
+    collisions: Vec<(SocketAddr, u64)>,
+}
+
+impl AddrMan {
+    pub fn new(key: [u8; 32]) -> Self {
+        AddrMan {
+            key,
+            asmap: None,
+            new: vec![None; NEW_BUCKET_COUNT * BUCKET_SIZE],
+            tried: vec![None; TRIED_BUCKET_COUNT * BUCKET_SIZE],
+            slots: HashMap::new(),
+            collisions: Vec::new(),
+        }
+    }
+
+    pub fn with_asmap(mut self, asmap: Option<Asmap>) -> Self {
+        self.asmap = asmap;
+        self
+    }
+
+    /// AS of `ip` according to the loaded asmap, if any.
+    pub fn asn(&self, ip: IpAddr) -> Option<u32> {
+        self.asmap.as_ref().and_then(|m| m.lookup(ip))
+    }
+
+    /// Reads the secret bucket key from `path`, creating it on first use.
+    /// Without a stable key bucket placement would change on every restart.
+    pub fn load_or_create_key(path: &Path) -> io::Result<[u8; 32]> {
+        match fs::read_to_string(path) {
+            Ok(hex_key) => hex::decode(hex_key.trim())
+                .ok()
+                .and_then(|k| k.try_into().ok())
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "addrman key must be 32 hex-encoded bytes")),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let key: [u8; 32] = rand::random();
+                write_atomic(path, hex::encode(key).as_bytes())?;
+                Ok(key)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn hash(&self, parts: &[&[u8]]) -> u64 {
+        let mut data = self.key.to_vec();
+        for part in parts {
+            data.extend_from_slice(&(part.len() as u32).to_le_bytes());
+            data.extend_from_slice(part);
+        }
+        u64::from_le_bytes(sha256d(&data)[..8].try_into().unwrap())
+    }
+
+    fn group(&self, addr: &SocketAddr) -> String {
+        diversity_group(self.asn(addr.ip()), addr.ip())
+    }
+
+    pub fn new_bucket(&self, addr: &SocketAddr, source: &SocketAddr) -> usize {
+        let (group, src_group) = (self.group(addr), self.group(source));
+        let h1 = self.hash(&[group.as_bytes(), src_group.as_bytes()]) % NEW_BUCKETS_PER_SOURCE_GROUP;
+        (self.hash(&[src_group.as_bytes(), &h1.to_le_bytes()]) % NEW_BUCKET_COUNT as u64) as usize
+    }
+
+    pub fn tried_bucket(&self, addr: &SocketAddr) -> usize {
+        let key = addr.to_string();
+        let h1 = self.hash(&[key.as_bytes()]) % TRIED_BUCKETS_PER_GROUP;
+        (self.hash(&[self.group(addr).as_bytes(), &h1.to_le_bytes()]) % TRIED_BUCKET_COUNT as u64) as usize
+    }
+
+    fn position(&self, table: Table, bucket: usize, addr: &SocketAddr) -> usize {
+        let tag: &[u8] = if table == Table::New { b"N" } else { b"K" };
+        let key = addr.to_string();
+        let pos = self.hash(&[tag, &(bucket as u64).to_le_bytes(), key.as_bytes()]) % BUCKET_SIZE as u64;
+        bucket * BUCKET_SIZE + pos as usize
+    }
+
+    fn tried_index(&self, addr: &SocketAddr) -> usize {
+        self.position(Table::Tried, self.tried_bucket(addr), addr)
+    }
+
+    fn table_mut(&mut self, table: Table) -> &mut Vec<Option<SocketAddr>> {
+        match table {
+            Table::New => &mut self.new,
+            Table::Tried => &mut self.tried,
+        }
+    }
+
+    pub fn contains(&self, addr: &SocketAddr) -> bool {
+        self.slots.contains_key(addr)
+    }
+
+    #[cfg(test)]
+    pub fn is_tried(&self, addr: &SocketAddr) -> bool {
+        self.slots.get(addr).is_some_and(|s| s.table == Table::Tried)
+    }
+
+    pub fn len_new(&self) -> usize {
+        self.slots.values().filter(|s| s.table == Table::New).count()
+    }
+
+    pub fn len_tried(&self) -> usize {
+        self.slots.len() - self.len_new()
+    }
+
+    /// Bucket of each address in the new table.
+    #[cfg(test)]
+    pub fn new_buckets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots.values().filter(|s| s.table == Table::New).map(|s| s.index / BUCKET_SIZE)
+    }
+
+    #[cfg(test)]
+    pub fn tried_buckets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots.values().filter(|s| s.table == Table::Tried).map(|s| s.index / BUCKET_SIZE)
+    }
+
+    /// Puts a gossiped address in the new table. On a collision the current
+    /// occupant stays unless `terrible` says it is worthless.
+    pub fn add(&mut self, addr: SocketAddr, source: SocketAddr, terrible: &dyn Fn(&SocketAddr) -> bool) -> AddOutcome {
+        if self.contains(&addr) {
+            return AddOutcome::Known;
+        }
+        let index = self.position(Table::New, self.new_bucket(&addr, &source), &addr);
+        let outcome = match self.new[index] {
+            None => AddOutcome::Added,
+            Some(old) if terrible(&old) => {
+                self.slots.remove(&old);
+                AddOutcome::Replaced(old)
+            }
+            Some(_) => return AddOutcome::Rejected,
+        };
+        self.new[index] = Some(addr);
+        self.slots.insert(addr, Slot { table: Table::New, index, source });
+        outcome
+    }
+
+    pub fn remove(&mut self, addr: &SocketAddr) -> bool {
+        let Some(slot) = self.slots.remove(addr) else { return false };
+        self.table_mut(slot.table)[slot.index] = None;
+        true
+    }
+
+    /// Moves a known address to the tried table after a successful connection
+    /// at `now`; returns whether it moved. If the tried slot is taken the address
+    /// stays in new and waits in `resolve_collisions` (Core's test-before-evict),
+    /// so one good peer can't push out an established tried entry.
+    pub fn mark_good(&mut self, addr: SocketAddr, now: u64) -> bool {
+        let Some(slot) = self.slots.get(&addr).copied() else { return false };
+        if slot.table == Table::Tried {
+            return false;
+        }
+        let index = self.tried_index(&addr);
+        if self.tried[index].is_some() {
+            if self.collisions.len() < MAX_TRIED_COLLISIONS && !self.collisions.iter().any(|(a, _)| *a == addr) {
+                self.collisions.push((addr, now));
+            }
+            return false;
+        }
+        self.move_to_tried(addr, slot, index);
+        true
+    }
+
+    fn move_to_tried(&mut self, addr: SocketAddr, slot: Slot, index: usize) {
+        self.new[slot.index] = None;
+        self.tried[index] = Some(addr);
+        self.slots.insert(addr, Slot { table: Table::Tried, index, ..slot });
+    }
+
+    /// Settles pending collisions. `verdict(occupant, since)` is `Some(true)` if the
+    /// occupant proved good (the waiting entry gives up), `Some(false)` if it failed
+    /// its check (it goes back to new and the waiting entry takes the slot) and
+    /// `None` while undecided. Returns entries dropped for lack of room in new.
+    pub fn resolve_collisions(&mut self, verdict: &dyn Fn(&SocketAddr, u64) -> Option<bool>) -> Vec<SocketAddr> {
+        let mut dropped = Vec::new();
+        for (addr, since) in std::mem::take(&mut self.collisions) {
+            let Some(slot) = self.slots.get(&addr).copied().filter(|s| s.table == Table::New) else { continue };
+            let index = self.tried_index(&addr);
+            let Some(old) = self.tried[index] else {
+                self.move_to_tried(addr, slot, index);
+                continue;
+            };
+            match verdict(&old, since) {
+                None => self.collisions.push((addr, since)),
+                Some(true) => {}
+                Some(false) => {
+                    let old_slot = self.slots.remove(&old).expect("ocupante da tried sem slot");
+                    self.tried[index] = None;
+                    self.move_to_tried(addr, slot, index);
+                    if self.add(old, old_slot.source, &|_| false) != AddOutcome::Added {
+                        dropped.push(old);
+                    }
+                }
+            }
+        }
+        dropped
+    }
+}
+
+/// A `PeerStore` whose gossip goes through an `AddrMan` first: addresses that
+/// find no slot are never stored, and entries evicted from the tables are
+/// removed from the store. Updates to a stored peer always apply, slot or not;
+/// an update that would create a peer passes the same checks as gossip.
+pub struct AddrManStore {
+    inner: Box<dyn PeerStore>,
+    addrman: AddrMan,
+    /// Stored peers per netgroup, kept in step with `inner` for the insert cap.
+    groups: HashMap<Subnet, usize>,
+    /// `RetentionPolicy::max_per_netgroup` applied to gossip as it arrives (0 = off).
+    netgroup_limit: usize,
+}
+
+impl AddrManStore {
+    /// Rebuilds the tables from what `inner` already holds. Peers we connected
+    /// to claim tried slots first; returns the store and how many peers did not fit.
+    /// Those stay in `inner`: a restart never deletes what the database holds.
+    ///
+    /// With an asmap every stored peer gets its ASN (re)attached, since the map
+    /// may have changed since the peer was stored.
+    pub fn new(mut inner: Box<dyn PeerStore>, key: [u8; 32], asmap: Option<Asmap>) -> (Self, usize) {
+        let mut peers = inner.all_peers();
+        peers.sort_by(|a, b| b.last_connected.cmp(&a.last_connected).then(a.address.cmp(&b.address)));
+        let mut addrman = AddrMan::new(key).with_asmap(asmap);
+        let now = now_secs();
+        let mut unindexed = 0;
+        for peer in &peers {
+            if addrman.asmap.is_some() {
+                let asn = addrman.asn(peer.address.ip());
+                if peer.asn != asn {
+                    inner.update_peer(peer.address, &mut |p| p.asn = asn);
+                }
+            }
+            let source = peer.source.unwrap_or(peer.address);
+            if addrman.add(peer.address, source, &|_| false) != AddOutcome::Added {
+                unindexed += 1;
+                continue;
+            }
+            // Ao reconstruir, quem conectou mais recentemente fica com a vaga
+            if peer.last_connected.is_some() && addrman.tried[addrman.tried_index(&peer.address)].is_none() {
+                addrman.mark_good(peer.address, now);
+            }
+        }
+        let mut groups: HashMap<Subnet, usize> = HashMap::new();
+        for peer in &peers {
+            *groups.entry(netgroup(peer.address.ip())).or_default() += 1;
+        }
+        (AddrManStore { inner, addrman, groups, netgroup_limit: 0 }, unindexed)
+    }
+
+    pub fn addrman(&self) -> &AddrMan {
+        &self.addrman
+    }
+
+    /// Turns away gossiped addresses from netgroups that already hold `limit` peers,
+    /// so an addr flood can't fill the database between prunes.
+    pub fn set_netgroup_limit(&mut self, limit: usize) {
+        self.netgroup_limit = limit;
+    }
+
+    fn group_full(&self, addr: &SocketAddr) -> bool {
+        self.netgroup_limit > 0
+            && self.groups.get(&netgroup(addr.ip())).is_some_and(|n| *n >= self.netgroup_limit)
+    }
+
+    /// Counts `addr` if a write just created it.
+    fn note_stored(&mut self, addr: SocketAddr, was_known: bool) {
+        if !was_known && self.inner.get_peer(&addr).is_some() {
+            *self.groups.entry(netgroup(addr.ip())).or_default() += 1;
+        }
+    }
+
+    /// Removes `addr` from the durable store, keeping the group counts right.
+    fn evict(&mut self, addr: &SocketAddr) -> bool {
+        let removed = self.inner.remove_peer(addr);
+        if removed {
+            if let Some(n) = self.groups.get_mut(&netgroup(addr.ip())) {
+                *n = n.saturating_sub(1);
+            }
+        }
+        removed
+    }
+
+    /// Stores the ASN of a peer that was just written; without an asmap
+    /// whatever ASN the peer already had is left alone.
+    fn attach_asn(&mut self, addr: SocketAddr) {
+        if self.addrman.asmap.is_none() {
+            return;
+        }
+        let asn = self.addrman.asn(addr.ip());
+        if self.inner.get_peer(&addr).is_some_and(|p| p.asn != asn) {
+            self.inner.update_peer(addr, &mut |p| p.asn = asn);
+        }
+    }
+
+    /// Gives `addr` a slot if it can; returns false if it was turned away.
+    fn track(&mut self, addr: SocketAddr, source: Option<SocketAddr>) -> bool {
+        let now = now_secs();
+        let inner = &self.inner;
+        let terrible = |a: &SocketAddr| inner.get_peer(a).is_none_or(|p| is_terrible(&p, now));
+        match self.addrman.add(addr, source.unwrap_or(addr), &terrible) {
+            AddOutcome::Added | AddOutcome::Known => true,
+            AddOutcome::Replaced(old) => {
+                self.evict(&old);
+                true
+            }
+            AddOutcome::Rejected => false,
+        }
+    }
+
+    /// Whether a write may touch `addr`: stored peers always, new ones only if
+    /// they get a slot, like gossip.
+    fn admit(&mut self, addr: SocketAddr) -> bool {
+        if self.inner.get_peer(&addr).is_some() {
+            // Sem vaga o peer só fica fora das tabelas; a atualização vale do mesmo jeito
+            self.track(addr, None);
+            return true;
+        }
+        !self.group_full(&addr) && self.track(addr, None)
+    }
+
+    /// Core's `ResolveCollisions`, judged on what the store knows of each occupant.
+    fn resolve_collisions(&mut self) {
+        let now = now_secs();
+        let inner = &self.inner;
+        let verdict = |old: &SocketAddr, since: u64| {
+            let Some(p) = inner.get_peer(old) else { return Some(false) };
+            if p.uptime.last_try.is_some_and(|t| t >= since) {
+                Some(p.status == PeerStatus::ConnectedRecently)
+            } else if p.last_connected.is_some_and(|t| now.saturating_sub(t) < RECENT_SUCCESS_SECS) {
+                Some(true)
+            } else if now.saturating_sub(since) > TEST_WINDOW_SECS {
+                Some(false)
+            } else {
+                None
+            }
+        };
+        for dropped in self.addrman.resolve_collisions(&verdict) {
+            self.evict(&dropped);
+        }
+    }
+}
+
+impl PeerStore for AddrManStore {
+    fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+        let known = self.inner.get_peer(&addr).is_some();
+        if !known && self.group_full(&addr) {
+            return;
+        }
+        if self.track(addr, source) {
+            self.inner.register_peer(addr, services, source);
+            self.note_stored(addr, known);
+            self.attach_asn(addr);
+        }
+    }
+
+    fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+        let known = self.inner.get_peer(&addr).is_some();
+        if !self.admit(addr) {
+            return;
+        }
+        let good = status == PeerStatus::ConnectedRecently;
+        self.inner.update_status(addr, status);
+        self.note_stored(addr, known);
+        if good {
+            self.addrman.mark_good(addr, now_secs());
+        }
+        self.resolve_collisions();
+    }
+
+    fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+        let known = self.inner.get_peer(&addr).is_some();
+        if !self.admit(addr) {
+            return;
+        }
+        self.inner.update_peer(addr, f);
+        self.note_stored(addr, known);
+        self.attach_asn(addr);
+    }
+
+    fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo> {
+        self.inner.get_peer(addr)
+    }
+
+    fn good_peers(&self) -> Vec<PeerInfo> {
+        self.inner.good_peers()
+    }
+
+    fn all_peers(&self) -> Vec<PeerInfo> {
+        self.inner.all_peers()
+    }
+
+    fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+        self.addrman.remove(addr);
+        self.evict(addr)
+    }
+
+    fn persist(&mut self) -> io::Result<()> {
+        self.inner.persist()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::IpAddr;
+use std::path::Path;
+use crate::p2p::retention::netgroup;
+
+// Tamanhos de campo do interpretador de asmap do Bitcoin Core (util/asmap.cpp)
+const TYPE_BIT_SIZES: &[u8] = &[0, 0, 1];
+const ASN_BIT_SIZES: &[u8] = &[15, 16, 17, 18, 19, 20, 21, 22, 23, 24];
+const MATCH_BIT_SIZES: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+const JUMP_BIT_SIZES: &[u8] = &[
+    5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+];
+
+/// Every lookup walks the 128 bits of an IPv6 (or IPv4-mapped) address.
+const IP_BITS: u32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction {
+    Return,
+    Jump,
+    Match,
+    Default,
+}
+
+/// Reads bits from the program; `None` means it ended in the middle of a field.
+struct Reader<'a> {
+    bits: &'a [bool],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn next_bit(&mut self) -> Option<bool> {
+        let bit = *self.bits.get(self.pos)?;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bits.len() - self.pos
+    }
+
+    /// Core's `DecodeBits`: a unary exponent picking one of `sizes`, then a mantissa.
+    fn decode(&mut self, min: u32, sizes: &[u8]) -> Option<u32> {
+        let mut val = u64::from(min);
+        for (i, &size) in sizes.iter().enumerate() {
+            let bigger = i + 1 < sizes.len() && self.next_bit()?;
+            if bigger {
+                val += 1 << size;
+                continue;
+            }
+            for b in (0..size).rev() {
+                val += u64::from(self.next_bit()?) << b;
+            }
+            return u32::try_from(val).ok();
+        }
+        None
+    }
+
+    fn instruction(&mut self) -> Option<Instruction> {
+        Some(match self.decode(0, TYPE_BIT_SIZES)? {
+            0 => Instruction::Return,
+            1 => Instruction::Jump,
+            2 => Instruction::Match,
+            _ => Instruction::Default,
+        })
+    }
+
+    fn asn(&mut self) -> Option<u32> {
+        self.decode(1, ASN_BIT_SIZES)
+    }
+
+    fn match_bits(&mut self) -> Option<u32> {
+        self.decode(2, MATCH_BIT_SIZES)
+    }
+
+    fn jump(&mut self) -> Option<u32> {
+        self.decode(17, JUMP_BIT_SIZES)
+    }
+}
+
+/// Bits a MATCH compares; the highest set bit of the operand is only a length marker.
+fn match_len(operand: u32) -> u32 {
+    31 - operand.leading_zeros()
+}
+
+/// IP to autonomous system map in Bitcoin Core's compressed format
+/// (the `-asmap` file, built with contrib/asmap).
+///
+/// The file is a small bytecode program walked bit by bit over the address;
+/// it is checked once on load so lookups never run off the end.
+#[derive(Debug, Clone)]
+pub struct Asmap {
+    bits: Vec<bool>,
+}
+
+impl Asmap {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        // Bits de cada byte do menos para o mais significativo, como no Core
+        let bits = bytes.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1)).collect();
+        let asmap = Asmap { bits };
+        if !asmap.sane() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "invalid asmap file"));
+        }
+        Ok(asmap)
+    }
+
+    /// AS the address belongs to, `None` when the map has no entry for it.
+    /// Only IPv4 and IPv6 are mapped; Tor addresses never are.
+    pub fn lookup(&self, ip: IpAddr) -> Option<u32> {
+        let octets = match ip {
+            IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+            IpAddr::V6(v6) if v6.octets()[..6] == [0xFD, 0x87, 0xD8, 0x7E, 0xEB, 0x43] => return None,
+            IpAddr::V6(v6) => v6.octets(),
+        };
+        let ip_bits: Vec<bool> = octets.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1)).collect();
+        self.interpret(&ip_bits).filter(|&asn| asn != 0)
+    }
+
+    /// Core's `Interpret`.
+    fn interpret(&self, ip: &[bool]) -> Option<u32> {
+        let mut r = Reader { bits: &self.bits, pos: 0 };
+        let mut ip = ip.iter().copied();
+        let mut default_asn = 0;
+        while r.remaining() > 0 {
+            match r.instruction()? {
+                Instruction::Return => return r.asn(),
+                Instruction::Jump => {
+                    let jump = r.jump()? as usize;
+                    if jump >= r.remaining() {
+                        return None;
+                    }
+                    if ip.next()? {
+                        r.pos += jump;
+                    }
+                }
+                Instruction::Match => {
+                    let operand = r.match_bits()?;
+                    for b in (0..match_len(operand)).rev() {
+                        if ip.next()? != ((operand >> b) & 1 == 1) {
+                            return Some(default_asn);
+                        }
+                    }
+                }
+                Instruction::Default => default_asn = r.asn()?,
+            }
+        }
+        None
+    }
+
+    /// Core's `SanityCheckASMap`: every path ends in a RETURN without running
+    /// out of input bits, jumps land on instruction boundaries, and the file
+    /// carries no junk besides up to seven zero bits of padding.
+    fn sane(&self) -> bool {
+        let mut r = Reader { bits: &self.bits, pos: 0 };
+        let mut bits = IP_BITS;
+        // Destinos de saltos pendentes e quantos bits de IP sobram neles
+        let mut jumps: Vec<(usize, u32)> = Vec::new();
+        let mut prev = Instruction::Jump;
+        let mut had_incomplete_match = false;
+        while r.remaining() > 0 {
+            if jumps.last().is_some_and(|&(target, _)| r.pos >= target) {
+                return false;
+            }
+            let Some(op) = r.instruction() else { return false };
+            match op {
+                Instruction::Return => {
+                    if prev == Instruction::Default || r.asn().is_none() {
+                        return false;
+                    }
+                    match jumps.pop() {
+                        None => return r.remaining() <= 7 && r.bits[r.pos..].iter().all(|b| !b),
+                        Some((target, left)) => {
+                            if r.pos != target {
+                                return false;
+                            }
+                            bits = left;
+                            prev = Instruction::Jump;
+                        }
+                    }
+                }
+                Instruction::Jump => {
+                    let Some(jump) = r.jump() else { return false };
+                    let jump = jump as usize;
+                    if jump > r.remaining() || bits == 0 {
+                        return false;
+                    }
+                    bits -= 1;
+                    let target = r.pos + jump;
+                    if jumps.last().is_some_and(|&(t, _)| target >= t) {
+                        return false;
+                    }
+                    jumps.push((target, bits));
+                    prev = Instruction::Jump;
+                }
+                Instruction::Match => {
+                    let Some(operand) = r.match_bits() else { return false };
+                    let len = match_len(operand);
+                    if prev != Instruction::Match {
+                        had_incomplete_match = false;
+                    }
+                    if len < 8 && had_incomplete_match {
+                        return false;
+                    }
+                    had_incomplete_match = len < 8;
+                    if bits < len {
+                        return false;
+                    }
+                    bits -= len;
+                    prev = Instruction::Match;
+                }
+                Instruction::Default => {
+                    if prev == Instruction::Default || r.asn().is_none() {
+                        return false;
+                    }
+                    prev = Instruction::Default;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// One asmap instruction, for assembling maps in tests.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Return(u32),
+    /// Skips this many bits of program when the next IP bit is 1.
+    Jump(u32),
+    Match(u32),
+    Default(u32),
+}
+
+#[cfg(test)]
+fn encode(bits: &mut Vec<bool>, val: u32, min: u32, sizes: &[u8]) {
+    let mut v = val - min;
+    for (i, &size) in sizes.iter().enumerate() {
+        if i + 1 < sizes.len() {
+            if v >= 1 << size {
+                bits.push(true);
+                v -= 1 << size;
+                continue;
+            }
+            bits.push(false);
+        }
+        bits.extend((0..size).rev().map(|b| (v >> b) & 1 == 1));
+        return;
+    }
+}
+
+/// Inverse of the decoder: the program as bits, before padding.
+#[cfg(test)]
+pub fn assemble_bits(ops: &[Op]) -> Vec<bool> {
+    let mut bits = Vec::new();
+    for op in ops {
+        let (code, arg, min, sizes) = match *op {
+            Op::Return(asn) => (0, asn, 1, ASN_BIT_SIZES),
+            Op::Jump(off) => (1, off, 17, JUMP_BIT_SIZES),
+            Op::Match(m) => (2, m, 2, MATCH_BIT_SIZES),
+            Op::Default(asn) => (3, asn, 1, ASN_BIT_SIZES),
+        };
+        encode(&mut bits, code, 0, TYPE_BIT_SIZES);
+        encode(&mut bits, arg, min, sizes);
+    }
+    bits
+}
+
+/// The program as an asmap file, zero padded to a whole byte.
+#[cfg(test)]
+pub fn assemble(ops: &[Op]) -> Vec<u8> {
+    assemble_bits(ops)
+        .chunks(8)
+        .map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (i, &b)| byte | (u8::from(b) << i)))
+        .collect()
+}
+
+/// `AS13335`, or the address' netgroup when the AS is unknown; peers with the
+/// same group are spread out in DNS answers and addrman buckets.
+pub fn diversity_group(asn: Option<u32>, ip: IpAddr) -> String {
+    match asn {
+        Some(asn) => format!("AS{}", asn),
+        None => netgroup(ip).to_string(),
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// `peers.json` -> `peers.json.<n>`
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+fn sync_parent_dir(path: &Path) {
+    // Garante que o rename chegou ao disco; em alguns sistemas não dá pra abrir diretório
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    if let Ok(d) = File::open(dir) {
+        let _ = d.sync_all();
+    }
+}
+
+/// Writes `data` to `path` without ever leaving a half-written file behind:
+/// the bytes go to `<path>.tmp`, are fsynced, and then renamed over `path`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_with_backups(path, data, 0)
+}
+
+/// Same as `write_atomic`, but the previous `keep` versions of the file are kept
+/// as `<path>.1` (newest) .. `<path>.<keep>` (oldest).
+pub fn write_with_backups(path: &Path, data: &[u8], keep: usize) -> io::Result<()> {
+    write_file(path, data, keep, false)
+}
+
+/// Same as `write_atomic`, for secrets: on unix the file is created readable
+/// by its owner only, before any byte is written.
+pub fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_file(path, data, 0, true)
+}
+
+fn open_tmp(tmp: &Path, private: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    if private {
+        // Um .tmp esquecido manteria as permissões antigas; recria do zero
+        let _ = fs::remove_file(tmp);
+        options.create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+    }
+    options.open(tmp)
+}
+
+fn write_file(path: &Path, data: &[u8], keep: usize, private: bool) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    {
+        let mut file = open_tmp(&tmp, private)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+
+    if keep > 0 && path.exists() {
+        for n in (1..keep).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        // Copia em vez de renomear: `path` continua válido até o rename final
+        fs::copy(path, backup_path(path, 1))?;
+    }
+
+    fs::rename(&tmp, path)?;
+    sync_parent_dir(path);
+    Ok(())
+}
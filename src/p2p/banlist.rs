@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::p2p::atomic_file::write_atomic;
+use crate::p2p::database::{now_secs, PeerStatus};
+use crate::p2p::peer_store::SharedPeerStore;
+
+/// Same default as Bitcoin Core's `-bantime`.
+pub const DEFAULT_BAN_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest timed ban accepted over RPC; beyond this, ban permanently.
+pub const MAX_BAN_TIME: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// An address range in CIDR notation; a bare IP is a /32 (or /128) subnet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Subnet {
+    network: IpAddr,
+    prefix: u8,
+}
+
+/// IPv4 addresses are compared as IPv4-mapped IPv6 so one code path handles both.
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+fn mask(bits: u32) -> u128 {
+    if bits == 0 { 0 } else { u128::MAX << (128 - bits) }
+}
+
+impl Subnet {
+    pub fn new(ip: IpAddr, prefix: u8) -> Option<Self> {
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return None;
+        }
+        // Normaliza para o endereço de rede (1.2.3.4/24 -> 1.2.3.0/24)
+        let bits = u128::from(to_v6(ip)) & mask(Self::v6_bits(ip, prefix));
+        let v6 = Ipv6Addr::from(bits);
+        let network = match (ip, v6.to_ipv4_mapped()) {
+            (IpAddr::V4(_), Some(v4)) => IpAddr::V4(v4),
+            _ => IpAddr::V6(v6),
+        };
+        Some(Subnet { network, prefix })
+    }
+
+    pub fn single(ip: IpAddr) -> Self {
+        let prefix = if ip.is_ipv4() { 32 } else { 128 };
+        Subnet { network: ip, prefix }
+    }
+
+    fn v6_bits(ip: IpAddr, prefix: u8) -> u32 {
+        if ip.is_ipv4() { 96 + prefix as u32 } else { prefix as u32 }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if ip.is_ipv4() != self.network.is_ipv4() {
+            return false;
+        }
+        let m = mask(Self::v6_bits(self.network, self.prefix));
+        u128::from(to_v6(ip)) & m == u128::from(to_v6(self.network)) & m
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let ip: IpAddr = ip.parse().map_err(|_| format!("invalid IP address: {}", ip))?;
+        match prefix {
+            None => Ok(Subnet::single(ip)),
+            Some(p) => {
+                let p: u8 = p.parse().map_err(|_| format!("invalid prefix length: {}", p))?;
+                Subnet::new(ip, p).ok_or_else(|| format!("prefix /{} too long for {}", p, ip))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Subnet> for String {
+    fn from(s: Subnet) -> String {
+        s.to_string()
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BanEntry {
+    pub subnet: Subnet,
+    pub created: u64,
+    /// Unix time the ban ends; `None` bans forever.
+    pub until: Option<u64>,
+    pub reason: String,
+}
+
+impl BanEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BanFile {
+    version: u32,
+    bans: Vec<BanEntry>,
+}
+
+/// Addresses and subnets the seeder must never connect to, learn from or serve.
+#[derive(Debug, Default)]
+pub struct BanList {
+    entries: BTreeMap<Subnet, BanEntry>,
+    path: Option<PathBuf>,
+}
+
+pub type SharedBanList = Arc<Mutex<BanList>>;
+
+impl BanList {
+    /// Loads the ban list kept at `path` (empty if the file doesn't exist yet).
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut list = BanList { entries: BTreeMap::new(), path: Some(path.to_path_buf()) };
+        match fs::read_to_string(path) {
+            Ok(data) => {
+                let file: BanFile =
+                    serde_json::from_str(&data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                list.entries = file.bans.into_iter().map(|b| (b.subnet, b)).collect();
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(list)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let file = BanFile { version: 1, bans: self.entries.values().cloned().collect() };
+        write_atomic(path, serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+
+    /// Bans `subnet` for `duration` (forever if `None`), replacing any earlier ban.
+    pub fn ban(&mut self, subnet: Subnet, duration: Option<Duration>, reason: &str) -> io::Result<()> {
+        let now = now_secs();
+        let entry = BanEntry {
+            subnet,
+            created: now,
+            until: duration.map(|d| now.saturating_add(d.as_secs())),
+            reason: reason.to_string(),
+        };
+        self.entries.insert(subnet, entry);
+        self.save()
+    }
+
+    /// Returns whether there was a ban to lift.
+    pub fn unban(&mut self, subnet: &Subnet) -> io::Result<bool> {
+        let removed = self.entries.remove(subnet).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// The active ban covering `ip`, if any.
+    pub fn ban_for(&self, ip: IpAddr) -> Option<&BanEntry> {
+        let now = now_secs();
+        self.entries
+            .values()
+            .find(|b| !b.is_expired(now) && b.subnet.contains(ip))
+    }
+
+    pub fn is_banned(&self, addr: &SocketAddr) -> bool {
+        self.ban_for(addr.ip()).is_some()
+    }
+
+    /// Drops expired bans and returns the ones still active.
+    pub fn list(&mut self) -> io::Result<Vec<BanEntry>> {
+        let now = now_secs();
+        let before = self.entries.len();
+        self.entries.retain(|_, b| !b.is_expired(now));
+        if self.entries.len() != before {
+            self.save()?;
+        }
+        Ok(self.entries.values().cloned().collect())
+    }
+}
+
+/// Parses ban durations like `3600`, `90s`, `30m`, `12h` or `7d`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = num.parse().ok()?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    Some(Duration::from_secs(n.checked_mul(unit)?))
+}
+
+/// Bans `subnet` and marks every known peer inside it as `Banned` in the store.
+/// Returns how many known peers were affected.
+pub fn ban_subnet(
+    bans: &SharedBanList,
+    store: &SharedPeerStore,
+    subnet: Subnet,
+    duration: Option<Duration>,
+    reason: &str,
+) -> io::Result<usize> {
+    bans.lock().unwrap().ban(subnet, duration, reason)?;
+    let mut db = store.lock().unwrap();
+    let hit: Vec<SocketAddr> = db
+        .all_peers()
+        .into_iter()
+        .filter(|p| subnet.contains(p.address.ip()))
+        .map(|p| p.address)
+        .collect();
+    for addr in &hit {
+        db.ban_peer(*addr);
+    }
+    Ok(hit.len())
+}
+
+/// Lifts the ban on `subnet`; peers inside it that are no longer covered by
+/// another ban go back to `NeverTried` so the crawler retries them.
+pub fn unban_subnet(bans: &SharedBanList, store: &SharedPeerStore, subnet: &Subnet) -> io::Result<bool> {
+    let mut list = bans.lock().unwrap();
+    if !list.unban(subnet)? {
+        return Ok(false);
+    }
+    let mut db = store.lock().unwrap();
+    for peer in db.all_peers() {
+        if peer.status == PeerStatus::Banned && subnet.contains(peer.address.ip()) && !list.is_banned(&peer.address) {
+            db.update_status(peer.address, PeerStatus::NeverTried);
+        }
+    }
+    Ok(true)
+}
+
+/// Lifts every ban, like `unban_subnet` for each of them; returns how many there were.
+pub fn clear_banned(bans: &SharedBanList, store: &SharedPeerStore) -> io::Result<usize> {
+    let subnets: Vec<Subnet> = bans.lock().unwrap().list()?.into_iter().map(|b| b.subnet).collect();
+    for subnet in &subnets {
+        unban_subnet(bans, store, subnet)?;
+    }
+    Ok(subnets.len())
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use serde::Serialize;
+use crate::p2p::database::{PeerInfo, PeerStatus};
+
+pub const DEFAULT_PORT: u16 = 8333;
+
+/// Service bits from Bitcoin Core's protocol.h, in bit order.
+const SERVICE_NAMES: &[(u64, &str)] = &[
+    (1 << 0, "NETWORK"),
+    (1 << 1, "GETUTXO"),
+    (1 << 2, "BLOOM"),
+    (1 << 3, "WITNESS"),
+    (1 << 6, "COMPACT_FILTERS"),
+    (1 << 10, "NETWORK_LIMITED"),
+    (1 << 11, "P2P_V2"),
+];
+
+/// One line of a census table: how many peers fall in `key`, and how many of
+/// those we reached recently.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CensusRow {
+    pub key: String,
+    pub total: usize,
+    pub reachable: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Census {
+    pub total: usize,
+    pub reachable: usize,
+    pub networks: Vec<CensusRow>,
+    pub user_agents: Vec<CensusRow>,
+    pub protocol_versions: Vec<CensusRow>,
+    pub services: Vec<CensusRow>,
+    pub ports: Vec<CensusRow>,
+    /// All `(desconhecido)` unless an asmap was loaded.
+    pub asns: Vec<CensusRow>,
+}
+
+/// `NETWORK|WITNESS|NETWORK_LIMITED (0x409)`; unknown bits stay in the hex part.
+pub fn service_names(services: u64) -> String {
+    let names: Vec<&str> = SERVICE_NAMES
+        .iter()
+        .filter(|(bit, _)| services & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        format!("NONE ({:#x})", services)
+    } else {
+        format!("{} ({:#x})", names.join("|"), services)
+    }
+}
+
+/// IPv4, IPv6, or Tor for the OnionCat range (fd87:d87e:eb43::/48).
+pub fn network_name(ip: IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "IPv4",
+        IpAddr::V6(v6) if v6.octets()[..6] == [0xFD, 0x87, 0xD8, 0x7E, 0xEB, 0x43] => "Onion",
+        IpAddr::V6(v6) if v6.to_ipv4_mapped().is_some() => "IPv4",
+        IpAddr::V6(_) => "IPv6",
+    }
+}
+
+fn tally<F>(peers: &[PeerInfo], key: F) -> Vec<CensusRow>
+where
+    F: Fn(&PeerInfo) -> String,
+{
+    let mut rows: HashMap<String, CensusRow> = HashMap::new();
+    for peer in peers {
+        let k = key(peer);
+        let row = rows
+            .entry(k.clone())
+            .or_insert_with(|| CensusRow { key: k, total: 0, reachable: 0 });
+        row.total += 1;
+        if peer.status == PeerStatus::ConnectedRecently {
+            row.reachable += 1;
+        }
+    }
+    let mut rows: Vec<CensusRow> = rows.into_values().collect();
+    rows.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.key.cmp(&b.key)));
+    rows
+}
+
+fn unknown<T: ToString>(v: Option<T>) -> String {
+    v.map_or_else(|| "(desconhecido)".to_string(), |v| v.to_string())
+}
+
+pub fn build_census(peers: &[PeerInfo]) -> Census {
+    Census {
+        total: peers.len(),
+        reachable: peers.iter().filter(|p| p.status == PeerStatus::ConnectedRecently).count(),
+        networks: tally(peers, |p| network_name(p.address.ip()).to_string()),
+        user_agents: tally(peers, |p| unknown(p.user_agent.clone())),
+        protocol_versions: tally(peers, |p| unknown(p.protocol_version)),
+        services: tally(peers, |p| unknown(p.services.map(service_names))),
+        ports: tally(peers, |p| match p.address.port() {
+            DEFAULT_PORT => format!("{} (padrão)", DEFAULT_PORT),
+            port => port.to_string(),
+        }),
+        asns: tally(peers, |p| unknown(p.asn.map(|asn| format!("AS{}", asn)))),
+    }
+}
+
+fn section(out: &mut String, title: &str, rows: &[CensusRow], limit: usize) {
+    let width = rows.iter().take(limit).map(|r| r.key.chars().count()).max().unwrap_or(0).max(title.len());
+    let _ = writeln!(out, "\n{:<width$}  {:>8}  {:>10}", title, "peers", "alcançáveis", width = width);
+    let _ = writeln!(out, "{}", "-".repeat(width + 22));
+    for row in rows.iter().take(limit) {
+        let _ = writeln!(out, "{:<width$}  {:>8}  {:>10}", row.key, row.total, row.reachable, width = width);
+    }
+    if rows.len() > limit {
+        let (total, reachable) = rows[limit..].iter().fold((0, 0), |(t, r), row| (t + row.total, r + row.reachable));
+        let other = format!("(outros {})", rows.len() - limit);
+        let _ = writeln!(out, "{:<width$}  {:>8}  {:>10}", other, total, reachable, width = width);
+    }
+}
+
+/// Terminal table; the long tails (user agents, ports...) are cut at `limit` rows.
+pub fn render_table(census: &Census, limit: usize) -> String {
+    let mut out = format!("Peers: {} ({} alcançáveis)\n", census.total, census.reachable);
+    section(&mut out, "Rede", &census.networks, limit);
+    section(&mut out, "User agent", &census.user_agents, limit);
+    section(&mut out, "Versão do protocolo", &census.protocol_versions, limit);
+    section(&mut out, "Serviços", &census.services, limit);
+    section(&mut out, "Porta", &census.ports, limit);
+    section(&mut out, "Sistema autônomo", &census.asns, limit);
+    out
+}
@@ -0,0 +1,272 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use crate::p2p::banlist::{BanList, SharedBanList};
+use crate::p2p::peer_store::{PeerStore, SharedPeerStore};
+use crate::p2p::utils::{sha256d, MAGIC};
+
+// Constantes do addrman.h / protocol.h do Bitcoin Core
+const INCOMPATIBILITY_BASE: u8 = 32;
+/// Newest peers.dat format we understand (Format::V4_MULTIPORT).
+const MAX_SUPPORTED_FORMAT: u8 = 4;
+/// From Format::V3_BIP155 on, addresses are stored in the BIP155 (addrv2) encoding.
+const FORMAT_BIP155: u8 = 3;
+const DISK_VERSION_IGNORE_MASK: i32 = 0x0007_FFFF;
+const DISK_VERSION_ADDRV2: i32 = 1 << 29;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+/// Address as found in the file; only IPv4/IPv6 can be stored in `PeerDatabase`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NetAddr {
+    Ip(std::net::IpAddr),
+    /// Tor, I2P, CJDNS and other networks we can't connect to.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreAddr {
+    pub addr: Option<SocketAddr>,
+    pub time: u32,
+    pub services: u64,
+    pub last_success: i64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub new: usize,
+    pub tried: usize,
+    pub anchors: usize,
+    /// Entries on networks we can't store (Tor, I2P, CJDNS).
+    pub skipped: usize,
+    /// Entries covered by our ban list.
+    pub banned: usize,
+    /// Entries the store turned away (full bucket or netgroup).
+    pub refused: usize,
+    pub imported: usize,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.pos + n > self.data.len() {
+            return Err(invalid(format!("unexpected end of file at byte {}", self.pos)));
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn compact_size(&mut self) -> io::Result<u64> {
+        Ok(match self.u8()? {
+            0xFD => u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            0xFE => self.u32()? as u64,
+            0xFF => self.u64()?,
+            n => n as u64,
+        })
+    }
+
+    /// CNetAddr, in the pre-BIP155 16-byte form or the addrv2 form.
+    fn net_addr(&mut self, addrv2: bool) -> io::Result<NetAddr> {
+        if !addrv2 {
+            let bytes: [u8; 16] = self.take(16)?.try_into().unwrap();
+            let v6 = Ipv6Addr::from(bytes);
+            // fd87:d87e:eb43::/48 é o "OnionCat" usado para Tor v2 no formato antigo
+            if bytes[..6] == [0xFD, 0x87, 0xD8, 0x7E, 0xEB, 0x43] {
+                return Ok(NetAddr::Other);
+            }
+            return Ok(NetAddr::Ip(match v6.to_ipv4_mapped() {
+                Some(v4) => v4.into(),
+                None => v6.into(),
+            }));
+        }
+        let network = self.u8()?;
+        let len = self.compact_size()?;
+        if len > 512 {
+            return Err(invalid(format!("address too long ({} bytes)", len)));
+        }
+        let bytes = self.take(len as usize)?;
+        Ok(match (network, len) {
+            (1, 4) => NetAddr::Ip(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).unwrap()).into()),
+            (2, 16) => NetAddr::Ip(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).unwrap()).into()),
+            (1, _) | (2, _) => return Err(invalid(format!("bad length {} for network {}", len, network))),
+            _ => NetAddr::Other,
+        })
+    }
+
+    /// CAddress in disk format: version, time, services and CService.
+    fn disk_address(&mut self, stream_addrv2: bool) -> io::Result<(NetAddr, u32, u64, u16)> {
+        let version = self.i32()? & !DISK_VERSION_IGNORE_MASK;
+        let addrv2 = match version {
+            0 => false,
+            DISK_VERSION_ADDRV2 if stream_addrv2 => true,
+            v => return Err(invalid(format!("unsupported address version {:#x}", v))),
+        };
+        let time = self.u32()?;
+        let services = if addrv2 { self.compact_size()? } else { self.u64()? };
+        let net = self.net_addr(addrv2)?;
+        let port = u16::from_be_bytes(self.take(2)?.try_into().unwrap());
+        Ok((net, time, services, port))
+    }
+}
+
+fn to_core_addr(net: NetAddr, time: u32, services: u64, port: u16, last_success: i64) -> CoreAddr {
+    let addr = match net {
+        NetAddr::Ip(ip) => Some(SocketAddr::new(ip, port)),
+        NetAddr::Other => None,
+    };
+    CoreAddr { addr, time, services, last_success }
+}
+
+/// Checks the network magic and trailing sha256d of a Core `.dat` file and
+/// returns the serialized payload in between.
+fn unwrap_file(data: &[u8]) -> io::Result<&[u8]> {
+    if data.len() < 4 + 32 {
+        return Err(invalid("file too short"));
+    }
+    let (body, checksum) = data.split_at(data.len() - 32);
+    if sha256d(body) != checksum {
+        return Err(invalid("checksum mismatch"));
+    }
+    if body[..4] != MAGIC {
+        return Err(invalid("file is not from Bitcoin mainnet"));
+    }
+    Ok(&body[4..])
+}
+
+/// Parses `peers.dat` into (new table, tried table) entries.
+pub fn parse_peers_dat(data: &[u8]) -> io::Result<(Vec<CoreAddr>, Vec<CoreAddr>)> {
+    let mut r = Reader { data: unwrap_file(data)?, pos: 0 };
+    let format = r.u8()?;
+    let compat = r.u8()?.saturating_sub(INCOMPATIBILITY_BASE);
+    if compat > MAX_SUPPORTED_FORMAT {
+        return Err(invalid(format!("peers.dat format {} needs a newer reader", format)));
+    }
+    let addrv2 = format >= FORMAT_BIP155;
+    r.take(32)?; // nKey
+    let n_new = r.i32()?;
+    let n_tried = r.i32()?;
+    let _n_buckets = r.i32()?;
+    if !(0..=1 << 20).contains(&n_new) || !(0..=1 << 20).contains(&n_tried) {
+        return Err(invalid(format!("implausible table sizes {} / {}", n_new, n_tried)));
+    }
+
+    let mut read_table = |n: i32| -> io::Result<Vec<CoreAddr>> {
+        (0..n)
+            .map(|_| {
+                let (net, time, services, port) = r.disk_address(addrv2)?;
+                r.net_addr(addrv2)?; // source
+                let last_success = r.i64()?;
+                let _attempts = r.i32()?;
+                Ok(to_core_addr(net, time, services, port, last_success))
+            })
+            .collect()
+    };
+    let new = read_table(n_new)?;
+    let tried = read_table(n_tried)?;
+    // O resto (buckets e checksum do asmap) só importa para o addrman do Core
+    Ok((new, tried))
+}
+
+/// Parses `anchors.dat`: the block-relay-only peers Core reconnects to on startup.
+pub fn parse_anchors_dat(data: &[u8]) -> io::Result<Vec<CoreAddr>> {
+    let mut r = Reader { data: unwrap_file(data)?, pos: 0 };
+    let count = r.compact_size()?;
+    if count > 1024 {
+        return Err(invalid(format!("implausible anchor count {}", count)));
+    }
+    (0..count)
+        .map(|_| {
+            let (net, time, services, port) = r.disk_address(true)?;
+            Ok(to_core_addr(net, time, services, port, 0))
+        })
+        .collect()
+}
+
+fn insert(store: &mut dyn PeerStore, bans: &BanList, entries: &[CoreAddr], summary: &mut ImportSummary) {
+    for entry in entries {
+        let Some(addr) = entry.addr else {
+            summary.skipped += 1;
+            continue;
+        };
+        if bans.is_banned(&addr) {
+            summary.banned += 1;
+            continue;
+        }
+        let before = store.get_peer(&addr);
+        store.register_peer(addr, Some(entry.services), None);
+        if store.get_peer(&addr).is_none() {
+            summary.refused += 1;
+            continue;
+        }
+        let time = entry.time as u64;
+        store.update_peer(addr, &mut |p| {
+            // Usa os horários do Core em vez do da importação, sem nunca voltar no
+            // tempo: um peers.dat antigo não pode envelhecer o que já sabemos
+            let seen = before.as_ref().and_then(|b| b.last_seen);
+            p.last_seen = Some(seen.map_or(time, |s| s.max(time)));
+            p.first_seen = Some(p.first_seen.map_or(time, |f| f.min(time)));
+            if entry.last_success > 0 {
+                p.last_connected = Some(p.last_connected.map_or(entry.last_success as u64, |c| c.max(entry.last_success as u64)));
+            }
+        });
+        summary.imported += 1;
+    }
+}
+
+/// Imports a Core `peers.dat` or `anchors.dat` (told apart by file name) into the store.
+pub fn import_core_file(path: &Path, store: &mut dyn PeerStore, bans: &BanList) -> io::Result<ImportSummary> {
+    let data = fs::read(path)?;
+    let is_anchors = path
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().starts_with("anchors"));
+    let mut summary = ImportSummary::default();
+    if is_anchors {
+        let anchors = parse_anchors_dat(&data)?;
+        summary.anchors = anchors.len();
+        insert(store, bans, &anchors, &mut summary);
+    } else {
+        let (new, tried) = parse_peers_dat(&data)?;
+        summary.new = new.len();
+        summary.tried = tried.len();
+        insert(store, bans, &tried, &mut summary);
+        insert(store, bans, &new, &mut summary);
+    }
+    Ok(summary)
+}
+
+/// `import_core_file` on the shared store, persisting the result.
+pub fn import_into(path: &Path, store: &SharedPeerStore, bans: &SharedBanList) -> io::Result<ImportSummary> {
+    let bans = bans.lock().unwrap();
+    let mut db = store.lock().unwrap();
+    let summary = import_core_file(path, &mut **db, &bans)?;
+    db.persist()?;
+    Ok(summary)
+}
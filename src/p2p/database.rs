@@ -1,9 +1,16 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use crate::p2p::atomic_file::{backup_path, write_with_backups};
+use crate::p2p::migrations::{migrate, DB_VERSION};
+use crate::p2p::utils::VersionInfo;
+
+/// How many previous versions of the peer database are kept next to it.
+pub const DB_BACKUPS: usize = 2;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PeerStatus {
@@ -13,72 +20,284 @@ pub enum PeerStatus {
     Banned,
 }
 
+impl PeerStatus {
+    pub const ALL: [PeerStatus; 4] =
+        [PeerStatus::NeverTried, PeerStatus::ConnectedRecently, PeerStatus::Unreachable, PeerStatus::Banned];
+
+    /// snake_case name used in metrics labels and RPC results.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeerStatus::NeverTried => "never_tried",
+            PeerStatus::ConnectedRecently => "connected_recently",
+            PeerStatus::Unreachable => "unreachable",
+            PeerStatus::Banned => "banned",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub address: SocketAddr,
+    #[serde(default)]
     pub last_seen: Option<u64>,         
+    #[serde(default)]
     pub last_connected: Option<u64>,    
     pub status: PeerStatus,
+    #[serde(default)]
     pub services: Option<u64>,      
+    /// When we first heard about this address.
+    #[serde(default)]
+    pub first_seen: Option<u64>,
+    /// Peer that gossiped this address to us (`None` for seeds and manual adds).
+    #[serde(default)]
+    pub source: Option<SocketAddr>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    #[serde(default)]
+    pub start_height: Option<i32>,
+    /// Round trip of the last ping/pong, in milliseconds.
+    #[serde(default)]
+    pub ping_ms: Option<u64>,
+    /// Why the last connection attempt failed; cleared on success.
+    #[serde(default)]
+    pub last_failure: Option<PeerFailure>,
+    /// Accumulated protocol violations, see `misbehavior::Misbehavior::score`.
+    #[serde(default)]
+    pub misbehavior_score: u32,
+    /// Failed connection attempts since the last success.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Reachability over time, updated on every connection attempt.
+    #[serde(default)]
+    pub uptime: UptimeStats,
+    /// Autonomous system of the address, from the asmap (`None` without one).
+    #[serde(default)]
+    pub asn: Option<u32>,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub struct PeerDatabase {
-    pub peers: HashMap<SocketAddr, PeerInfo>,
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum FailureStage {
+    Connect,
+    Handshake,
 }
 
-impl PeerDatabase {
-    pub fn load_from_file(path: &str) -> Self {
-        if let Ok(mut file) = File::open(path) {
-            let mut data = String::new();
-            if file.read_to_string(&mut data).is_ok() {
-                if let Ok(db) = serde_json::from_str(&data) {
-                    return db;
-                }
-            }
-        }
-        PeerDatabase::default()
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerFailure {
+    pub at: u64,
+    pub stage: FailureStage,
+    pub reason: String,
+}
+
+/// Exponentially decaying success rate over one time window, as kept by
+/// sipa's bitcoin-seeder (`CAddrStat`).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct UptimeStat {
+    pub weight: f64,
+    pub count: f64,
+    pub reliability: f64,
+}
+
+impl UptimeStat {
+    fn update(&mut self, good: bool, age: u64, tau: f64) {
+        let f = (-(age as f64) / tau).exp();
+        self.reliability = self.reliability * f + if good { 1.0 - f } else { 0.0 };
+        self.count = self.count * f + 1.0;
+        self.weight = self.weight * f + (1.0 - f);
     }
+}
 
-    pub fn save_to_file(&self, path: &str) {
-        if let Ok(json) = serde_json::to_string_pretty(self) {
-            if let Ok(mut file) = OpenOptions::new().write(true).create(true).truncate(true).open(path) {
-                let _ = file.write_all(json.as_bytes());
-            }
-        }
+/// Uptime over the 2h/8h/1d/7d/30d windows reported in `dnsseed.dump`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UptimeStats {
+    pub last_try: Option<u64>,
+    pub h2: UptimeStat,
+    pub h8: UptimeStat,
+    pub d1: UptimeStat,
+    pub w1: UptimeStat,
+    pub m1: UptimeStat,
+}
+
+impl UptimeStats {
+    pub fn record(&mut self, good: bool, now: u64) {
+        let age = self.last_try.map_or(0, |t| now.saturating_sub(t));
+        self.h2.update(good, age, 3600.0 * 2.0);
+        self.h8.update(good, age, 3600.0 * 8.0);
+        self.d1.update(good, age, 3600.0 * 24.0);
+        self.w1.update(good, age, 3600.0 * 24.0 * 7.0);
+        self.m1.update(good, age, 3600.0 * 24.0 * 30.0);
+        self.last_try = Some(now);
     }
 
-    pub fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>) {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
-        let entry = self.peers.entry(addr).or_insert(PeerInfo {
-            address: addr,
+    /// The five windows, shortest first.
+    pub fn windows(&self) -> [UptimeStat; 5] {
+        [self.h2, self.h8, self.d1, self.w1, self.m1]
+    }
+}
+
+impl PeerInfo {
+    pub fn new(address: SocketAddr, status: PeerStatus, now: u64) -> Self {
+        PeerInfo {
+            address,
             last_seen: Some(now),
             last_connected: None,
-            status: PeerStatus::NeverTried,
-            services,
-        });
-        entry.last_seen = Some(now);
+            status,
+            services: None,
+            first_seen: Some(now),
+            source: None,
+            user_agent: None,
+            protocol_version: None,
+            start_height: None,
+            ping_ms: None,
+            last_failure: None,
+            misbehavior_score: 0,
+            attempts: 0,
+            uptime: UptimeStats::default(),
+            asn: None,
+        }
+    }
+
+    /// Marks the peer as seen again, keeping the last advertised services.
+    pub fn touch(&mut self, services: Option<u64>, now: u64) {
+        self.last_seen = Some(now);
         if let Some(s) = services {
-            entry.services = Some(s);
+            self.services = Some(s);
         }
     }
 
-    pub fn _update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
-        if let Some(peer) = self.peers.get_mut(&addr) {
-            peer.status = status.clone();
-            peer.last_seen = Some(now);
-            if status == PeerStatus::ConnectedRecently {
-                peer.last_connected = Some(now);
+    /// Stores what the peer told us about itself in its `version` message.
+    pub fn record_version(&mut self, info: &VersionInfo) {
+        self.protocol_version = Some(info.version);
+        self.services = Some(info.services);
+        self.user_agent = Some(info.user_agent.clone());
+        self.start_height = Some(info.start_height);
+        self.last_failure = None;
+    }
+
+    pub fn record_failure(&mut self, stage: FailureStage, reason: String, now: u64) {
+        self.last_failure = Some(PeerFailure { at: now, stage, reason });
+    }
+
+    pub fn set_status(&mut self, status: PeerStatus, now: u64) {
+        // Só tentativas de conexão contam para o uptime, não ban/unban
+        match status {
+            PeerStatus::ConnectedRecently => {
+                self.uptime.record(true, now);
+                self.attempts = 0;
             }
+            PeerStatus::Unreachable => {
+                self.uptime.record(false, now);
+                self.attempts = self.attempts.saturating_add(1);
+            }
+            _ => {}
+        }
+        if status == PeerStatus::ConnectedRecently {
+            self.last_connected = Some(now);
+        }
+        self.status = status;
+        self.last_seen = Some(now);
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PeerDatabase {
+    pub peers: HashMap<SocketAddr, PeerInfo>,
+}
+
+/// What `load_from_file` had to do to produce a database.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    /// File the peers were read from; `None` when starting with an empty database.
+    pub source: Option<PathBuf>,
+    /// Files that exist but could not be read, with the reason.
+    pub corrupt: Vec<(PathBuf, String)>,
+}
+
+impl PeerDatabase {
+    fn read_file(path: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        Self::from_json(&data)
+    }
+
+    /// Parses any supported on-disk version, migrating it to the current one.
+    pub fn from_json(data: &str) -> io::Result<Self> {
+        let doc: serde_json::Value =
+            serde_json::from_str(data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        serde_json::from_value(migrate(doc)?).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Loads `path`, falling back to the newest readable backup if it is corrupt.
+    ///
+    /// Only a missing database yields an empty one: if the file and every backup
+    /// are unreadable this fails, so we never overwrite good data with nothing.
+    pub fn load_from_file(path: &Path) -> io::Result<(Self, LoadReport)> {
+        let mut report = LoadReport::default();
+        let candidates = std::iter::once(path.to_path_buf())
+            .chain((1..=DB_BACKUPS).map(|n| backup_path(path, n)));
+
+        for candidate in candidates {
+            match Self::read_file(&candidate) {
+                Ok(db) => {
+                    report.source = Some(candidate);
+                    return Ok((db, report));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => report.corrupt.push((candidate, e.to_string())),
+            }
+        }
+
+        if report.corrupt.is_empty() {
+            Ok((PeerDatabase::default(), report))
         } else {
-            self.peers.insert(addr, PeerInfo {
-                address: addr,
-                last_seen: Some(now),
-                last_connected: if status == PeerStatus::ConnectedRecently { Some(now) } else { None },
-                status,
-                services: None,
-            });
+            let files: Vec<String> = report
+                .corrupt
+                .iter()
+                .map(|(p, e)| format!("{}: {}", p.display(), e))
+                .collect();
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("peer database and all backups are corrupt ({})", files.join("; ")),
+            ))
         }
     }
-}
\ No newline at end of file
+
+    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
+        let doc = serde_json::json!({ "version": DB_VERSION, "peers": &self.peers });
+        let json = serde_json::to_string_pretty(&doc)?;
+        write_with_backups(path, json.as_bytes(), DB_BACKUPS)
+    }
+
+    pub fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+        let now = now_secs();
+        self.peers
+            .entry(addr)
+            .or_insert_with(|| PeerInfo { source, ..PeerInfo::new(addr, PeerStatus::NeverTried, now) })
+            .touch(services, now);
+    }
+
+    pub fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+        let now = now_secs();
+        f(self.peers.entry(addr).or_insert_with(|| PeerInfo::new(addr, PeerStatus::NeverTried, now)));
+    }
+
+    pub fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+        let now = now_secs();
+        self.peers
+            .entry(addr)
+            .or_insert_with(|| PeerInfo::new(addr, PeerStatus::NeverTried, now))
+            .set_status(status, now);
+    }
+
+    pub fn good_peers(&self) -> Vec<PeerInfo> {
+        self.peers
+            .values()
+            .filter(|p| p.status == PeerStatus::ConnectedRecently)
+            .cloned()
+            .collect()
+    }
+}
@@ -1,67 +1,87 @@
 use std::net::SocketAddr;
-use std::sync::{Arc, RwLock};
 use tokio::net::UdpSocket;
-use crate::p2p::database::PeerDatabase;
-use rand::seq::IteratorRandom;
+use crate::p2p::peer_store::SharedPeerStore;
+use crate::p2p::banlist::SharedBanList;
+use rand::seq::SliceRandom;
+use crate::p2p::asmap::diversity_group;
+use crate::p2p::database::PeerInfo;
+use std::collections::HashSet;
 use crate::p2p::log::{LogLevel};
+use crate::p2p::metrics::SharedMetrics;
 
 
 const MAX_PEERS: usize = 10;
 const DNS_PORT: u16 = 1053; 
 
 pub async fn run_dns_server(
-    peer_db: Arc<RwLock<PeerDatabase>>,
+    peer_store: SharedPeerStore,
+    bans: SharedBanList,
     domain: &str,
     log_tx: std::sync::mpsc::Sender<crate::p2p::log::LogMessage>,
+    metrics: SharedMetrics,
 ) -> std::io::Result<()> {
     let addr = format!("0.0.0.0:{}", DNS_PORT);
     let socket = UdpSocket::bind(&addr).await?;
-    crate::p2p::log::log(&log_tx, LogLevel::Info, crate::p2p::log::Event::Custom(format!("DNS server listening on {}", addr)));
+    crate::p2p::log::log(&log_tx, LogLevel::Info, crate::p2p::log::Event::DnsListening(addr.clone()));
 
     let mut buf = [0u8; 512];
     loop {
         let (len, src) = socket.recv_from(&mut buf).await?;
         let req = &buf[..len];
 
-        if let Some((txid, is_a_query, qname)) = parse_dns_query(req) {
-            if is_a_query && qname == domain {
+        if let Some((txid, qtype, qclass, qname)) = parse_dns_query(req) {
+            if qtype == 1 && qclass == 1 && qname == domain {
                 let peers = {
-                    let db = peer_db.read().unwrap();
-                    db.peers
-                        .values()
-                        .filter(|p| p.status == crate::p2p::database::PeerStatus::ConnectedRecently)
-                        .map(|p| p.address)
-                        .choose_multiple(&mut rand::rng(), MAX_PEERS)
+                    let bans = bans.lock().unwrap();
+                    let db = peer_store.lock().unwrap();
+                    let good = db.good_peers().into_iter().filter(|p| !bans.is_banned(&p.address)).collect();
+                    pick_diverse(good, MAX_PEERS)
                 };
                 let response = build_dns_response(req, txid, &qname, &peers);
                 let _ = socket.send_to(&response, src).await;
+                metrics.dns_query(qtype, "NOERROR");
                 crate::p2p::log::log(
                     &log_tx,
                     LogLevel::Info,
-                    crate::p2p::log::Event::Custom(format!("Respondido para {} com {} peers", src, peers.len()))
+                    crate::p2p::log::Event::DnsAnswered(src, peers.len())
                 );
             } else {
                 let response = build_dns_notimpl(req, txid);
                 let _ = socket.send_to(&response, src).await;
+                metrics.dns_query(qtype, "NOTIMP");
                 crate::p2p::log::log(
                     &log_tx,
                     LogLevel::Warn,
-                    crate::p2p::log::Event::Custom(format!("Consulta não suportada de {}", src))
+                    crate::p2p::log::Event::DnsUnsupported(src)
                 );
             }
         } else {
+            metrics.dns_malformed();
             crate::p2p::log::log(
                 &log_tx,
                 LogLevel::Warn,
-                crate::p2p::log::Event::Custom(format!("Consulta DNS malformada de {}", src))
+                crate::p2p::log::Event::DnsMalformed(src)
             );
         }
     }
 }
 
 
+/// Picks up to `max` random peers, at most one per AS (or netgroup when the AS
+/// is unknown) until every group had its turn, so one hoster can't fill an answer.
+pub fn pick_diverse(mut peers: Vec<PeerInfo>, max: usize) -> Vec<SocketAddr> {
+    peers.shuffle(&mut rand::rng());
+    let mut groups = HashSet::new();
+    let (mut picked, rest): (Vec<_>, Vec<_>) = peers
+        .into_iter()
+        .partition(|p| groups.insert(diversity_group(p.asn, p.address.ip())));
+    picked.truncate(max);
+    picked.extend(rest.into_iter().take(max - picked.len()));
+    picked.into_iter().map(|p| p.address).collect()
+}
 
-fn parse_dns_query(req: &[u8]) -> Option<(u16, bool, String)> {
+/// Transaction id, QTYPE, QCLASS and name of the first question.
+fn parse_dns_query(req: &[u8]) -> Option<(u16, u16, u16, String)> {
     if req.len() < 12 {
         return None;
     }
@@ -94,9 +114,7 @@ fn parse_dns_query(req: &[u8]) -> Option<(u16, bool, String)> {
     let qtype = u16::from_be_bytes([req[idx], req[idx+1]]);
     let qclass = u16::from_be_bytes([req[idx+2], req[idx+3]]);
     let qname = labels.join(".");
-
-    let is_a_query = qtype == 1 && qclass == 1;
-    Some((txid, is_a_query, qname))
+    Some((txid, qtype, qclass, qname))
 }
 
 fn build_dns_response(req: &[u8], txid: u16, _qname: &str, peers: &[SocketAddr]) -> Vec<u8> {
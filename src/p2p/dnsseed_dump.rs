@@ -0,0 +1,88 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::p2p::atomic_file::write_atomic;
+use crate::p2p::database::{PeerInfo, PeerStatus};
+use crate::p2p::log::{log, log_as, Component, Event, LogLevel, LogMessage};
+use crate::p2p::peer_store::SharedPeerStore;
+
+const HEADER: &str = "# address                                        good  lastSuccess    %(2h)   %(8h)   %(1d)   %(7d)  %(30d)  blocks      svcs  version\n";
+
+/// One line of `dnsseed.dump`, laid out exactly like sipa's bitcoin-seeder.
+fn dump_line(peer: &PeerInfo) -> String {
+    let mut line = format!(
+        "{:<47}  {:>4}  {:>11}",
+        peer.address.to_string(),
+        (peer.status == PeerStatus::ConnectedRecently) as u8,
+        peer.last_connected.unwrap_or(0),
+    );
+    for (i, stat) in peer.uptime.windows().iter().enumerate() {
+        let sep = if i == 0 { "  " } else { " " };
+        let _ = write!(line, "{}{:>6.2}%", sep, 100.0 * stat.reliability);
+    }
+    let _ = writeln!(
+        line,
+        "  {:>6}  {:08x}  {:>5} \"{}\"",
+        peer.start_height.unwrap_or(0),
+        peer.services.unwrap_or(0),
+        peer.protocol_version.unwrap_or(0),
+        peer.user_agent.as_deref().unwrap_or(""),
+    );
+    line
+}
+
+/// Renders the whole dump, most reliable peers (over 30 days) first.
+pub fn format_dump(mut peers: Vec<PeerInfo>) -> String {
+    peers.sort_by(|a, b| {
+        let score = |p: &PeerInfo| p.uptime.m1.weight * p.uptime.m1.reliability;
+        score(b).total_cmp(&score(a)).then(a.address.cmp(&b.address))
+    });
+    let mut out = String::from(HEADER);
+    for peer in &peers {
+        out.push_str(&dump_line(peer));
+    }
+    out
+}
+
+/// Writes the current store contents to `path` atomically; returns how many peers were written.
+pub fn write_dump(store: &SharedPeerStore, path: &Path) -> io::Result<usize> {
+    let peers = store.lock().unwrap().all_peers();
+    let count = peers.len();
+    write_atomic(path, format_dump(peers).as_bytes())?;
+    Ok(count)
+}
+
+/// How often the writer checks `stopping` while it waits for the next dump.
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Rewrites the dump every `interval` until `stopping` is set, then writes it
+/// one last time and returns.
+pub fn spawn_dump_writer(
+    store: SharedPeerStore,
+    path: PathBuf,
+    interval: Duration,
+    log_tx: Sender<LogMessage>,
+    stopping: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let next = Instant::now() + interval;
+        while Instant::now() < next && !stopping.load(Ordering::SeqCst) {
+            thread::sleep(STOP_POLL.min(next.saturating_duration_since(Instant::now())));
+        }
+        let last = stopping.load(Ordering::SeqCst);
+        match write_dump(&store, &path) {
+            Ok(count) => log(&log_tx, LogLevel::Debug, Event::DumpWritten(path.display().to_string(), count)),
+            Err(e) => log_as(&log_tx, Component::Db, LogLevel::Error, Event::DumpFailed(
+                path.display().to_string(), e.to_string(),
+            )),
+        }
+        if last {
+            return;
+        }
+    })
+}
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use crate::p2p::atomic_file::write_atomic;
+use crate::p2p::database::PeerInfo;
+
+const HOUR: u64 = 3600;
+/// Hours whose reachable set is still kept to count distinct addresses.
+const OPEN_HOURS: u64 = 2;
+
+/// One connection attempt, as appended to the history log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub time: u64,
+    pub address: SocketAddr,
+    pub success: bool,
+    pub latency_ms: Option<u64>,
+    pub version: Option<u32>,
+    pub height: Option<i32>,
+}
+
+fn field<T: ToString>(v: Option<T>) -> String {
+    v.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+fn parse_field<T: std::str::FromStr>(s: &str) -> Option<T> {
+    if s == "-" { None } else { s.parse().ok() }
+}
+
+impl Observation {
+    /// The outcome of the attempt that just updated `peer`. Latency, version and
+    /// height are only meaningful when we actually talked to it.
+    pub fn from_peer(peer: &PeerInfo, success: bool, time: u64) -> Self {
+        Observation {
+            time,
+            address: peer.address,
+            success,
+            latency_ms: peer.ping_ms.filter(|_| success),
+            version: peer.protocol_version.filter(|_| success),
+            height: peer.start_height.filter(|_| success),
+        }
+    }
+
+    /// Tab separated: time, address, 1/0, latency, version, height (`-` when unknown).
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.time,
+            self.address,
+            self.success as u8,
+            field(self.latency_ms),
+            field(self.version),
+            field(self.height),
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let f: Vec<&str> = line.split('\t').collect();
+        if f.len() != 6 {
+            return None;
+        }
+        Some(Observation {
+            time: f[0].parse().ok()?,
+            address: f[1].parse().ok()?,
+            success: f[2] == "1",
+            latency_ms: parse_field(f[3]),
+            version: parse_field(f[4]),
+            height: parse_field(f[5]),
+        })
+    }
+}
+
+/// Attempts, successes and distinct reachable addresses in one hour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HourRollup {
+    pub attempts: u32,
+    pub successes: u32,
+    pub reachable: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RollupFile {
+    version: u32,
+    hours: BTreeMap<u64, HourRollup>,
+    /// Reachable addresses of the hours still being counted.
+    #[serde(default)]
+    open: HashMap<u64, HashSet<SocketAddr>>,
+    /// Bytes of the log already counted; what comes after is replayed on open.
+    /// Missing in version 1 files.
+    #[serde(default)]
+    log_offset: Option<u64>,
+}
+
+/// Append-only log of every crawl attempt plus hourly rollups.
+///
+/// The log (`history.log`) is the source of truth and is only ever appended
+/// to; the rollups (`history.log.rollup.json`) are rewritten atomically on
+/// `flush`, together with how much of the log they cover. On open, whatever
+/// the log gained after the last flush is replayed, and the rollups are
+/// rebuilt from scratch if they go missing. Per-peer queries read only that
+/// peer's lines, found through an in-memory index of their offsets.
+#[derive(Debug, Default)]
+pub struct HistoryLog {
+    path: Option<PathBuf>,
+    file: Option<File>,
+    rollups: RollupFile,
+    /// Where each peer's lines start in the log.
+    index: HashMap<SocketAddr, Vec<u64>>,
+    /// Bytes in the log, i.e. where the next line goes.
+    log_len: u64,
+    /// Observations of a history without a file (memory backend).
+    memory: Vec<Observation>,
+}
+
+pub type SharedHistory = Arc<Mutex<HistoryLog>>;
+
+fn rollup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".rollup.json");
+    PathBuf::from(name)
+}
+
+pub fn hour_of(time: u64) -> u64 {
+    time - time % HOUR
+}
+
+impl HistoryLog {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let rollups = match fs::read_to_string(rollup_path(path)) {
+            Ok(data) => serde_json::from_str(&data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => RollupFile::default(),
+            Err(e) => return Err(e),
+        };
+        let len = repair_tail(path)?;
+        let mut history = HistoryLog {
+            path: Some(path.to_path_buf()),
+            file: Some(OpenOptions::new().create(true).append(true).open(path)?),
+            rollups,
+            index: HashMap::new(),
+            log_len: len,
+            memory: Vec::new(),
+        };
+        let from = match history.rollups.log_offset {
+            // Versão 1 não diz até onde contou: só dá para confiar nela como está
+            None if !history.rollups.hours.is_empty() => len,
+            Some(offset) if offset <= len => offset,
+            // Log menor do que o já contado (trocado ou truncado): recomeça do zero
+            _ => {
+                history.rollups = RollupFile::default();
+                0
+            }
+        };
+        for (at, obs) in read_log(path, 0)? {
+            history.index.entry(obs.address).or_default().push(at);
+            if at >= from {
+                history.roll_up(&obs);
+            }
+        }
+        Ok(history)
+    }
+
+    fn roll_up(&mut self, obs: &Observation) {
+        let hour = hour_of(obs.time);
+        let rollup = self.rollups.hours.entry(hour).or_default();
+        rollup.attempts += 1;
+        if !obs.success {
+            return;
+        }
+        rollup.successes += 1;
+        let newest = self.rollups.open.keys().max().copied().unwrap_or(hour).max(hour);
+        // Uma hora já fechada não tem mais o conjunto de endereços para contar
+        if hour + OPEN_HOURS * HOUR <= newest {
+            return;
+        }
+        let seen = self.rollups.open.entry(hour).or_default();
+        seen.insert(obs.address);
+        rollup.reachable = seen.len() as u32;
+        self.rollups.open.retain(|h, _| *h + OPEN_HOURS * HOUR > newest);
+    }
+
+    pub fn record(&mut self, obs: Observation) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => {
+                // Uma única escrita por linha, para uma queda não intercalar pedaços
+                let line = format!("{}\n", obs.to_line());
+                file.write_all(line.as_bytes())?;
+                self.index.entry(obs.address).or_default().push(self.log_len);
+                self.log_len += line.len() as u64;
+            }
+            None => self.memory.push(obs.clone()),
+        }
+        self.roll_up(&obs);
+        Ok(())
+    }
+
+    /// Saves the rollups; the log itself is already on disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let (Some(path), Some(file)) = (&self.path, &mut self.file) else { return Ok(()) };
+        file.flush()?;
+        self.rollups.version = 2;
+        self.rollups.log_offset = Some(self.log_len);
+        write_atomic(&rollup_path(path), serde_json::to_string(&self.rollups)?.as_bytes())
+    }
+
+    /// Every observation, oldest first; reads the whole log. Unparseable lines
+    /// are skipped.
+    #[cfg(test)]
+    pub fn observations(&self) -> io::Result<Vec<Observation>> {
+        match &self.path {
+            Some(path) => Ok(read_log(path, 0)?.into_iter().map(|(_, obs)| obs).collect()),
+            None => Ok(self.memory.clone()),
+        }
+    }
+
+    /// The peer's observations, oldest first, read from its indexed lines only.
+    pub fn peer_history(&self, addr: &SocketAddr) -> io::Result<Vec<Observation>> {
+        let Some(path) = &self.path else {
+            return Ok(self.memory.iter().filter(|o| o.address == *addr).cloned().collect());
+        };
+        let Some(offsets) = self.index.get(addr) else { return Ok(Vec::new()) };
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut out = Vec::with_capacity(offsets.len());
+        let mut line = Vec::new();
+        for &at in offsets {
+            reader.seek(SeekFrom::Start(at))?;
+            line.clear();
+            reader.read_until(b'\n', &mut line)?;
+            out.extend(parse_line(&line));
+        }
+        Ok(out)
+    }
+
+    /// When the peer was last reachable before its current run of failures,
+    /// or `None` if it is online (last attempt succeeded) or was never reached.
+    pub fn went_offline(&self, addr: &SocketAddr) -> io::Result<Option<u64>> {
+        let history = self.peer_history(addr)?;
+        if history.last().is_none_or(|o| o.success) {
+            return Ok(None);
+        }
+        Ok(history.iter().rev().find(|o| o.success).map(|o| o.time))
+    }
+
+    /// Hourly rollups for hours in `[from, to]`.
+    pub fn rollups(&self, from: u64, to: u64) -> Vec<(u64, HourRollup)> {
+        if hour_of(from) > to {
+            return Vec::new();
+        }
+        self.rollups.hours.range(hour_of(from)..=to).map(|(h, r)| (*h, *r)).collect()
+    }
+
+    /// Distinct reachable nodes seen in the hour containing `time`.
+    pub fn reachable_at(&self, time: u64) -> Option<u32> {
+        self.rollups.hours.get(&hour_of(time)).map(|r| r.reachable)
+    }
+}
+
+/// A complete log line; a torn one (no newline) is never half-read.
+fn parse_line(line: &[u8]) -> Option<Observation> {
+    if !line.ends_with(b"\n") {
+        return None;
+    }
+    std::str::from_utf8(line).ok().and_then(|l| Observation::from_line(l.trim_end()))
+}
+
+/// Observations in the log from byte `offset` on, with where each line starts.
+fn read_log(path: &Path, offset: u64) -> io::Result<Vec<(u64, Observation)>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut out = Vec::new();
+    let mut line = Vec::new();
+    let mut at = offset;
+    loop {
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        out.extend(parse_line(&line).map(|obs| (at, obs)));
+        at += read as u64;
+        line.clear();
+    }
+    Ok(out)
+}
+
+/// Cuts a torn last line (a write cut short by a crash) off the log, so the next
+/// record starts on a line of its own. Returns the log's length afterwards.
+fn repair_tail(path: &Path) -> io::Result<u64> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let mut end = len;
+    let mut buf = [0u8; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+            end = start + i as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+    if end != len {
+        file.set_len(end)?;
+    }
+    Ok(end)
+}
+
+/// Text bar chart of reachable nodes per hour, one line per hour.
+pub fn plot_reachable(rollups: &[(u64, HourRollup)], width: usize) -> String {
+    let max = rollups.iter().map(|(_, r)| r.reachable).max().unwrap_or(0).max(1);
+    let mut out = String::new();
+    for (hour, r) in rollups {
+        let when = chrono::DateTime::from_timestamp(*hour as i64, 0)
+            .map(|d| d.format("%Y-%m-%d %H:00").to_string())
+            .unwrap_or_else(|| hour.to_string());
+        let bar = "█".repeat((r.reachable as usize * width).div_ceil(max as usize));
+        let _ = writeln!(out, "{} | {:<width$} {} ({} tentativas)", when, bar, r.reachable, r.attempts, width = width);
+    }
+    out
+}
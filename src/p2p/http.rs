@@ -0,0 +1,161 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest request body we accept; RPC calls are tiny.
+const MAX_BODY: usize = 1 << 20;
+
+/// Largest request line plus headers we accept.
+const MAX_HEADER: u64 = 8 * 1024;
+
+/// Most header lines we accept in one request.
+const MAX_HEADERS: usize = 64;
+
+/// Time a client has to send the whole request, however slowly it trickles in.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections served at once; past this, new ones are closed straight away.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Just enough HTTP/1.1 for the metrics and RPC endpoints: one request per connection.
+#[derive(Debug, Clone, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// First header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Reads from the stream until a fixed deadline, instead of a timeout that
+/// restarts with every byte received.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request took too long"));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// Reads one line of the request head, failing if it runs past `MAX_HEADER`.
+fn read_head_line(head: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    line.clear();
+    let n = head.read_line(line)?;
+    if n > 0 && !line.ends_with('\n') {
+        // Sem quebra de linha: ou o limite acabou, ou a conexão fechou no meio
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request header too large or truncated"));
+    }
+    Ok(n)
+}
+
+pub fn read_request(stream: &TcpStream) -> io::Result<HttpRequest> {
+    read_request_within(stream, REQUEST_TIMEOUT)
+}
+
+/// `read_request` with the deadline as a parameter, so tests need not wait seconds.
+pub fn read_request_within(stream: &TcpStream, timeout: Duration) -> io::Result<HttpRequest> {
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline: Instant::now() + timeout });
+    let mut head = (&mut reader).take(MAX_HEADER);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line"));
+    };
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or(target).to_string(),
+        ..Default::default()
+    };
+
+    loop {
+        if read_head_line(&mut head, &mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if request.headers.len() == MAX_HEADERS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many request headers"));
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            request.headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+
+    let len: usize = request.header("Content-Length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    if len > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+    request.body = vec![0; len];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+/// Accepts connections on `listener` and hands each to `handle` on its own
+/// thread, so a slow or stalled client doesn't hold up the others.
+pub fn serve<F>(listener: TcpListener, handle: F)
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    let handle = Arc::new(handle);
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming().flatten() {
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+        let (handle, active) = (handle.clone(), active.clone());
+        thread::spawn(move || {
+            handle(stream);
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Writes a complete response and closes the exchange (`Connection: close`).
+pub fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write_response_with(stream, status, &[], content_type, body)
+}
+
+/// `write_response` with extra headers, e.g. `WWW-Authenticate` on a 401.
+pub fn write_response_with(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    write!(
+        stream,
+        "{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        head,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -1,7 +1,16 @@
 use std::net::SocketAddr;
 use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use crate::p2p::atomic_file::backup_path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
@@ -13,6 +22,27 @@ pub enum LogLevel {
 }
 
 impl LogLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    /// Fixed-width tag used in text log lines.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "[TRACE]",
+            LogLevel::Debug => "[DEBUG]",
+            LogLevel::Info  => "[INFO ]",
+            LogLevel::Warn  => "[WARN ]",
+            LogLevel::Error => "[ERROR]",
+        }
+    }
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "trace" => Some(LogLevel::Trace),
@@ -25,13 +55,359 @@ impl LogLevel {
     }
 }
 
+/// Part of the seeder a log line comes from; `--verbosity` can set a level per component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Component {
+    Client,
+    Crawler,
+    Db,
+    Dns,
+    Cli,
+    Rpc,
+    Metrics,
+}
+
+impl Component {
+    pub const ALL: [Component; 7] = [
+        Component::Client,
+        Component::Crawler,
+        Component::Db,
+        Component::Dns,
+        Component::Cli,
+        Component::Rpc,
+        Component::Metrics,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::Client => "client",
+            Component::Crawler => "crawler",
+            Component::Db => "db",
+            Component::Dns => "dns",
+            Component::Cli => "cli",
+            Component::Rpc => "rpc",
+            Component::Metrics => "metrics",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Component::ALL.into_iter().find(|c| c.name() == s.to_lowercase())
+    }
+}
+
+/// Minimum level per component, e.g. `info,dns=trace,crawler=warn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LogLevel,
+    overrides: BTreeMap<Component, LogLevel>,
+}
+
+pub type SharedLogFilter = Arc<Mutex<LogFilter>>;
+
+impl LogFilter {
+    pub fn new(default: LogLevel) -> Self {
+        LogFilter { default, overrides: BTreeMap::new() }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = LogFilter::new(LogLevel::Info);
+        filter.apply(spec)?;
+        Ok(filter)
+    }
+
+    /// Applies comma-separated directives on top of the current filter: a bare
+    /// level sets the default, `component=level` one component. Nothing changes
+    /// if any directive is invalid.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        let mut next = self.clone();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let level_of = |s: &str| LogLevel::from_str(s).ok_or_else(|| format!("invalid log level: {}", s));
+            match directive.split_once('=') {
+                None => next.default = level_of(directive)?,
+                Some((component, level)) => {
+                    let component = Component::from_str(component.trim())
+                        .ok_or_else(|| format!("unknown component: {}", component))?;
+                    next.overrides.insert(component, level_of(level.trim())?);
+                }
+            }
+        }
+        *self = next;
+        Ok(())
+    }
+
+    pub fn enabled(&self, component: Component, level: LogLevel) -> bool {
+        level >= *self.overrides.get(&component).unwrap_or(&self.default)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.name())?;
+        for (component, level) in &self.overrides {
+            write!(f, ",{}={}", component.name(), level.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// How log lines are rendered, on the terminal and in the log file alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// `[INFO ] message`
+    Text,
+    /// One JSON object per line with typed fields
+    Json,
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
     Connected(SocketAddr),
     FailedConnection(SocketAddr, String),
-    _PeerDiscovered(SocketAddr),
-    _SavedToDisk(usize),
-    Custom(String),
+    /// Address learned from an `addr` message.
+    PeerDiscovered(SocketAddr),
+    /// Host name being looked up.
+    Resolving(String),
+    /// Host name and the addresses it resolved to.
+    Resolved(String, Vec<SocketAddr>),
+    /// Host name none of whose addresses accepted a connection.
+    NoReachableAddress(String),
+    Connecting(SocketAddr),
+    /// Banned peer we did not connect to.
+    SkippedBanned(SocketAddr),
+    /// Peer, command and payload size of a message we sent.
+    MessageSent(SocketAddr, String, usize),
+    /// Peer, command and payload size of a message we got.
+    MessageReceived(SocketAddr, String, usize),
+    /// Peer, command and payload size of a message we don't handle.
+    UnknownMessage(SocketAddr, String, usize),
+    HandshakeComplete(SocketAddr),
+    /// Peer and round trip of our ping, in milliseconds.
+    PingMeasured(SocketAddr, u64),
+    /// Peer and number of entries in its `inv`.
+    InvReceived(SocketAddr, usize),
+    /// Peer, inventory type, hash, and whether we asked for it.
+    InvItem(SocketAddr, String, String, bool),
+    /// Peer and how many items we asked for with `getdata`.
+    GetDataSent(SocketAddr, usize),
+    /// Why the interactive client's message loop ended.
+    ClientStopped(String),
+    CrawlStarted(SocketAddr),
+    /// Peers the crawl started with, and whether it was stopped early.
+    CrawlFinished(usize, bool),
+    /// Peer whose connection ended after the handshake, and why.
+    ConnectionClosed(SocketAddr, String),
+    /// Peer, protocol version, user agent and start height from its `version`.
+    PeerVersion(SocketAddr, u32, String, i32),
+    /// Peer and how many addresses it sent us.
+    AddrReceived(SocketAddr, usize),
+    SavedToDisk(usize, usize, Duration),
+    /// What failed to be written (database, history...) and the error.
+    StorageError(String, String),
+    /// Peer, violation and its misbehavior score after it.
+    Misbehavior(SocketAddr, String, u32),
+    AutoBanned(SocketAddr, String),
+    /// File imported from Bitcoin Core, addresses stored and addresses left out.
+    CoreImported(String, usize, usize),
+    /// dnsseed.dump path and number of peers written.
+    DumpWritten(String, usize),
+    /// Addrman tables rebuilt at startup: new, tried, and stored peers that did not fit.
+    AddrManLoaded(usize, usize, usize),
+    /// asmap path, peers mapped to an AS and distinct ASes among them.
+    AsmapLoaded(String, usize, usize),
+    /// Peers pruned for failed attempts, age and netgroup limits, and how many remain.
+    Pruned(usize, usize, usize, usize),
+    /// Address the DNS server is bound to.
+    DnsListening(String),
+    /// Client and how many peers went into the answer.
+    DnsAnswered(SocketAddr, usize),
+    /// Client that asked for something other than our A record.
+    DnsUnsupported(SocketAddr),
+    DnsMalformed(SocketAddr),
+    /// Address the Prometheus endpoint is bound to.
+    MetricsListening(SocketAddr),
+    /// Address the JSON-RPC server is bound to.
+    RpcListening(SocketAddr),
+    /// Method of an RPC call.
+    RpcCall(String),
+    /// Address `addnode` put on the crawl queue.
+    NodeQueued(SocketAddr),
+    /// RPC client turned away and the HTTP status it got (401 or 403).
+    RpcDenied(SocketAddr, u16),
+    /// Why the seeder is exiting.
+    ShuttingDown(String),
+    /// Core file that failed to import and the error.
+    CoreImportFailed(String, String),
+    /// Error that stopped the database prune.
+    PruneFailed(String),
+    /// Database file that failed to load and why; the next backup is tried.
+    DbCorrupt(String, String),
+    /// Backup the database was restored from.
+    DbRestored(String),
+    /// Database path that did not exist yet.
+    DbMissing(String),
+    /// dnsseed.dump path and the error writing it.
+    DumpFailed(String, String),
+    /// Peer that crossed the misbehavior threshold and why the ban failed.
+    BanFailed(SocketAddr, String),
+    /// Panic payload of the crawler task.
+    CrawlerPanicked(String),
+    /// Why the tokio runtime could not be created.
+    RuntimeFailed(String),
+    /// stdin closed while the RPC server is up; the seeder waits for `stop`.
+    StdinClosed,
+    ClientStarting,
+    /// Step of the interactive client's startup that failed (connect or handshake) and the error.
+    ClientStartFailed(String, String),
+    /// Subnet banned from the console, seconds (None for permanent) and reason.
+    Banned(String, Option<u64>, String),
+    Unbanned(String),
+}
+
+impl Event {
+    /// Stable snake_case name of the variant, the `event` key in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Connected(_) => "connected",
+            Event::FailedConnection(..) => "failed_connection",
+            Event::PeerDiscovered(_) => "peer_discovered",
+            Event::Resolving(_) => "resolving",
+            Event::Resolved(..) => "resolved",
+            Event::NoReachableAddress(_) => "no_reachable_address",
+            Event::Connecting(_) => "connecting",
+            Event::SkippedBanned(_) => "skipped_banned",
+            Event::MessageSent(..) => "message_sent",
+            Event::MessageReceived(..) => "message_received",
+            Event::UnknownMessage(..) => "unknown_message",
+            Event::HandshakeComplete(_) => "handshake_complete",
+            Event::PingMeasured(..) => "ping_measured",
+            Event::InvReceived(..) => "inv_received",
+            Event::InvItem(..) => "inv_item",
+            Event::GetDataSent(..) => "getdata_sent",
+            Event::ClientStopped(_) => "client_stopped",
+            Event::CrawlStarted(_) => "crawl_started",
+            Event::CrawlFinished(..) => "crawl_finished",
+            Event::ConnectionClosed(..) => "connection_closed",
+            Event::PeerVersion(..) => "peer_version",
+            Event::AddrReceived(..) => "addr_received",
+            Event::SavedToDisk(..) => "saved_to_disk",
+            Event::StorageError(..) => "storage_error",
+            Event::Misbehavior(..) => "misbehavior",
+            Event::AutoBanned(..) => "auto_banned",
+            Event::CoreImported(..) => "core_imported",
+            Event::DumpWritten(..) => "dump_written",
+            Event::AddrManLoaded(..) => "addrman_loaded",
+            Event::AsmapLoaded(..) => "asmap_loaded",
+            Event::Pruned(..) => "pruned",
+            Event::DnsListening(_) => "dns_listening",
+            Event::MetricsListening(_) => "metrics_listening",
+            Event::RpcListening(_) => "rpc_listening",
+            Event::RpcCall(_) => "rpc_call",
+            Event::NodeQueued(_) => "node_queued",
+            Event::RpcDenied(..) => "rpc_denied",
+            Event::ShuttingDown(_) => "shutting_down",
+            Event::DnsAnswered(..) => "dns_answered",
+            Event::DnsUnsupported(_) => "dns_unsupported",
+            Event::DnsMalformed(_) => "dns_malformed",
+            Event::CoreImportFailed(..) => "core_import_failed",
+            Event::PruneFailed(_) => "prune_failed",
+            Event::DbCorrupt(..) => "db_corrupt",
+            Event::DbRestored(_) => "db_restored",
+            Event::DbMissing(_) => "db_missing",
+            Event::DumpFailed(..) => "dump_failed",
+            Event::BanFailed(..) => "ban_failed",
+            Event::CrawlerPanicked(_) => "crawler_panicked",
+            Event::RuntimeFailed(_) => "runtime_failed",
+            Event::StdinClosed => "stdin_closed",
+            Event::ClientStarting => "client_starting",
+            Event::ClientStartFailed(..) => "client_start_failed",
+            Event::Banned(..) => "banned",
+            Event::Unbanned(_) => "unbanned",
+        }
+    }
+
+    /// Component the event belongs to unless the caller says otherwise (see `log_as`).
+    pub fn component(&self) -> Component {
+        match self {
+            Event::Resolving(_) | Event::Resolved(..) | Event::NoReachableAddress(_) | Event::MessageSent(..)
+            | Event::MessageReceived(..) | Event::UnknownMessage(..) | Event::HandshakeComplete(_)
+            | Event::PingMeasured(..) | Event::InvReceived(..) | Event::InvItem(..) | Event::GetDataSent(..)
+            | Event::ClientStopped(_) | Event::ClientStarting | Event::ClientStartFailed(..) => Component::Client,
+            Event::DnsListening(_) | Event::DnsAnswered(..) | Event::DnsUnsupported(_) | Event::DnsMalformed(_) => {
+                Component::Dns
+            }
+            Event::SavedToDisk(..) | Event::StorageError(..) | Event::CoreImported(..) | Event::DumpWritten(..)
+            | Event::AddrManLoaded(..) | Event::AsmapLoaded(..) | Event::Pruned(..) | Event::CoreImportFailed(..)
+            | Event::PruneFailed(_) | Event::DbCorrupt(..) | Event::DbRestored(_) | Event::DbMissing(_)
+            | Event::DumpFailed(..) | Event::BanFailed(..) => Component::Db,
+            Event::MetricsListening(_) => Component::Metrics,
+            Event::RpcListening(_) | Event::RpcCall(_) | Event::NodeQueued(_) | Event::RpcDenied(..)
+            | Event::ShuttingDown(_) => Component::Rpc,
+            Event::StdinClosed | Event::Banned(..) | Event::Unbanned(_) => Component::Cli,
+            _ => Component::Crawler,
+        }
+    }
+
+    /// The variant's data under named keys.
+    pub fn fields(&self) -> Value {
+        match self {
+            Event::Connected(peer)
+            | Event::PeerDiscovered(peer)
+            | Event::HandshakeComplete(peer)
+            | Event::Connecting(peer)
+            | Event::SkippedBanned(peer)
+            | Event::CrawlStarted(peer) => json!({ "peer": peer }),
+            Event::FailedConnection(peer, reason) | Event::ConnectionClosed(peer, reason) => {
+                json!({ "peer": peer, "reason": reason })
+            }
+            Event::Resolving(host) | Event::NoReachableAddress(host) => json!({ "host": host }),
+            Event::Resolved(host, addrs) => json!({ "host": host, "addresses": addrs }),
+            Event::MessageSent(peer, command, bytes)
+            | Event::MessageReceived(peer, command, bytes)
+            | Event::UnknownMessage(peer, command, bytes) => json!({ "peer": peer, "command": command, "bytes": bytes }),
+            Event::PingMeasured(peer, ms) => json!({ "peer": peer, "ping_ms": ms }),
+            Event::InvReceived(peer, count) | Event::GetDataSent(peer, count) => json!({ "peer": peer, "count": count }),
+            Event::InvItem(peer, kind, hash, requested) => json!({
+                "peer": peer, "type": kind, "hash": hash, "requested": requested,
+            }),
+            Event::ClientStopped(reason) => json!({ "reason": reason }),
+            Event::CrawlFinished(peers, stopped) => json!({ "peers": peers, "stopped": stopped }),
+            Event::PeerVersion(peer, version, user_agent, height) => json!({
+                "peer": peer, "version": version, "user_agent": user_agent, "start_height": height,
+            }),
+            Event::AddrReceived(peer, count) => json!({ "peer": peer, "count": count }),
+            Event::SavedToDisk(changes, peers, elapsed) => json!({
+                "changes": changes, "peers": peers, "elapsed_ms": elapsed.as_millis() as u64,
+            }),
+            Event::StorageError(what, error) => json!({ "target": what, "error": error }),
+            Event::Misbehavior(peer, what, score) => json!({ "peer": peer, "violation": what, "score": score }),
+            Event::AutoBanned(peer, reason) => json!({ "peer": peer, "reason": reason }),
+            Event::CoreImported(file, imported, skipped) => json!({ "file": file, "imported": imported, "skipped": skipped }),
+            Event::DumpWritten(file, peers) => json!({ "file": file, "peers": peers }),
+            Event::AddrManLoaded(new, tried, unindexed) => json!({ "new": new, "tried": tried, "unindexed": unindexed }),
+            Event::AsmapLoaded(file, mapped, asns) => json!({ "file": file, "mapped": mapped, "asns": asns }),
+            Event::Pruned(failed, stale, netgroup, remaining) => json!({
+                "failed": failed, "stale": stale, "over_netgroup": netgroup, "remaining": remaining,
+            }),
+            Event::DnsListening(addr) => json!({ "address": addr }),
+            Event::MetricsListening(addr) | Event::RpcListening(addr) => json!({ "address": addr }),
+            Event::RpcCall(method) => json!({ "method": method }),
+            Event::NodeQueued(peer) => json!({ "peer": peer }),
+            Event::RpcDenied(client, status) => json!({ "client": client, "status": status }),
+            Event::ShuttingDown(reason) => json!({ "reason": reason }),
+            Event::DnsAnswered(client, peers) => json!({ "client": client, "peers": peers }),
+            Event::DnsUnsupported(client) | Event::DnsMalformed(client) => json!({ "client": client }),
+            Event::CoreImportFailed(file, error) | Event::DumpFailed(file, error) => json!({ "file": file, "error": error }),
+            Event::PruneFailed(error) | Event::RuntimeFailed(error) => json!({ "error": error }),
+            Event::DbCorrupt(file, reason) => json!({ "file": file, "reason": reason }),
+            Event::DbRestored(file) | Event::DbMissing(file) => json!({ "file": file }),
+            Event::BanFailed(peer, error) => json!({ "peer": peer, "error": error }),
+            Event::CrawlerPanicked(panic) => json!({ "panic": panic }),
+            Event::StdinClosed | Event::ClientStarting => json!({}),
+            Event::ClientStartFailed(stage, error) => json!({ "stage": stage, "error": error }),
+            Event::Banned(subnet, secs, reason) => json!({ "subnet": subnet, "duration_secs": secs, "reason": reason }),
+            Event::Unbanned(subnet) => json!({ "subnet": subnet }),
+        }
+    }
 }
 
 impl fmt::Display for Event {
@@ -39,9 +415,81 @@ impl fmt::Display for Event {
         match self {
             Event::Connected(addr) => write!(f, "Conectado ao peer {}", addr),
             Event::FailedConnection(addr, reason) => write!(f, "Falha ao conectar em {}: {}", addr, reason),
-            Event::_PeerDiscovered(addr) => write!(f, "Novo peer descoberto: {}", addr),
-            Event::_SavedToDisk(count) => write!(f, "Banco de dados salvo ({} peers)", count),
-            Event::Custom(msg) => write!(f, "{}", msg),
+            Event::PeerDiscovered(addr) => write!(f, "Novo peer descoberto: {}", addr),
+            Event::Resolving(host) => write!(f, "Resolvendo {}", host),
+            Event::Resolved(host, addrs) => write!(f, "{} resolvido para {:?}", host, addrs),
+            Event::NoReachableAddress(host) => write!(f, "Não foi possível conectar a nenhum endereço de {}", host),
+            Event::Connecting(addr) => write!(f, "Tentando conectar em {}", addr),
+            Event::SkippedBanned(addr) => write!(f, "Ignorando {} (banido)", addr),
+            Event::MessageSent(addr, command, bytes) => write!(f, "Enviado {} para {} ({} bytes)", command, addr, bytes),
+            Event::MessageReceived(addr, command, bytes) => write!(f, "Recebido {} de {} ({} bytes)", command, addr, bytes),
+            Event::UnknownMessage(addr, command, bytes) => {
+                write!(f, "Comando desconhecido {} de {} ({} bytes)", command, addr, bytes)
+            }
+            Event::HandshakeComplete(addr) => write!(f, "Handshake com {} completo", addr),
+            Event::PingMeasured(addr, ms) => write!(f, "Ping para {}: {} ms", addr, ms),
+            Event::InvReceived(addr, 0) => write!(f, "{} enviou um inv vazio", addr),
+            Event::InvReceived(addr, count) => write!(f, "{} anunciou {} itens de inventário", addr, count),
+            Event::InvItem(_, kind, hash, requested) => {
+                write!(f, "{} {}{}", kind, hash, if *requested { "" } else { " (ignorado)" })
+            }
+            Event::GetDataSent(_, 0) => write!(f, "Nenhum item novo para pedir"),
+            Event::GetDataSent(addr, count) => write!(f, "Pedindo {} itens para {} via getdata", count, addr),
+            Event::ClientStopped(reason) => write!(f, "Cliente parado: {}", reason),
+            Event::CrawlStarted(addr) => write!(f, "Iniciando crawl em {}", addr),
+            Event::CrawlFinished(peers, false) => write!(f, "Crawl finalizado ({} peers)", peers),
+            Event::CrawlFinished(peers, true) => write!(f, "Crawl interrompido ({} peers)", peers),
+            Event::ConnectionClosed(addr, reason) => write!(f, "Conexão com {} encerrada após handshake: {}", addr, reason),
+            Event::PeerVersion(addr, version, user_agent, height) => {
+                write!(f, "{} versão {} {} altura {}", addr, version, user_agent, height)
+            }
+            Event::AddrReceived(addr, count) => write!(f, "{} enviou {} endereços", addr, count),
+            Event::SavedToDisk(changes, count, elapsed) => write!(
+                f, "Banco de dados salvo ({} alterações, {} peers em {:?})", changes, count, elapsed
+            ),
+            Event::StorageError(what, error) => write!(f, "Falha ao salvar {}: {}", what, error),
+            Event::Misbehavior(addr, what, score) => write!(f, "Peer {} violou o protocolo: {} (pontuação {})", addr, what, score),
+            Event::AutoBanned(addr, reason) => write!(f, "Peer {} banido automaticamente: {}", addr, reason),
+            Event::CoreImported(file, imported, skipped) => write!(
+                f, "Importados {} peers de {} ({} ignorados)", imported, file, skipped
+            ),
+            Event::DumpWritten(file, count) => write!(f, "{} atualizado ({} peers)", file, count),
+            Event::AddrManLoaded(new, tried, unindexed) => write!(
+                f, "Addrman: {} na tabela new, {} na tried ({} sem vaga, mantidos no banco)", new, tried, unindexed
+            ),
+            Event::AsmapLoaded(file, mapped, asns) => write!(
+                f, "asmap {} carregado: {} peers em {} sistemas autônomos", file, mapped, asns
+            ),
+            Event::Pruned(failed, stale, netgroup, remaining) => write!(
+                f, "Limpeza do banco: {} sem sucesso, {} antigos, {} acima do limite por rede ({} restantes)",
+                failed, stale, netgroup, remaining
+            ),
+            Event::DnsListening(addr) => write!(f, "Servidor DNS escutando em {}", addr),
+            Event::MetricsListening(addr) => write!(f, "Métricas disponíveis em http://{}/metrics", addr),
+            Event::RpcListening(addr) => write!(f, "Servidor RPC escutando em http://{}/", addr),
+            Event::RpcCall(method) => write!(f, "Chamada RPC: {}", method),
+            Event::NodeQueued(addr) => write!(f, "{} adicionado à fila de crawl", addr),
+            Event::RpcDenied(client, 403) => write!(f, "Chamada RPC recusada de {}: endereço não permitido", client),
+            Event::RpcDenied(client, _) => write!(f, "Chamada RPC recusada de {}: credenciais inválidas", client),
+            Event::ShuttingDown(reason) => write!(f, "Encerrando: {}", reason),
+            Event::DnsAnswered(client, count) => write!(f, "Respondido para {} com {} peers", client, count),
+            Event::DnsUnsupported(client) => write!(f, "Consulta não suportada de {}", client),
+            Event::DnsMalformed(client) => write!(f, "Consulta DNS malformada de {}", client),
+            Event::CoreImportFailed(file, error) => write!(f, "Falha ao importar {}: {}", file, error),
+            Event::PruneFailed(error) => write!(f, "Falha ao limpar banco de dados: {}", error),
+            Event::DbCorrupt(file, reason) => write!(f, "Banco de dados corrompido {}: {}", file, reason),
+            Event::DbRestored(file) => write!(f, "Banco de dados restaurado do backup {}", file),
+            Event::DbMissing(file) => write!(f, "Nenhum banco em {}, começando vazio", file),
+            Event::DumpFailed(file, error) => write!(f, "Falha ao escrever {}: {}", file, error),
+            Event::BanFailed(addr, error) => write!(f, "Falha ao banir {}: {}", addr, error),
+            Event::CrawlerPanicked(panic) => write!(f, "Erro ao executar crawler: {}", panic),
+            Event::RuntimeFailed(error) => write!(f, "Erro ao criar runtime tokio: {}", error),
+            Event::StdinClosed => write!(f, "stdin fechado; aguardando stop via RPC"),
+            Event::ClientStarting => write!(f, "Iniciando cliente Bitcoin P2P"),
+            Event::ClientStartFailed(stage, error) => write!(f, "Erro no {}: {}", stage, error),
+            Event::Banned(subnet, Some(secs), reason) => write!(f, "{} banido por {}s ({})", subnet, secs, reason),
+            Event::Banned(subnet, None, reason) => write!(f, "{} banido permanentemente ({})", subnet, reason),
+            Event::Unbanned(subnet) => write!(f, "Banimento de {} removido", subnet),
         }
     }
 }
@@ -49,39 +497,275 @@ impl fmt::Display for Event {
 #[derive(Debug, Clone)]
 pub struct LogMessage {
     pub level: LogLevel,
+    pub component: Component,
     pub event: Event,
 }
 
 impl LogMessage {
     pub fn new(level: LogLevel, event: Event) -> Self {
-        LogMessage { level, event }
+        LogMessage { level, component: event.component(), event }
+    }
+
+    /// The line as written in `--log-format json`; `message` carries the text rendering.
+    pub fn to_json(&self, timestamp: &str) -> Value {
+        let mut obj = json!({
+            "timestamp": timestamp,
+            "level": self.level.name(),
+            "component": self.component.name(),
+            "event": self.event.kind(),
+            "message": self.event.to_string(),
+        });
+        if let (Some(obj), Value::Object(fields)) = (obj.as_object_mut(), self.event.fields()) {
+            obj.extend(fields);
+        }
+        obj
+    }
+
+    /// The line as written in `--log-format text`, after the timestamp in files.
+    pub fn to_text(&self) -> String {
+        format!("{} {}: {}", self.level.prefix(), self.component.name(), self.event)
     }
 }
 
-pub struct Logger {
+/// A log line as kept in memory for the `logs` command.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    /// Grows by one per record, so a follower can ask for what it hasn't seen yet.
+    pub seq: u64,
+    pub timestamp: String,
+    pub level: LogLevel,
+    pub component: Component,
+    pub message: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}: {}", self.timestamp, self.level.prefix(), self.component.name(), self.message)
+    }
+}
+
+/// Which buffered lines to show: `logs [level] [component] [n]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogQuery {
+    pub level: LogLevel,
+    pub component: Option<Component>,
+    pub limit: usize,
+}
+
+impl Default for LogQuery {
+    fn default() -> Self {
+        LogQuery { level: LogLevel::Trace, component: None, limit: 50 }
+    }
+}
+
+impl LogQuery {
+    /// Arguments may come in any order; anything unrecognised is an error.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let mut query = LogQuery::default();
+        for arg in args {
+            if let Some(level) = LogLevel::from_str(arg) {
+                query.level = level;
+            } else if let Some(component) = Component::from_str(arg) {
+                query.component = Some(component);
+            } else {
+                query.limit = arg.parse().ok()?;
+            }
+        }
+        Some(query)
+    }
+
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        record.level >= self.level && self.component.is_none_or(|c| c == record.component)
+    }
+}
+
+/// The last lines the logger let through, oldest first.
+#[derive(Debug)]
+pub struct LogBuffer {
+    capacity: usize,
     min_level: LogLevel,
+    records: VecDeque<LogRecord>,
+    next_seq: u64,
+}
+
+pub type SharedLogBuffer = Arc<Mutex<LogBuffer>>;
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        LogBuffer { capacity, min_level: LogLevel::Trace, records: VecDeque::with_capacity(capacity), next_seq: 0 }
+    }
+
+    /// Drops messages below `level`, independently of `--verbosity`.
+    pub fn with_min_level(mut self, level: LogLevel) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    pub fn push(&mut self, timestamp: &str, msg: &LogMessage) {
+        if self.capacity == 0 || msg.level < self.min_level {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(LogRecord {
+            seq: self.next_seq,
+            timestamp: timestamp.to_string(),
+            level: msg.level,
+            component: msg.component,
+            message: msg.event.to_string(),
+        });
+        self.next_seq += 1;
+    }
+
+    /// Sequence number the next pushed record will get.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// The newest `query.limit` matching records, oldest first.
+    pub fn query(&self, query: &LogQuery) -> Vec<LogRecord> {
+        let mut found: Vec<_> = self.records.iter().rev().filter(|r| query.matches(r)).take(query.limit).cloned().collect();
+        found.reverse();
+        found
+    }
+
+    /// Matching records pushed at or after `seq` (ignores `query.limit`).
+    pub fn since(&self, seq: u64, query: &LogQuery) -> Vec<LogRecord> {
+        self.records.iter().filter(|r| r.seq >= seq && query.matches(r)).cloned().collect()
+    }
+}
+
+/// Where and how long log files are kept.
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    /// Rotate once the file would grow past this many bytes (0 = never).
+    pub max_size: u64,
+    /// Rotate once the file has been written to for this long (zero = never).
+    pub max_age: Duration,
+    /// Rotated files kept as `<path>.1` (newest) .. `<path>.<keep>` (oldest).
+    pub keep: usize,
+}
+
+/// How long a failed rotation waits before being tried again.
+const ROTATE_RETRY: Duration = Duration::from_secs(60);
+/// Least time between two reports of log file errors.
+const FILE_ERROR_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Append-only log file that rotates itself by size and age.
+pub struct RotatingFile {
+    config: LogFileConfig,
+    file: File,
+    size: u64,
+    opened: SystemTime,
+    /// After a failed rotation, when to try again; until then lines keep going to the current file.
+    retry_rotate: Option<Instant>,
+}
+
+impl RotatingFile {
+    /// Appends to an existing file; its age counts from when it was created.
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+        let meta = file.metadata()?;
+        let opened = meta.created().unwrap_or_else(|_| SystemTime::now());
+        Ok(RotatingFile { config, file, size: meta.len(), opened, retry_rotate: None })
+    }
+
+    fn due(&self, len: u64) -> bool {
+        let too_big = self.config.max_size > 0 && self.size > 0 && self.size + len > self.config.max_size;
+        let too_old = !self.config.max_age.is_zero()
+            && self.opened.elapsed().is_ok_and(|age| age >= self.config.max_age);
+        too_big || (too_old && self.size > 0)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.config.path;
+        for n in (1..self.config.keep).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        if self.config.keep > 0 {
+            fs::rename(path, backup_path(path, 1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        self.size = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+
+    /// Appends `line`. A failed rotation is returned as the error, but the line
+    /// is still written to the current file and rotation waits `ROTATE_RETRY`.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        let mut rotated = Ok(());
+        if self.due(len) && self.retry_rotate.is_none_or(|at| Instant::now() >= at) {
+            rotated = self.rotate();
+            self.retry_rotate = rotated.is_err().then(|| Instant::now() + ROTATE_RETRY);
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        rotated
+    }
+}
+
+pub struct Logger {
+    filter: SharedLogFilter,
     rx: Receiver<LogMessage>,
+    format: LogFormat,
+    stdout: bool,
+    file: Option<RotatingFile>,
+    buffer: SharedLogBuffer,
+    /// When a log file error was last printed, so a broken file doesn't flood stderr.
+    file_error_reported: Option<Instant>,
 }
 
 impl Logger {
-    pub fn spawn(min_level: LogLevel, rx: Receiver<LogMessage>) -> thread::JoinHandle<()> {
+    pub fn spawn(
+        filter: SharedLogFilter,
+        rx: Receiver<LogMessage>,
+        format: LogFormat,
+        stdout: bool,
+        file: Option<RotatingFile>,
+        buffer: SharedLogBuffer,
+    ) -> thread::JoinHandle<()> {
         thread::spawn(move || {
-            let logger = Logger { min_level, rx };
+            let logger = Logger { filter, rx, format, stdout, file, buffer, file_error_reported: None };
             logger.run();
         })
     }
 
-    fn run(self) {
+    fn run(mut self) {
         while let Ok(msg) = self.rx.recv() {
-            if msg.level >= self.min_level {
-                let prefix = match msg.level {
-                    LogLevel::Trace => "[TRACE]",
-                    LogLevel::Debug => "[DEBUG]",
-                    LogLevel::Info  => "[INFO ]",
-                    LogLevel::Warn  => "[WARN ]",
-                    LogLevel::Error => "[ERROR]",
-                };
-                println!("{} {}", prefix, msg.event);
+            let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            // O buffer tem seu próprio nível: `logs trace` funciona mesmo com --verbosity info
+            self.buffer.lock().unwrap().push(&now, &msg);
+            if !self.filter.lock().unwrap().enabled(msg.component, msg.level) {
+                continue;
+            }
+            let (line, file_line) = match self.format {
+                LogFormat::Json => {
+                    let json = msg.to_json(&now).to_string();
+                    (json.clone(), json)
+                }
+                LogFormat::Text => {
+                    let line = msg.to_text();
+                    let file_line = format!("{} {}", now, line);
+                    (line, file_line)
+                }
+            };
+            if self.stdout {
+                println!("{}", line);
+            }
+            if let Some(file) = &mut self.file {
+                if let Err(e) = file.write_line(&file_line) {
+                    if self.file_error_reported.is_none_or(|at| at.elapsed() >= FILE_ERROR_REPORT_INTERVAL) {
+                        eprintln!("Falha ao escrever no arquivo de log: {} (novos avisos só daqui a um minuto)", e);
+                        self.file_error_reported = Some(Instant::now());
+                    }
+                }
             }
         }
     }
@@ -89,4 +773,10 @@ impl Logger {
 
 pub fn log(sender: &Sender<LogMessage>, level: LogLevel, event: Event) {
     let _ = sender.send(LogMessage::new(level, event));
+}
+
+/// `log` for events whose default component is wrong here, e.g. a
+/// `Connected` from the interactive client rather than the crawler.
+pub fn log_as(sender: &Sender<LogMessage>, component: Component, level: LogLevel, event: Event) {
+    let _ = sender.send(LogMessage { component, ..LogMessage::new(level, event) });
 }
\ No newline at end of file
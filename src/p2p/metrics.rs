@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::p2p::database::{PeerInfo, PeerStatus};
+use crate::p2p::http::{read_request, serve, write_response};
+use crate::p2p::misbehavior::Misbehavior;
+use crate::p2p::peer_store::SharedPeerStore;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Service bit filters a seeder is usually asked for (`x9` = NETWORK | WITNESS).
+const GOOD_FILTERS: [(&str, u64); 7] = [
+    ("all", 0),
+    ("x1", 0x1),
+    ("x5", 0x5),
+    ("x9", 0x9),
+    ("xd", 0xd),
+    ("x400", 0x400),
+    ("x409", 0x409),
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket of `LATENCY_BUCKETS`, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    crawl_attempts: u64,
+    crawl_successes: u64,
+    handshake_failures: BTreeMap<&'static str, u64>,
+    addr_messages: u64,
+    addresses_learned: u64,
+    /// Keyed by query type and response code.
+    dns_queries: BTreeMap<(String, &'static str), u64>,
+    dns_malformed: u64,
+    connect_latency: Histogram,
+    handshake_latency: Histogram,
+    ping_latency: Histogram,
+}
+
+/// Counters and histograms fed by the crawler and the DNS server.
+///
+/// Gauges (peers by status, good nodes by filter) aren't stored here: they are
+/// computed from the peer store on every scrape.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+pub type SharedMetrics = Arc<Metrics>;
+
+impl Metrics {
+    pub fn crawl_attempt(&self) {
+        self.counters.lock().unwrap().crawl_attempts += 1;
+    }
+
+    pub fn crawl_success(&self) {
+        self.counters.lock().unwrap().crawl_successes += 1;
+    }
+
+    /// `reason` should come from `failure_reason` so the label set stays small.
+    pub fn handshake_failed(&self, reason: &'static str) {
+        *self.counters.lock().unwrap().handshake_failures.entry(reason).or_default() += 1;
+    }
+
+    pub fn addr_received(&self, addresses: usize) {
+        let mut c = self.counters.lock().unwrap();
+        c.addr_messages += 1;
+        c.addresses_learned += addresses as u64;
+    }
+
+    pub fn dns_query(&self, qtype: u16, rcode: &'static str) {
+        *self.counters.lock().unwrap().dns_queries.entry((qtype_name(qtype), rcode)).or_default() += 1;
+    }
+
+    pub fn dns_malformed(&self) {
+        self.counters.lock().unwrap().dns_malformed += 1;
+    }
+
+    pub fn observe_connect(&self, elapsed: Duration) {
+        self.counters.lock().unwrap().connect_latency.observe(elapsed);
+    }
+
+    pub fn observe_handshake(&self, elapsed: Duration) {
+        self.counters.lock().unwrap().handshake_latency.observe(elapsed);
+    }
+
+    pub fn observe_ping(&self, elapsed: Duration) {
+        self.counters.lock().unwrap().ping_latency.observe(elapsed);
+    }
+
+    /// Crawl attempts and how many of them completed the handshake.
+    pub fn crawl_totals(&self) -> (u64, u64) {
+        let c = self.counters.lock().unwrap();
+        (c.crawl_attempts, c.crawl_successes)
+    }
+
+    /// DNS queries answered, whatever their type or response code.
+    pub fn dns_query_count(&self) -> u64 {
+        self.counters.lock().unwrap().dns_queries.values().sum()
+    }
+
+    /// DNS queries by type and response code, and packets that could not be parsed.
+    pub fn dns_stats(&self) -> (Vec<(String, &'static str, u64)>, u64) {
+        let c = self.counters.lock().unwrap();
+        let by_type = c.dns_queries.iter().map(|((qtype, rcode), n)| (qtype.clone(), *rcode, *n)).collect();
+        (by_type, c.dns_malformed)
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn render(&self, peers: &[PeerInfo]) -> String {
+        let c = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        counter(&mut out, "seeder_crawl_attempts_total", "Peers the crawler tried to connect to", &[(String::new(), c.crawl_attempts)]);
+        counter(&mut out, "seeder_crawl_successes_total", "Crawls that completed the handshake", &[(String::new(), c.crawl_successes)]);
+        let failures: Vec<_> = c.handshake_failures.iter().map(|(r, n)| (format!("reason=\"{}\"", r), *n)).collect();
+        counter(&mut out, "seeder_handshake_failures_total", "Failed handshakes by reason", &failures);
+        counter(&mut out, "seeder_addr_messages_total", "addr messages received", &[(String::new(), c.addr_messages)]);
+        counter(&mut out, "seeder_addresses_learned_total", "Addresses received in addr messages", &[(String::new(), c.addresses_learned)]);
+        let queries: Vec<_> = c
+            .dns_queries
+            .iter()
+            .map(|((qtype, rcode), n)| (format!("type=\"{}\",rcode=\"{}\"", qtype, rcode), *n))
+            .collect();
+        counter(&mut out, "seeder_dns_queries_total", "DNS queries answered by type and response code", &queries);
+        counter(&mut out, "seeder_dns_malformed_total", "DNS packets that could not be parsed", &[(String::new(), c.dns_malformed)]);
+
+        let by_status: Vec<_> = PeerStatus::ALL
+            .iter()
+            .map(|s| (format!("status=\"{}\"", s.name()), peers.iter().filter(|p| p.status == *s).count() as u64))
+            .collect();
+        gauge(&mut out, "seeder_peers", "Known peers by status", &by_status);
+        let good: Vec<_> = peers.iter().filter(|p| p.status == PeerStatus::ConnectedRecently).collect();
+        let by_filter: Vec<_> = GOOD_FILTERS
+            .iter()
+            .map(|(name, mask)| {
+                let n = good.iter().filter(|p| p.services.unwrap_or(0) & mask == *mask).count();
+                (format!("filter=\"{}\"", name), n as u64)
+            })
+            .collect();
+        gauge(&mut out, "seeder_good_nodes", "Recently reachable peers by service filter", &by_filter);
+
+        histogram(&mut out, "seeder_connect_latency_seconds", "Time to open the TCP connection", &c.connect_latency);
+        histogram(&mut out, "seeder_handshake_latency_seconds", "Time from connection to verack", &c.handshake_latency);
+        histogram(&mut out, "seeder_ping_latency_seconds", "Round trip of ping/pong after the handshake", &c.ping_latency);
+        out
+    }
+}
+
+/// Bounded label for a failed handshake, so an error message can't create a new series.
+pub fn failure_reason(e: &io::Error) -> &'static str {
+    if let Some(what) = Misbehavior::from_io(e) {
+        return what.name();
+    }
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => "closed",
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => "reset",
+        io::ErrorKind::TimedOut => "timeout",
+        _ => "other",
+    }
+}
+
+fn qtype_name(qtype: u16) -> String {
+    match qtype {
+        1 => "A".into(),
+        2 => "NS".into(),
+        6 => "SOA".into(),
+        16 => "TXT".into(),
+        28 => "AAAA".into(),
+        255 => "ANY".into(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn samples(out: &mut String, name: &str, values: &[(String, u64)]) {
+    for (labels, value) in values {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, values: &[(String, u64)]) {
+    header(out, name, help, "counter");
+    samples(out, name, values);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, values: &[(String, u64)]) {
+    header(out, name, help, "gauge");
+    samples(out, name, values);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, h: &Histogram) {
+    header(out, name, help, "histogram");
+    let mut cumulative = 0;
+    for (le, n) in LATENCY_BUCKETS.iter().zip(h.buckets) {
+        cumulative += n;
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, h.count);
+    let _ = writeln!(out, "{}_sum {}", name, h.sum);
+    let _ = writeln!(out, "{}_count {}", name, h.count);
+}
+
+/// Serves `GET /metrics` on `addr` from a background thread.
+///
+/// Binds before returning, so a busy port is reported at startup.
+pub fn serve_metrics(addr: SocketAddr, metrics: SharedMetrics, peer_store: SharedPeerStore) -> io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    Ok(thread::spawn(move || {
+        serve(listener, move |stream| {
+            // Um scrape com problema não derruba o endpoint
+            let _ = answer(stream, &metrics, &peer_store);
+        })
+    }))
+}
+
+fn answer(mut stream: TcpStream, metrics: &Metrics, peer_store: &SharedPeerStore) -> io::Result<()> {
+    let request = read_request(&stream)?;
+    if request.method == "GET" && request.path == "/metrics" {
+        let peers = peer_store.lock().unwrap().all_peers();
+        write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", &metrics.render(&peers))
+    } else {
+        write_response(&mut stream, "404 Not Found", "text/plain", "not found\n")
+    }
+}
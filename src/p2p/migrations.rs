@@ -0,0 +1,63 @@
+use std::io::{self, ErrorKind};
+use serde_json::{json, Value};
+
+/// Version written by this build. Bump it and append a step to `MIGRATIONS`
+/// whenever the on-disk shape of the peer database changes.
+pub const DB_VERSION: u64 = 3;
+
+type Migration = fn(Value) -> io::Result<Value>;
+
+/// `MIGRATIONS[i]` turns a version `i + 1` document into version `i + 2`.
+const MIGRATIONS: &[Migration] = &[v1_to_v2, v2_to_v3];
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Files written before versioning have no `version` field and count as v1.
+pub fn version_of(doc: &Value) -> io::Result<u64> {
+    match doc.get("version") {
+        None => Ok(1),
+        Some(v) => v.as_u64().ok_or_else(|| invalid(format!("invalid version field: {}", v))),
+    }
+}
+
+/// Brings a parsed peer database up to `DB_VERSION`, one step at a time.
+pub fn migrate(mut doc: Value) -> io::Result<Value> {
+    let mut version = version_of(&doc)?;
+    if version == 0 || version > DB_VERSION {
+        return Err(invalid(format!(
+            "peer database version {} is not supported (this build reads up to {})",
+            version, DB_VERSION
+        )));
+    }
+    while version < DB_VERSION {
+        doc = MIGRATIONS[(version - 1) as usize](doc)?;
+        version += 1;
+        doc["version"] = json!(version);
+    }
+    Ok(doc)
+}
+
+/// v1: bare `{"peers": {...}}` as written by the first `save_to_file`.
+/// v2: same map inside a `{"version": 2, "peers": {...}}` envelope.
+fn v1_to_v2(doc: Value) -> io::Result<Value> {
+    let peers = doc
+        .get("peers")
+        .cloned()
+        .ok_or_else(|| invalid("v1 database without a peers map".into()))?;
+    Ok(json!({ "version": 2, "peers": peers }))
+}
+
+/// v3 adds per-peer metadata. Everything new is optional, but `first_seen`
+/// can be backfilled: the oldest timestamp we have for old entries is `last_seen`.
+fn v2_to_v3(mut doc: Value) -> io::Result<Value> {
+    if let Some(peers) = doc.get_mut("peers").and_then(Value::as_object_mut) {
+        for peer in peers.values_mut() {
+            if peer.get("first_seen").is_none() {
+                peer["first_seen"] = peer.get("last_seen").cloned().unwrap_or(Value::Null);
+            }
+        }
+    }
+    Ok(doc)
+}
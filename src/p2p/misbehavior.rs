@@ -0,0 +1,145 @@
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use crate::p2p::banlist::{ban_subnet, SharedBanList, Subnet};
+use crate::p2p::log::{log, log_as, Component, Event, LogLevel, LogMessage};
+use crate::p2p::peer_store::SharedPeerStore;
+use crate::p2p::utils::{parse_addr_entries, parse_compact_size, AddrEntry};
+
+/// Score at which a peer is banned automatically (Bitcoin Core uses the same).
+pub const BAN_THRESHOLD: u32 = 100;
+/// How long an automatic ban lasts.
+pub const MISBEHAVIOR_BAN_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+/// Bitcoin Core never sends more than this many addresses in one `addr`.
+pub const MAX_ADDR_PER_MESSAGE: usize = 1000;
+
+/// Messages a peer may send before the version/verack exchange is over.
+const HANDSHAKE_COMMANDS: &[&str] = &["version", "verack", "wtxidrelay", "sendaddrv2", "sendtxrcncl", "ping", "pong"];
+
+/// Protocol violations we hold against a peer.
+///
+/// Also used as the inner error of `io::ErrorKind::InvalidData` errors coming
+/// out of message reading, so callers can tell a hostile peer from a dead socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    BadMagic,
+    BadChecksum,
+    OversizedPayload,
+    MalformedVersion,
+    MalformedAddr,
+    AddrFlood,
+    UnsolicitedMessage,
+}
+
+impl Misbehavior {
+    pub fn score(&self) -> u32 {
+        match self {
+            Misbehavior::BadMagic => 50,
+            Misbehavior::BadChecksum => 20,
+            Misbehavior::OversizedPayload => 100,
+            Misbehavior::MalformedVersion => 50,
+            Misbehavior::MalformedAddr => 20,
+            Misbehavior::AddrFlood => 20,
+            Misbehavior::UnsolicitedMessage => 10,
+        }
+    }
+
+    /// Stable snake_case name, used as a metrics label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Misbehavior::BadMagic => "bad_magic",
+            Misbehavior::BadChecksum => "bad_checksum",
+            Misbehavior::OversizedPayload => "oversized_payload",
+            Misbehavior::MalformedVersion => "malformed_version",
+            Misbehavior::MalformedAddr => "malformed_addr",
+            Misbehavior::AddrFlood => "addr_flood",
+            Misbehavior::UnsolicitedMessage => "unsolicited_message",
+        }
+    }
+
+    /// Extracts the violation from an error returned while reading a message.
+    pub fn from_io(e: &io::Error) -> Option<Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Misbehavior>()).copied()
+    }
+
+    pub fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
+impl fmt::Display for Misbehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Misbehavior::BadMagic => "Invalid magic bytes",
+            Misbehavior::BadChecksum => "Invalid checksum",
+            Misbehavior::OversizedPayload => "Oversized payload",
+            Misbehavior::MalformedVersion => "Malformed version message",
+            Misbehavior::MalformedAddr => "Malformed addr message",
+            Misbehavior::AddrFlood => "Too many addresses in one addr message",
+            Misbehavior::UnsolicitedMessage => "Unsolicited message before handshake",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::error::Error for Misbehavior {}
+
+/// Whether `command` is acceptable before the handshake has finished.
+pub fn allowed_before_handshake(command: &str) -> bool {
+    HANDSHAKE_COMMANDS.contains(&command)
+}
+
+/// Flags floods and truncated entries in an `addr` payload of which `parsed`
+/// entries could be decoded.
+pub fn check_addr(payload: &[u8], parsed: usize) -> Option<Misbehavior> {
+    let (count, _) = parse_compact_size(payload);
+    if count > MAX_ADDR_PER_MESSAGE {
+        Some(Misbehavior::AddrFlood)
+    } else if payload.is_empty() || parsed < count {
+        Some(Misbehavior::MalformedAddr)
+    } else {
+        None
+    }
+}
+
+pub fn inspect_addr(payload: &[u8]) -> (Vec<AddrEntry>, Option<Misbehavior>) {
+    let entries = parse_addr_entries(payload);
+    let problem = check_addr(payload, entries.len());
+    (entries, problem)
+}
+
+/// Adds the violation to the peer's score and bans it once `BAN_THRESHOLD` is reached.
+/// Returns the new score.
+///
+/// The ban covers the peer's whole IP (every port), as in Bitcoin Core: other
+/// ports are the same host, and a per-port ban is dodged by reconnecting from
+/// another one. Banning resets the stored score, so after the ban expires the
+/// peer needs another `BAN_THRESHOLD` worth of violations to be banned again.
+pub fn report_misbehavior(
+    store: &SharedPeerStore,
+    bans: &SharedBanList,
+    log_tx: &Sender<LogMessage>,
+    addr: SocketAddr,
+    what: Misbehavior,
+) -> u32 {
+    let mut score = 0;
+    store.lock().unwrap().update_peer(addr, &mut |p| {
+        p.misbehavior_score = p.misbehavior_score.saturating_add(what.score());
+        score = p.misbehavior_score;
+    });
+    log(log_tx, LogLevel::Warn, Event::Misbehavior(addr, what.to_string(), score));
+
+    if score >= BAN_THRESHOLD && !bans.lock().unwrap().is_banned(&addr) {
+        let reason = format!("misbehavior score {} (last: {})", score, what);
+        match ban_subnet(bans, store, Subnet::single(addr.ip()), Some(MISBEHAVIOR_BAN_TIME), &reason) {
+            Ok(_) => {
+                store.lock().unwrap().update_peer(addr, &mut |p| p.misbehavior_score = 0);
+                log(log_tx, LogLevel::Warn, Event::AutoBanned(addr, reason));
+            }
+            Err(e) => log_as(log_tx, Component::Db, LogLevel::Error, Event::BanFailed(addr, e.to_string())),
+        }
+    }
+    score
+}
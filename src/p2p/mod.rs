@@ -2,6 +2,25 @@ pub mod messageheader;
 pub mod utils;
 pub mod p2p_client;
 pub mod database;
+pub mod atomic_file;
+pub mod migrations;
+pub mod peer_store;
+pub mod sqlite_store;
+pub mod banlist;
+pub mod misbehavior;
+pub mod core_import;
+pub mod dnsseed_dump;
+pub mod peer_export;
+pub mod retention;
+pub mod addrman;
+pub mod history;
+pub mod census;
+pub mod asmap;
+pub mod http;
+pub mod metrics;
+pub mod rpc;
+pub mod rpc_auth;
+pub mod services;
 pub mod log;
 pub mod multhread;
 pub mod inventory;
@@ -1,31 +1,211 @@
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Sender, WeakSender};
+use tokio::net::TcpStream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+use std::io;
 use std::net::SocketAddr;
-use crate::p2p::database::{PeerStatus, PeerDatabase};
+use crate::p2p::database::{now_secs, FailureStage, PeerStatus};
+use crate::p2p::utils::{build_version_payload, parse_version_message, sha256d, AddrEntry, VersionInfo, MAGIC};
+use crate::p2p::peer_store::SharedPeerStore;
+use crate::p2p::banlist::SharedBanList;
+use crate::p2p::history::{Observation, SharedHistory};
+use crate::p2p::misbehavior::{allowed_before_handshake, inspect_addr, report_misbehavior, Misbehavior};
 use crate::p2p::log::{LogLevel, Event, log, LogMessage};
-use std::sync::{Arc, Mutex};
-use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashSet, VecDeque};
+use serde::Serialize;
+use std::time::{Duration, Instant};
 use crate::p2p::messageheader::MessageHeader;
+use crate::p2p::metrics::{failure_reason, Metrics, SharedMetrics};
+
+/// What `getcrawlstatus` and the prompt report about the current or last crawl.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CrawlState {
+    pub running: bool,
+    /// Unix times the crawl started and, once done, finished.
+    pub started: Option<u64>,
+    pub finished: Option<u64>,
+    /// Peers handed to the crawlers.
+    pub peers: usize,
+    /// Whether it was cut short by `stopcrawl`.
+    pub stopped: bool,
+}
+
+/// Background crawl shared by the interactive prompt and the RPC server.
+#[derive(Debug, Default)]
+pub struct CrawlControl {
+    stop: AtomicBool,
+    pub state: Mutex<CrawlState>,
+    /// Addresses waiting for the next crawl (`addnode`), oldest first.
+    pub queue: Mutex<VecDeque<SocketAddr>>,
+    /// Peers that accepted a connection during the current or last crawl.
+    pub connected: Arc<Mutex<HashSet<SocketAddr>>>,
+    /// Channel of the running crawl's DB task, without keeping it open.
+    pub db_tx: Mutex<Option<WeakSender<DbCommand>>>,
+    finished: Condvar,
+}
+
+pub type SharedCrawl = Arc<CrawlControl>;
+
+impl CrawlControl {
+    /// Marks a crawl of `peers` as running; `false` if one already is.
+    pub fn begin(&self, peers: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.running {
+            return false;
+        }
+        *state = CrawlState { running: true, started: Some(now_secs()), peers, ..Default::default() };
+        self.stop.store(false, Ordering::SeqCst);
+        self.connected.lock().unwrap().clear();
+        true
+    }
+
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        state.finished = Some(now_secs());
+        state.stopped = self.stop.load(Ordering::SeqCst);
+        self.finished.notify_all();
+    }
+
+    /// Waits up to `timeout` for the running crawl, if any, to finish; `false` on timeout.
+    pub fn wait_finished(&self, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self.finished.wait_timeout_while(state, timeout, |s| s.running).unwrap();
+        !state.running
+    }
+
+    /// Asks the running crawlers to give up; `false` if nothing is running.
+    pub fn request_stop(&self) -> bool {
+        let running = self.state.lock().unwrap().running;
+        if running {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+        running
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+}
 
 pub async fn run_crawlers_with_log(
     peers: Vec<SocketAddr>,
     db_tx: Sender<DbCommand>,
     log_tx: std::sync::mpsc::Sender<LogMessage>,
-    crawl_connected: Arc<Mutex<HashSet<SocketAddr>>>,
+    crawl: SharedCrawl,
+    bans: SharedBanList,
+    metrics: SharedMetrics,
 ) {
     let mut handles = Vec::new();
     for addr in peers {
         let db_tx = db_tx.clone();
         let log_tx = log_tx.clone();
-        let crawl_connected = crawl_connected.clone();
+        let crawl_connected = crawl.connected.clone();
+        let bans = bans.clone();
+        let metrics = metrics.clone();
         let handle = tokio::spawn(async move {
-            log(&log_tx, LogLevel::Info, Event::Custom(format!("Iniciando crawl em {}", addr)));
-            crate::p2p::multhread::crawl_peer_with_log(addr, db_tx, log_tx, crawl_connected).await;
+            log(&log_tx, LogLevel::Info, Event::CrawlStarted(addr));
+            crate::p2p::multhread::crawl_peer_with_log(addr, db_tx, log_tx, crawl_connected, bans, metrics).await;
         });
         handles.push(handle);
     }
-    for handle in handles {
-        let _ = handle.await;
+    drop(db_tx);
+    while handles.iter().any(|h| !h.is_finished()) {
+        if crawl.stop_requested() {
+            // Abortar solta o db_tx de cada crawler, e o multhread_db grava e termina
+            handles.iter().for_each(|h| h.abort());
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long we keep listening for `addr`/`pong` after the handshake.
+const GOSSIP_WAIT: Duration = Duration::from_secs(5);
+/// Same limit as Bitcoin Core's MAX_PROTOCOL_MESSAGE_LENGTH.
+pub const MAX_PAYLOAD: u32 = 4_000_000;
+
+async fn send_message_async(stream: &mut TcpStream, command: &str, payload: &[u8]) -> io::Result<()> {
+    let mut message = MessageHeader::new(command, payload).to_bytes();
+    message.extend_from_slice(payload);
+    stream.write_all(&message).await
+}
+
+async fn read_message_async(stream: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut header_buf = [0u8; 24];
+    stream.read_exact(&mut header_buf).await?;
+    let header = MessageHeader::from_bytes(&header_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid message header"))?;
+    if header.magic != MAGIC {
+        return Err(Misbehavior::BadMagic.into_io());
+    }
+    if header.payload_size > MAX_PAYLOAD {
+        return Err(Misbehavior::OversizedPayload.into_io());
+    }
+    let mut payload = vec![0u8; header.payload_size as usize];
+    stream.read_exact(&mut payload).await?;
+    if header.checksum != sha256d(&payload)[0..4] {
+        return Err(Misbehavior::BadChecksum.into_io());
+    }
+    Ok((header.command_str(), payload))
+}
+
+/// What one crawl of a reachable peer taught us.
+#[derive(Debug, Default)]
+struct CrawlOutcome {
+    version: Option<VersionInfo>,
+    ping: Option<Duration>,
+    learned: Vec<AddrEntry>,
+    misbehavior: Vec<Misbehavior>,
+}
+
+async fn handshake(stream: &mut TcpStream, addr: SocketAddr, outcome: &mut CrawlOutcome) -> io::Result<()> {
+    send_message_async(stream, "version", &build_version_payload(addr)).await?;
+    let mut verack = false;
+    while outcome.version.is_none() || !verack {
+        let (command, payload) = read_message_async(stream).await?;
+        match command.as_str() {
+            "version" => {
+                let info = parse_version_message(&payload).ok_or_else(|| Misbehavior::MalformedVersion.into_io())?;
+                outcome.version = Some(info);
+                send_message_async(stream, "verack", &[]).await?;
+            }
+            "verack" => verack = true,
+            "ping" => send_message_async(stream, "pong", &payload).await?,
+            other if !allowed_before_handshake(other) => outcome.misbehavior.push(Misbehavior::UnsolicitedMessage),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// After the handshake: measure ping and collect the peer's `addr` answer.
+async fn gossip(stream: &mut TcpStream, outcome: &mut CrawlOutcome, metrics: &Metrics) -> io::Result<()> {
+    let nonce: u64 = rand::random();
+    send_message_async(stream, "getaddr", &[]).await?;
+    send_message_async(stream, "ping", &nonce.to_le_bytes()).await?;
+    let sent_at = Instant::now();
+
+    // Peers costumam mandar primeiro o próprio endereço num addr de 1 entrada
+    while outcome.ping.is_none() || outcome.learned.len() <= 1 {
+        let (command, payload) = read_message_async(stream).await?;
+        match command.as_str() {
+            "pong" if payload.get(0..8) == Some(&nonce.to_le_bytes()[..]) => outcome.ping = Some(sent_at.elapsed()),
+            "addr" => {
+                let (entries, problem) = inspect_addr(&payload);
+                outcome.misbehavior.extend(problem);
+                metrics.addr_received(entries.len());
+                outcome.learned.extend(entries);
+            }
+            "ping" => send_message_async(stream, "pong", &payload).await?,
+            _ => {}
+        }
     }
+    Ok(())
 }
 
 pub async fn crawl_peer_with_log(
@@ -33,46 +213,205 @@ pub async fn crawl_peer_with_log(
     db_tx: Sender<DbCommand>,
     log_tx: std::sync::mpsc::Sender<LogMessage>,
     crawl_connected: Arc<Mutex<HashSet<SocketAddr>>>,
+    bans: SharedBanList,
+    metrics: SharedMetrics,
 ) {
-    match tokio::net::TcpStream::connect(addr).await {
-        Ok(mut _stream) => {
-            log(&log_tx, LogLevel::Info, Event::Connected(addr));
-            let _ = db_tx.send(DbCommand::UpdatePeerStatus(addr, PeerStatus::ConnectedRecently)).await;
-            crawl_connected.lock().unwrap().insert(addr);
-
-            let header = MessageHeader::new("getaddr", &[]);
-            let mut _message = header.to_bytes();
-            if let Err(e) = _stream.try_write(&_message) {
-                log(&log_tx, LogLevel::Info, Event::Custom(format!("Falha ao enviar getaddr para {}: {}", addr, e)));
-            } else {
-                log(&log_tx, LogLevel::Info, Event::Custom(format!("Enviado getaddr para {}", addr)));
-            }
+    if bans.lock().unwrap().is_banned(&addr) {
+        log(&log_tx, LogLevel::Debug, Event::SkippedBanned(addr));
+        return;
+    }
+    metrics.crawl_attempt();
+    let started = Instant::now();
+    let mut stream = match timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return fail(addr, FailureStage::Connect, e.to_string(), &db_tx, &log_tx).await,
+        Err(_) => return fail(addr, FailureStage::Connect, "connection timed out".into(), &db_tx, &log_tx).await,
+    };
+    metrics.observe_connect(started.elapsed());
+    log(&log_tx, LogLevel::Info, Event::Connected(addr));
+    crawl_connected.lock().unwrap().insert(addr);
+
+    let mut outcome = CrawlOutcome::default();
+    let started = Instant::now();
+    let reason = match timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, addr, &mut outcome)).await {
+        Ok(Ok(())) => {
+            metrics.observe_handshake(started.elapsed());
+            metrics.crawl_success();
+            None
         }
-        Err(e) => {
-            log(&log_tx, LogLevel::Warn, Event::FailedConnection(addr, e.to_string()));
-            let _ = db_tx.send(DbCommand::UpdatePeerStatus(addr, PeerStatus::Unreachable)).await;
+        Ok(Err(e)) => {
+            metrics.handshake_failed(failure_reason(&e));
+            outcome.misbehavior.extend(Misbehavior::from_io(&e));
+            Some(e.to_string())
         }
+        Err(_) => {
+            metrics.handshake_failed("timeout");
+            Some("handshake timed out".into())
+        }
+    };
+    for what in outcome.misbehavior.drain(..) {
+        let _ = db_tx.send(DbCommand::Misbehaving(addr, what)).await;
+    }
+    if let Some(reason) = reason {
+        return fail(addr, FailureStage::Handshake, reason, &db_tx, &log_tx).await;
+    }
+
+    // Falhar aqui não invalida o peer: o handshake já deu certo
+    if let Ok(Err(e)) = timeout(GOSSIP_WAIT, gossip(&mut stream, &mut outcome, &metrics)).await {
+        outcome.misbehavior.extend(Misbehavior::from_io(&e));
+        log(&log_tx, LogLevel::Debug, Event::ConnectionClosed(addr, e.to_string()));
+    }
+    for what in outcome.misbehavior.drain(..) {
+        let _ = db_tx.send(DbCommand::Misbehaving(addr, what)).await;
+    }
+
+    if let Some(version) = outcome.version {
+        log(&log_tx, LogLevel::Info, Event::PeerVersion(
+            addr, version.version, version.user_agent.clone(), version.start_height,
+        ));
+        let _ = db_tx.send(DbCommand::RecordVersion(addr, version)).await;
+    }
+    if let Some(ping) = outcome.ping {
+        metrics.observe_ping(ping);
+        let _ = db_tx.send(DbCommand::RecordPing(addr, ping)).await;
+    }
+    log(&log_tx, LogLevel::Info, Event::AddrReceived(addr, outcome.learned.len()));
+    outcome.learned.retain(|e| !bans.lock().unwrap().is_banned(&e.addr));
+    for entry in outcome.learned {
+        let _ = db_tx.send(DbCommand::RegisterPeer(entry.addr, Some(entry.services), Some(addr))).await;
     }
+    let _ = db_tx.send(DbCommand::UpdatePeerStatus(addr, PeerStatus::ConnectedRecently)).await;
+}
+
+async fn fail(
+    addr: SocketAddr,
+    stage: FailureStage,
+    reason: String,
+    db_tx: &Sender<DbCommand>,
+    log_tx: &std::sync::mpsc::Sender<LogMessage>,
+) {
+    log(log_tx, LogLevel::Warn, Event::FailedConnection(addr, reason.clone()));
+    let _ = db_tx.send(DbCommand::RecordFailure(addr, stage, reason)).await;
+    let _ = db_tx.send(DbCommand::UpdatePeerStatus(addr, PeerStatus::Unreachable)).await;
 }
 
 #[derive(Debug)]
 pub enum DbCommand {
     UpdatePeerStatus(SocketAddr, PeerStatus),
-    _RegisterPeer(SocketAddr, Option<u64>),
+    /// Address, advertised services and the peer that gossiped it.
+    RegisterPeer(SocketAddr, Option<u64>, Option<SocketAddr>),
+    RecordVersion(SocketAddr, VersionInfo),
+    RecordPing(SocketAddr, Duration),
+    RecordFailure(SocketAddr, FailureStage, String),
+    Misbehaving(SocketAddr, Misbehavior),
+    /// Stop taking commands, apply the ones already queued, flush and exit.
+    Shutdown,
 }
 
 
+/// When the DB task writes its pending changes to disk.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushPolicy {
+    /// Flush at least this often while there are unsaved changes.
+    pub interval: Duration,
+    /// Flush right away once this many commands are pending.
+    pub max_dirty: usize,
+}
+
+fn flush(
+    store: &SharedPeerStore,
+    history: &SharedHistory,
+    dirty: &mut usize,
+    log_tx: &std::sync::mpsc::Sender<LogMessage>,
+) {
+    if *dirty == 0 {
+        return;
+    }
+    if let Err(e) = history.lock().unwrap().flush() {
+        log(log_tx, LogLevel::Error, Event::StorageError("histórico".into(), e.to_string()));
+    }
+    let started = Instant::now();
+    let mut db = store.lock().unwrap();
+    match db.persist() {
+        Ok(()) => {
+            log(log_tx, LogLevel::Debug, Event::SavedToDisk(*dirty, db.len(), started.elapsed()));
+            *dirty = 0;
+        }
+        // Mantém o contador: a gravação é tentada de novo no próximo tick ou no flush final
+        Err(e) => log(log_tx, LogLevel::Error, Event::StorageError("banco de dados".into(), e.to_string())),
+    }
+}
+
 pub async fn multhread_db(
-    mut db: PeerDatabase,
+    store: SharedPeerStore,
     mut rx: tokio::sync::mpsc::Receiver<DbCommand>,
-    db_path: &'static str,
+    log_tx: std::sync::mpsc::Sender<LogMessage>,
+    policy: FlushPolicy,
+    bans: SharedBanList,
+    history: SharedHistory,
 ) {
-    while let Some(cmd) = rx.recv().await {
-        match cmd {
-            DbCommand::UpdatePeerStatus(addr, status) => db._update_status(addr, status),
-            DbCommand::_RegisterPeer(addr, services) => db.register_peer(addr, services),
+    let mut dirty = 0usize;
+    let start = tokio::time::Instant::now() + policy.interval;
+    let mut ticker = tokio::time::interval_at(start, policy.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            cmd = rx.recv() => {
+                let Some(cmd) = cmd else { break };
+                {
+                    // Lista de banimentos sempre antes do banco, como em banlist::unban_subnet
+                    let banned = match &cmd {
+                        DbCommand::UpdatePeerStatus(addr, _) => bans.lock().unwrap().is_banned(addr),
+                        _ => false,
+                    };
+                    let mut db = store.lock().unwrap();
+                    match cmd {
+                        DbCommand::UpdatePeerStatus(addr, status) => {
+                            // Um peer banido durante o crawl continua banido quando o resultado chega
+                            let status = if banned { PeerStatus::Banned } else { status };
+                            db.update_status(addr, status.clone());
+                            // Cada tentativa do crawler vai para o histórico, com o que o peer informou
+                            let success = status == PeerStatus::ConnectedRecently;
+                            if success || status == PeerStatus::Unreachable {
+                                if let Some(peer) = db.get_peer(&addr) {
+                                    drop(db);
+                                    let obs = Observation::from_peer(&peer, success, now_secs());
+                                    if let Err(e) = history.lock().unwrap().record(obs) {
+                                        log(&log_tx, LogLevel::Error, Event::StorageError("histórico".into(), e.to_string()));
+                                    }
+                                }
+                            }
+                        }
+                        DbCommand::RegisterPeer(addr, services, source) => db.register_peer(addr, services, source),
+                        DbCommand::RecordVersion(addr, info) => db.update_peer(addr, &mut |p| p.record_version(&info)),
+                        DbCommand::RecordPing(addr, ping) => {
+                            db.update_peer(addr, &mut |p| p.ping_ms = Some(ping.as_millis() as u64))
+                        }
+                        DbCommand::RecordFailure(addr, stage, reason) => {
+                            db.update_peer(addr, &mut |p| p.record_failure(stage, reason.clone(), now_secs()))
+                        }
+                        DbCommand::Misbehaving(addr, what) => {
+                            // report_misbehavior trava o banco de novo (e a lista de banimentos)
+                            drop(db);
+                            report_misbehavior(&store, &bans, &log_tx, addr, what);
+                        }
+                        DbCommand::Shutdown => {
+                            // Os crawlers que ainda mandarem algo recebem erro; o que já está na fila é aplicado
+                            rx.close();
+                            continue;
+                        }
+                    }
+                }
+                dirty += 1;
+                if dirty >= policy.max_dirty {
+                    flush(&store, &history, &mut dirty, &log_tx);
+                }
+            }
+            _ = ticker.tick() => flush(&store, &history, &mut dirty, &log_tx),
         }
-        db.save_to_file(db_path);
     }
 
-}
\ No newline at end of file
+    // Todos os crawlers terminaram: grava o que ainda estiver pendente
+    flush(&store, &history, &mut dirty, &log_tx);
+}
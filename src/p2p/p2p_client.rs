@@ -1,16 +1,39 @@
 use std::net::{TcpStream, ToSocketAddrs, SocketAddr};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io::{Write, Read, ErrorKind, Result, Error};
 use crate::p2p::messageheader::MessageHeader;
 use crate::p2p::utils::*;
-use crate::p2p::database::PeerDatabase;
-use crate::p2p::log::{LogLevel, Event, log, LogMessage};
+use crate::p2p::peer_store::SharedPeerStore;
+use crate::p2p::banlist::SharedBanList;
+use crate::p2p::misbehavior::{allowed_before_handshake, check_addr, report_misbehavior, Misbehavior};
+use crate::p2p::multhread::MAX_PAYLOAD;
+use crate::p2p::database::{now_secs, FailureStage};
+use crate::p2p::log::{log_as, Component, Event, LogLevel, LogMessage};
 use crate::p2p::inventory::{InventoryType};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
 use tokio::net::TcpStream as TokioTcpStream;
 
+/// Live view of the client's connection, shared by all clones of the client.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientStatus {
+    pub peer: Option<SocketAddr>,
+    /// Unix time the TCP connection was opened.
+    pub connected_since: Option<u64>,
+    pub handshake_complete: bool,
+    pub last_send: Option<u64>,
+    pub last_recv: Option<u64>,
+    /// Bytes on the wire, headers included.
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub messages_sent: u64,
+    pub messages_recv: u64,
+    pub ping_ms: Option<u64>,
+}
+
+pub type SharedClientStatus = Arc<Mutex<ClientStatus>>;
 
-#[derive(Debug)]
 pub struct BitcoinClient {
     stream: Option<TcpStream>,
     connected_addr: Option<SocketAddr>,
@@ -18,13 +41,20 @@ pub struct BitcoinClient {
     version_received: bool,
     verack_received: bool,
     seen_inventory: std::collections::HashSet<[u8; 32]>,
-    pub peer_db: PeerDatabase,
+    ping_sent: Option<(u64, Instant)>,
+    pub peer_store: SharedPeerStore,
+    pub ban_list: SharedBanList,
     pub log_tx: Option<Sender<LogMessage>>,
+    pub status: SharedClientStatus,
 }
 
 impl BitcoinClient {
-    pub fn new_with_logger(log_tx: Sender<LogMessage>) -> Self {
-        let peer_db = PeerDatabase::load_from_file("peers.json");
+    pub fn new_with_logger(
+        log_tx: Sender<LogMessage>,
+        peer_store: SharedPeerStore,
+        ban_list: SharedBanList,
+        status: SharedClientStatus,
+    ) -> Self {
         BitcoinClient {
             stream: None,
             connected_addr: None,
@@ -32,40 +62,45 @@ impl BitcoinClient {
             version_received: false,
             verack_received: false,
             seen_inventory: std::collections::HashSet::new(),
-            peer_db,
+            ping_sent: None,
+            peer_store,
+            ban_list,
             log_tx: Some(log_tx),
+            status,
         }
     }
 
     pub fn connect(&mut self) -> Result<()> {
     let addr_str = "seed.bitcoin.sipa.be:8333";
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Info, Event::Custom(format!("Resolvendo {}", addr_str)));
-    }
+    self.log(LogLevel::Info, Event::Resolving(addr_str.to_string()));
     let socket_addrs: Vec<_> = addr_str.to_socket_addrs()?.collect();
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Debug, Event::Custom(format!("Endereços resolvidos: {:?}", socket_addrs)));
-    }
+    self.log(LogLevel::Debug, Event::Resolved(addr_str.to_string(), socket_addrs.clone()));
     let mut connected = false;
     for addr in &socket_addrs {
-        if let Some(ref tx) = self.log_tx {
-            log(tx, LogLevel::Info, Event::Custom(format!("Tentando conectar em {}", addr)));
+        if self.ban_list.lock().unwrap().is_banned(addr) {
+            self.log(LogLevel::Info, Event::SkippedBanned(*addr));
+            continue;
         }
+        self.log(LogLevel::Info, Event::Connecting(*addr));
         match TcpStream::connect_timeout(addr, Duration::from_secs(10)) {
             Ok(s) => {
-                if let Some(ref tx) = self.log_tx {
-                    log(tx, LogLevel::Info, Event::Connected(*addr));
-                }
+                self.log(LogLevel::Info, Event::Connected(*addr));
                 s.set_read_timeout(Some(Duration::from_secs(30)))?;
                 self.stream = Some(s);
                 self.connected_addr = Some(*addr);
+                *self.status.lock().unwrap() = ClientStatus {
+                    peer: Some(*addr),
+                    connected_since: Some(now_secs()),
+                    ..Default::default()
+                };
                 connected = true;
                 break;
             }
             Err(e) => {
-                if let Some(ref tx) = self.log_tx {
-                    log(tx, LogLevel::Warn, Event::FailedConnection(*addr, e.to_string()));
-                }
+                self.log(LogLevel::Warn, Event::FailedConnection(*addr, e.to_string()));
+                self.peer_store.lock().unwrap().update_peer(*addr, &mut |p| {
+                    p.record_failure(FailureStage::Connect, e.to_string(), now_secs())
+                });
                 continue;
             }
         }
@@ -73,144 +108,135 @@ impl BitcoinClient {
     if !connected {
         self.stream = None;
         self.connected_addr = None;
-        if let Some(ref tx) = self.log_tx {
-            log(tx, LogLevel::Error, Event::Custom("Não foi possível conectar a nenhum endereço".into()));
-        }
+        self.log(LogLevel::Error, Event::NoReachableAddress(addr_str.to_string()));
         return Err(Error::new(ErrorKind::ConnectionRefused, "Could not connect to any address"));
     }
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Info, Event::Custom(format!("Conexão estabelecida com {}", self.connected_addr.unwrap())));
-    }
     Ok(())
 }
     
     pub fn start_handshake(&mut self) -> Result<()> {
-        if let Some(ref tx) = self.log_tx {
-            log(tx, LogLevel::Debug, Event::Custom("Enviando mensagem version".into()));
-        }
         let version_payload = build_version_payload(self.connected_addr.unwrap());
         self.send_message("version", &version_payload)?;
         Ok(())
     }
-    
+
+    fn log(&self, level: LogLevel, event: Event) {
+        if let Some(ref tx) = self.log_tx {
+            log_as(tx, Component::Client, level, event);
+        }
+    }
+
+    /// The connected peer, or 0.0.0.0:0 before `connect` succeeded.
+    fn peer(&self) -> SocketAddr {
+        self.connected_addr.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)))
+    }
+
     fn handle_message(&mut self, command: &str, payload: &[u8]) -> Result<()> {
         match command {
             "version" => {
-                println!("   📋 Version message received");
                 self.version_received = true;
-    
-                if payload.len() >= 4 {
-                    let version = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
-                    println!("   🔢 Protocol version: {}", version);
+                if let Some(info) = parse_version_message(payload) {
+                    self.log(LogLevel::Info, Event::PeerVersion(
+                        self.peer(), info.version, info.user_agent.clone(), info.start_height,
+                    ));
+                    if let Some(addr) = self.connected_addr {
+                        self.peer_store.lock().unwrap().update_peer(addr, &mut |p| p.record_version(&info));
+                    }
                 }
-    
-                println!("📤 Sending verack...");
                 self.send_message("verack", &[])?;
             }
-            "verack" => {
-                println!("   ✅ Verack received");
-                self.verack_received = true;
-            }
+            "verack" => self.verack_received = true,
             "ping" => {
-                println!("   🏓 Ping received");
                 if payload.len() >= 8 {
-                    println!("📤 Sending pong...");
                     self.send_message("pong", payload)?;
                 }
             }
-            "pong" => {
-                println!("   🏓 Pong received");
-            }
-            "inv" => {
-                println!("   📦 Inventory message received");
-                self.handle_inv_message(payload)?;
-            }
+            "pong" => self.handle_pong(payload),
+            "inv" => self.handle_inv_message(payload)?,
             "addr" => {
-                println!("   📍 Address list received");
-                let addresses = parse_addr_message(payload);
-                println!("   📊 Successfully parsed {} addresses", addresses.len());
+                let addresses = parse_addr_entries(payload);
+                if let Some(problem) = check_addr(payload, addresses.len()) {
+                    self.report(problem);
+                }
+                self.log(LogLevel::Info, Event::AddrReceived(self.peer(), addresses.len()));
                 // Registra cada peer na base de dados
-                for addr in &addresses {
-                    self.peer_db.register_peer(*addr, None);
+                let bans = self.ban_list.lock().unwrap();
+                let mut store = self.peer_store.lock().unwrap();
+                for entry in addresses.iter().filter(|e| !bans.is_banned(&e.addr)) {
+                    self.log(LogLevel::Trace, Event::PeerDiscovered(entry.addr));
+                    store.register_peer(entry.addr, Some(entry.services), self.connected_addr);
                 }
                 // Opcional: salve imediatamente após receber novos peers
-                self.peer_db.save_to_file("peers.json");
-            }
-            "alert" => {
-                println!("   ⚠️  Alert message received (ignoring)");
-            }
-            "sendheaders" => {
-                println!("   📋 SendHeaders message received");
-            }
-            "sendcmpct" => {
-                println!("   📦 SendCmpct message received");
-            }
-            "feefilter" => {
-                println!("   💰 FeeFilter message received");
-            }
-            _ => {
-                println!("   ❓ Unknown command: {} ({} bytes)", command, payload.len());
+                if let Err(e) = store.persist() {
+                    self.log(LogLevel::Warn, Event::StorageError("banco de dados".into(), e.to_string()));
+                }
             }
+            "alert" | "sendheaders" | "sendcmpct" | "feefilter" => {}
+            _ => self.log(LogLevel::Debug, Event::UnknownMessage(self.peer(), command.to_string(), payload.len())),
         }
     
         Ok(())
     }
     
+    fn send_ping(&mut self) -> Result<()> {
+        let nonce: u64 = rand::random();
+        self.send_message("ping", &nonce.to_le_bytes())?;
+        self.ping_sent = Some((nonce, Instant::now()));
+        Ok(())
+    }
+
+    fn handle_pong(&mut self, payload: &[u8]) {
+        let Some((nonce, sent_at)) = self.ping_sent else { return };
+        if payload.len() < 8 || u64::from_le_bytes(payload[0..8].try_into().unwrap()) != nonce {
+            return;
+        }
+        self.ping_sent = None;
+        let ping_ms = sent_at.elapsed().as_millis() as u64;
+        self.log(LogLevel::Info, Event::PingMeasured(self.peer(), ping_ms));
+        self.status.lock().unwrap().ping_ms = Some(ping_ms);
+        if let Some(addr) = self.connected_addr {
+            self.peer_store.lock().unwrap().update_peer(addr, &mut |p| p.ping_ms = Some(ping_ms));
+        }
+    }
+
     fn handle_inv_message(&mut self, payload: &[u8]) -> Result<()> {
         let inventory_items = parse_inv_message(payload);
-        
+        let peer = self.peer();
+        self.log(LogLevel::Debug, Event::InvReceived(peer, inventory_items.len()));
         if inventory_items.is_empty() {
-            println!("   ⚠️  Empty inventory message");
             return Ok(());
         }
         
-        println!("   📊 Received {} inventory items:", inventory_items.len());
-        
         let mut items_to_request = Vec::new();
         
-        for (i, item) in inventory_items.iter().enumerate() {
-            let hash_str = item.hash_hex();
-            println!("     {}: {} - {}", i + 1, item.inv_type.name(), hash_str);
-            
+        for item in &inventory_items {
+            let mut requested = false;
             // Check if we've already seen this item
             if !self.seen_inventory.contains(&item.hash) {
                 self.seen_inventory.insert(item.hash);
                 
                 // Decide which types of inventory we want to request
-                match item.inv_type {
-                    InventoryType::Transaction | InventoryType::WitnessTransaction => {
-                        // Request transactions (you might want to limit this)
-                        if items_to_request.len() < 10 { // Limit to first 10 transactions
-                            items_to_request.push(item.clone());
-                        }
-                    }
-                    InventoryType::Block | InventoryType::WitnessBlock => {
-                        // Request blocks (be careful, blocks are large!)
-                        if items_to_request.len() < 3 { // Limit to first 3 blocks
-                            items_to_request.push(item.clone());
-                        }
-                    }
-                    InventoryType::CompactBlock => {
-                        // Request compact blocks
-                        items_to_request.push(item.clone());
-                    }
-                    _ => {
-                        // Skip other types for now
-                        println!("     → Skipping {} (not requesting)", item.inv_type.name());
-                    }
+                requested = match item.inv_type {
+                    // Request transactions (you might want to limit this)
+                    InventoryType::Transaction | InventoryType::WitnessTransaction => items_to_request.len() < 10,
+                    // Request blocks (be careful, blocks are large!)
+                    InventoryType::Block | InventoryType::WitnessBlock => items_to_request.len() < 3,
+                    InventoryType::CompactBlock => true,
+                    // Skip other types for now
+                    _ => false,
+                };
+                if requested {
+                    items_to_request.push(item.clone());
                 }
-            } else {
-                println!("     → Already seen this item, skipping");
             }
+            self.log(LogLevel::Trace, Event::InvItem(peer, item.inv_type.name().to_string(), item.hash_hex(), requested));
         }
         
         // Send getdata request for items we want
+        self.log(LogLevel::Debug, Event::GetDataSent(peer, items_to_request.len()));
         if !items_to_request.is_empty() {
-            println!("   📤 Requesting {} items via getdata...", items_to_request.len());
             let getdata_payload = build_getdata_payload(&items_to_request);
             self.send_message("getdata", &getdata_payload)?;
-        } else {
-            println!("   ✅ No new items to request");
         }
         
         Ok(())
@@ -242,7 +268,12 @@ impl BitcoinClient {
             stream.write_all(&message)?;
             stream.flush()?;
             
-            println!("📤 Sent: {} ({} bytes payload)", command, payload.len());
+            let mut status = self.status.lock().unwrap();
+            status.last_send = Some(now_secs());
+            status.bytes_sent += message.len() as u64;
+            status.messages_sent += 1;
+            drop(status);
+            self.log(LogLevel::Debug, Event::MessageSent(self.peer(), command.to_string(), payload.len()));
         }
         
         Ok(())
@@ -267,7 +298,10 @@ impl BitcoinClient {
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid message header"))?;
             
             if header.magic != MAGIC {
-                return Err(Error::new(ErrorKind::InvalidData, "Invalid magic bytes"));
+                return Err(Misbehavior::BadMagic.into_io());
+            }
+            if header.payload_size > MAX_PAYLOAD {
+                return Err(Misbehavior::OversizedPayload.into_io());
             }
             
             let mut payload = vec![0u8; header.payload_size as usize];
@@ -276,16 +310,36 @@ impl BitcoinClient {
                 
                 let computed_checksum = sha256d(&payload);
                 if header.checksum != computed_checksum[0..4] {
-                    return Err(Error::new(ErrorKind::InvalidData, "Invalid checksum"));
+                    return Err(Misbehavior::BadChecksum.into_io());
                 }
             }
             
+            let mut status = self.status.lock().unwrap();
+            status.last_recv = Some(now_secs());
+            status.bytes_recv += (header_buf.len() + payload.len()) as u64;
+            status.messages_recv += 1;
+            drop(status);
             Ok(Some((header, payload)))
         } else {
             Ok(None)
         }
     }
     
+    fn report(&self, what: Misbehavior) {
+        if let (Some(addr), Some(tx)) = (self.connected_addr, &self.log_tx) {
+            report_misbehavior(&self.peer_store, &self.ban_list, tx, addr, what);
+        }
+    }
+
+    fn record_failure(&mut self, stage: FailureStage, reason: String) {
+        if let Some(addr) = self.connected_addr {
+            self.peer_store
+                .lock()
+                .unwrap()
+                .update_peer(addr, &mut |p| p.record_failure(stage, reason.clone(), now_secs()));
+        }
+    }
+
     pub fn soft_stop(&mut self) -> std::io::Result<()> {
         if let Some(ref mut stream) = self.stream {
             let _ = stream.shutdown(std::net::Shutdown::Both);
@@ -296,43 +350,50 @@ impl BitcoinClient {
         self.version_received = false;
         self.verack_received = false;
         self.seen_inventory.clear();
+        self.ping_sent = None;
+        *self.status.lock().unwrap() = ClientStatus::default();
         Ok(())
     } 
    
-    pub fn message_loop_with_channel(&mut self, tx: &Sender<String>, running: &std::sync::Arc<std::sync::atomic::AtomicBool>) -> Result<()>{        let mut getaddr_sent = false;
+    pub fn message_loop(&mut self, running: &std::sync::Arc<std::sync::atomic::AtomicBool>) -> Result<()> {
+        let mut getaddr_sent = false;
         let mut message_count = 0;
         let max_messages = 500000;
 
         loop {
             if !running.load(std::sync::atomic::Ordering::SeqCst) {
-                let _ = tx.send("🛑 Parada solicitada pelo usuário".to_string());
+                self.log(LogLevel::Info, Event::ClientStopped("parada solicitada pelo usuário".into()));
                 break;
             }
             message_count += 1;
             if message_count > max_messages {
-                let _ = tx.send(format!("\n🎯 Processed {} messages, ending demo", max_messages));
+                self.log(LogLevel::Info, Event::ClientStopped(format!("{} mensagens processadas", max_messages)));
                 break;
             }
 
             if !self.is_connection_alive()? {
-                let _ = tx.send("🔌 Connection closed by peer".to_string());
+                self.log(LogLevel::Warn, Event::ClientStopped("conexão encerrada pelo peer".into()));
                 break;
             }
 
             match self.read_message() {
                 Ok(Some((header, payload))) => {
                     let command = header.command_str();
-                    let _ = tx.send(format!("⬇️ Received: {} ({} bytes)", command, payload.len()));
+                    self.log(LogLevel::Debug, Event::MessageReceived(self.peer(), command.clone(), payload.len()));
+                    if !self.handshake_complete && !allowed_before_handshake(&command) {
+                        self.report(Misbehavior::UnsolicitedMessage);
+                    }
 
                     self.handle_message(&command, &payload)?;
 
                     if self.version_received && self.verack_received && !self.handshake_complete {
-                        let _ = tx.send("\n🤝 Handshake complete!".to_string());
+                        self.log(LogLevel::Info, Event::HandshakeComplete(self.peer()));
                         self.handshake_complete = true;
+                        self.status.lock().unwrap().handshake_complete = true;
                     }
                     if self.handshake_complete && !getaddr_sent {
-                        let _ = tx.send("📤 Requesting peer addresses...".to_string());
                         self.send_message("getaddr", &[])?;
+                        self.send_ping()?;
                         getaddr_sent = true;
                     }
                 }
@@ -340,7 +401,13 @@ impl BitcoinClient {
                     std::thread::sleep(Duration::from_millis(100));
                 }
                 Err(e) => {
-                    let _ = tx.send(format!("❌ Error reading message: {}", e));
+                    self.log(LogLevel::Warn, Event::ClientStopped(format!("erro ao ler mensagem: {}", e)));
+                    if let Some(what) = Misbehavior::from_io(&e) {
+                        self.report(what);
+                    }
+                    if !self.handshake_complete {
+                        self.record_failure(FailureStage::Handshake, e.to_string());
+                    }
                     break;
                 }
             }
@@ -348,47 +415,34 @@ impl BitcoinClient {
                 std::thread::sleep(Duration::from_millis(500));
             }
         }
+        // A conexão acabou: getpeerinfo não deve mais mostrá-la
+        *self.status.lock().unwrap() = ClientStatus::default();
         Ok(())
     }
 
     pub async fn _connect_async(&mut self) -> Result<()> {
     let addr_str = "seed.bitcoin.sipa.be:8333";
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Info, Event::Custom(format!("Resolvendo {}", addr_str)));
-    }
+    self.log(LogLevel::Info, Event::Resolving(addr_str.to_string()));
     let socket_addrs: Vec<_> = tokio::net::lookup_host(addr_str).await?.collect();
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Debug, Event::Custom(format!("Endereços resolvidos: {:?}", socket_addrs)));
-    }
+    self.log(LogLevel::Debug, Event::Resolved(addr_str.to_string(), socket_addrs.clone()));
     for addr in &socket_addrs {
-        if let Some(ref tx) = self.log_tx {
-            log(tx, LogLevel::Info, Event::Custom(format!("Tentando conectar em {}", addr)));
-        }
+        self.log(LogLevel::Info, Event::Connecting(*addr));
         match TokioTcpStream::connect(addr).await {
             Ok(_s) => {
-                if let Some(ref tx) = self.log_tx {
-                    log(tx, LogLevel::Info, Event::Connected(*addr));
-                }
+                self.log(LogLevel::Info, Event::Connected(*addr));
                 self.connected_addr = Some(*addr);
                 break;
             }
             Err(e) => {
-                if let Some(ref tx) = self.log_tx {
-                    log(tx, LogLevel::Warn, Event::FailedConnection(*addr, e.to_string()));
-                }
+                self.log(LogLevel::Warn, Event::FailedConnection(*addr, e.to_string()));
                 continue;
             }
         }
     }
     if self.connected_addr.is_none() {
-        if let Some(ref tx) = self.log_tx {
-            log(tx, LogLevel::Error, Event::Custom("Não foi possível conectar a nenhum endereço".into()));
-        }
+        self.log(LogLevel::Error, Event::NoReachableAddress(addr_str.to_string()));
         return Err(Error::new(ErrorKind::ConnectionRefused, "Could not connect to any address"));
     }
-    if let Some(ref tx) = self.log_tx {
-        log(tx, LogLevel::Info, Event::Custom(format!("Conexão estabelecida com {}", self.connected_addr.unwrap())));
-    }
     Ok(())
 }
     
@@ -403,8 +457,11 @@ impl Clone for BitcoinClient {
             version_received: self.version_received,
             verack_received: self.verack_received,
             seen_inventory: self.seen_inventory.clone(),
-            peer_db: self.peer_db.clone(),
+            ping_sent: self.ping_sent,
+            peer_store: self.peer_store.clone(),
+            ban_list: self.ban_list.clone(),
             log_tx: self.log_tx.clone(),
+            status: self.status.clone(),
         }
     }
 }
\ No newline at end of file
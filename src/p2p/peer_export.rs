@@ -0,0 +1,353 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use crate::p2p::banlist::{parse_duration, BanList, SharedBanList};
+use crate::p2p::database::{now_secs, PeerInfo, PeerStatus};
+use crate::p2p::peer_store::{PeerStore, SharedPeerStore};
+
+/// Every `PeerInfo` field, so a CSV round trip loses nothing; `last_failure` and
+/// `uptime` are nested, so their cells hold JSON.
+const CSV_HEADER: &str = "address,status,first_seen,last_seen,last_connected,services,source,user_agent,protocol_version,start_height,ping_ms,misbehavior_score,asn,attempts,last_failure,uptime";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// `.csv` files are CSV, anything else is JSON lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Jsonl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NetworkKind {
+    Ipv4,
+    Ipv6,
+}
+
+/// Accepts the names used in peers.json (`ConnectedRecently`) or kebab-case (`connected-recently`).
+pub fn parse_status(s: &str) -> Result<PeerStatus, String> {
+    match s.replace(['-', '_'], "").to_ascii_lowercase().as_str() {
+        "nevertried" => Ok(PeerStatus::NeverTried),
+        "connectedrecently" => Ok(PeerStatus::ConnectedRecently),
+        "unreachable" => Ok(PeerStatus::Unreachable),
+        "banned" => Ok(PeerStatus::Banned),
+        _ => Err(format!("unknown peer status: {}", s)),
+    }
+}
+
+/// Parses a service bit mask in decimal or `0x` hex.
+pub fn parse_services(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid service flags: {}", s))
+}
+
+/// Which peers an export or import touches. Empty filter matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct PeerFilter {
+    pub status: Option<PeerStatus>,
+    pub network: Option<NetworkKind>,
+    /// Every bit set here must be advertised by the peer.
+    pub services: Option<u64>,
+    /// Peers not seen for longer than this are left out.
+    pub max_age: Option<Duration>,
+}
+
+impl PeerFilter {
+    /// Sets one `key=value` filter (`status`, `net`, `services` or `age`).
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "status" => self.status = Some(parse_status(value)?),
+            "net" => self.network = Some(match value {
+                "ipv4" => NetworkKind::Ipv4,
+                "ipv6" => NetworkKind::Ipv6,
+                _ => return Err(format!("unknown network: {}", value)),
+            }),
+            "services" => self.services = Some(parse_services(value)?),
+            "age" => self.max_age = Some(parse_duration(value).ok_or_else(|| format!("invalid age: {}", value))?),
+            _ => return Err(format!("unknown filter: {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, peer: &PeerInfo, now: u64) -> bool {
+        if self.status.as_ref().is_some_and(|s| *s != peer.status) {
+            return false;
+        }
+        match self.network {
+            Some(NetworkKind::Ipv4) if !peer.address.is_ipv4() => return false,
+            Some(NetworkKind::Ipv6) if !peer.address.is_ipv6() => return false,
+            _ => {}
+        }
+        if let Some(mask) = self.services {
+            if peer.services.unwrap_or(0) & mask != mask {
+                return false;
+            }
+        }
+        if let Some(age) = self.max_age {
+            let seen = peer.last_seen.unwrap_or(0);
+            if now.saturating_sub(seen) > age.as_secs() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Quotes `s` if needed. Control characters are dropped so every record stays on
+/// one line, which is how `import_peers` reads it back.
+fn csv_field(s: &str) -> String {
+    let s: String = s.chars().filter(|c| !c.is_control()).collect();
+    if s.contains([',', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s
+    }
+}
+
+fn opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn json_cell<T: serde::Serialize>(v: &T) -> String {
+    serde_json::to_string(v).unwrap_or_default()
+}
+
+fn parse_json_cell<T: serde::de::DeserializeOwned>(field: &str, name: &str) -> io::Result<Option<T>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(field).map(Some).map_err(|e| invalid(format!("invalid {}: {}", name, e)))
+}
+
+fn csv_row(p: &PeerInfo) -> String {
+    [
+        p.address.to_string(),
+        format!("{:?}", p.status),
+        opt(p.first_seen),
+        opt(p.last_seen),
+        opt(p.last_connected),
+        opt(p.services),
+        opt(p.source),
+        csv_field(p.user_agent.as_deref().unwrap_or("")),
+        opt(p.protocol_version),
+        opt(p.start_height),
+        opt(p.ping_ms),
+        p.misbehavior_score.to_string(),
+        opt(p.asn),
+        p.attempts.to_string(),
+        csv_field(&p.last_failure.as_ref().map(json_cell).unwrap_or_default()),
+        csv_field(&json_cell(&p.uptime)),
+    ]
+    .join(",")
+}
+
+/// Splits one CSV line, honouring quoted fields with `""` escapes.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+fn parse_opt<T: std::str::FromStr>(field: &str, name: &str) -> io::Result<Option<T>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field.parse().map(Some).map_err(|_| invalid(format!("invalid {}: {}", name, field)))
+}
+
+fn parse_csv_row(line: &str) -> io::Result<PeerInfo> {
+    let f = split_csv(line);
+    if f.len() != CSV_HEADER.split(',').count() {
+        return Err(invalid(format!("expected {} columns, got {}", CSV_HEADER.split(',').count(), f.len())));
+    }
+    let address: SocketAddr = f[0].parse().map_err(|_| invalid(format!("invalid address: {}", f[0])))?;
+    let status = parse_status(&f[1]).map_err(invalid)?;
+    let mut peer = PeerInfo::new(address, status, 0);
+    peer.first_seen = parse_opt(&f[2], "first_seen")?;
+    peer.last_seen = parse_opt(&f[3], "last_seen")?;
+    peer.last_connected = parse_opt(&f[4], "last_connected")?;
+    peer.services = parse_opt(&f[5], "services")?;
+    peer.source = parse_opt(&f[6], "source")?;
+    peer.user_agent = Some(f[7].clone()).filter(|ua| !ua.is_empty());
+    peer.protocol_version = parse_opt(&f[8], "protocol_version")?;
+    peer.start_height = parse_opt(&f[9], "start_height")?;
+    peer.ping_ms = parse_opt(&f[10], "ping_ms")?;
+    peer.misbehavior_score = parse_opt(&f[11], "misbehavior_score")?.unwrap_or(0);
+    peer.asn = parse_opt(&f[12], "asn")?;
+    peer.attempts = parse_opt(&f[13], "attempts")?.unwrap_or(0);
+    peer.last_failure = parse_json_cell(&f[14], "last_failure")?;
+    peer.uptime = parse_json_cell(&f[15], "uptime")?.unwrap_or_default();
+    Ok(peer)
+}
+
+/// Writes the peers matching `filter`, one per line. Returns how many were written.
+pub fn export_peers(
+    peers: &[PeerInfo],
+    format: ExportFormat,
+    filter: &PeerFilter,
+    out: &mut dyn Write,
+) -> io::Result<usize> {
+    let now = now_secs();
+    if format == ExportFormat::Csv {
+        writeln!(out, "{}", CSV_HEADER)?;
+    }
+    let mut written = 0;
+    for peer in peers.iter().filter(|p| filter.matches(p, now)) {
+        match format {
+            ExportFormat::Csv => writeln!(out, "{}", csv_row(peer))?,
+            ExportFormat::Jsonl => writeln!(out, "{}", serde_json::to_string(peer)?)?,
+        }
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub updated: usize,
+    /// Rows left out by the filter, the ban list or the store (no room for them).
+    pub skipped: usize,
+}
+
+fn newest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    a.max(b)
+}
+
+/// Folds a record from another database into ours. Timestamps keep the most
+/// recent (or for `first_seen`, the oldest) value; everything else comes from
+/// whichever side saw the peer last.
+fn merge_into(ours: &mut PeerInfo, theirs: &PeerInfo) {
+    let theirs_newer = theirs.last_seen > ours.last_seen;
+    ours.first_seen = match (ours.first_seen, theirs.first_seen) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    ours.last_seen = newest(ours.last_seen, theirs.last_seen);
+    ours.last_connected = newest(ours.last_connected, theirs.last_connected);
+    ours.misbehavior_score = ours.misbehavior_score.max(theirs.misbehavior_score);
+    if theirs_newer {
+        // Um ban nosso nunca é desfeito por um import
+        if ours.status != PeerStatus::Banned {
+            ours.status = theirs.status.clone();
+        }
+        ours.services = theirs.services.or(ours.services);
+        ours.user_agent = theirs.user_agent.clone().or(ours.user_agent.take());
+        ours.protocol_version = theirs.protocol_version.or(ours.protocol_version);
+        ours.start_height = theirs.start_height.or(ours.start_height);
+        ours.ping_ms = theirs.ping_ms.or(ours.ping_ms);
+        ours.asn = theirs.asn.or(ours.asn);
+        ours.attempts = theirs.attempts;
+        ours.last_failure = theirs.last_failure.clone();
+        ours.uptime = theirs.uptime.clone();
+    }
+    ours.source = ours.source.or(theirs.source);
+}
+
+/// Reads peers in `format` and merges those matching `filter` into `store`.
+/// Blank lines are skipped; a malformed line aborts with its line number.
+pub fn import_peers(
+    input: &mut dyn BufRead,
+    format: ExportFormat,
+    filter: &PeerFilter,
+    store: &mut dyn PeerStore,
+    bans: &BanList,
+) -> io::Result<MergeSummary> {
+    let now = now_secs();
+    let mut summary = MergeSummary::default();
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || (format == ExportFormat::Csv && line.starts_with("address,")) {
+            continue;
+        }
+        let incoming = match format {
+            ExportFormat::Csv => parse_csv_row(&line),
+            ExportFormat::Jsonl => serde_json::from_str::<PeerInfo>(&line).map_err(|e| invalid(e.to_string())),
+        }
+        .map_err(|e| invalid(format!("line {}: {}", n + 1, e)))?;
+
+        if !filter.matches(&incoming, now) || bans.is_banned(&incoming.address) {
+            summary.skipped += 1;
+            continue;
+        }
+        let exists = store.get_peer(&incoming.address).is_some();
+        store.update_peer(incoming.address, &mut |p| {
+            if exists {
+                merge_into(p, &incoming);
+            } else {
+                *p = incoming.clone();
+            }
+        });
+        if exists {
+            summary.updated += 1;
+        } else if store.get_peer(&incoming.address).is_some() {
+            summary.added += 1;
+        } else {
+            summary.skipped += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Exports the shared store to `path`, or to stdout when `path` is `None`.
+pub fn export_to_file(
+    store: &SharedPeerStore,
+    path: Option<&Path>,
+    format: ExportFormat,
+    filter: &PeerFilter,
+) -> io::Result<usize> {
+    let peers = store.lock().unwrap().all_peers();
+    match path {
+        Some(path) => export_peers(&peers, format, filter, &mut BufWriter::new(File::create(path)?)),
+        None => export_peers(&peers, format, filter, &mut io::stdout().lock()),
+    }
+}
+
+/// Imports `path` (`-` for stdin) into the shared store and persists it.
+pub fn import_from_file(
+    store: &SharedPeerStore,
+    bans: &SharedBanList,
+    path: &Path,
+    format: ExportFormat,
+    filter: &PeerFilter,
+) -> io::Result<MergeSummary> {
+    let mut input: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let bans = bans.lock().unwrap();
+    let mut db = store.lock().unwrap();
+    let summary = import_peers(&mut *input, format, filter, &mut **db, &bans)?;
+    db.persist()?;
+    Ok(summary)
+}
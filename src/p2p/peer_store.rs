@@ -0,0 +1,187 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use clap::ValueEnum;
+use std::sync::mpsc::Sender;
+use crate::p2p::database::{PeerDatabase, PeerInfo, PeerStatus, LoadReport};
+use crate::p2p::sqlite_store::SqlitePeerStore;
+use crate::p2p::log::{log_as, Component, Event, LogLevel, LogMessage};
+
+/// Storage backend for everything the seeder knows about peers.
+///
+/// The client, the crawler and the DNS server only talk to this trait, so the
+/// backend can be swapped with `--db` without touching them.
+pub trait PeerStore: Send {
+    /// Adds a gossiped address, remembering which peer (`source`) told us about it.
+    fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>);
+    fn update_status(&mut self, addr: SocketAddr, status: PeerStatus);
+    /// Applies `f` to the peer's entry, creating it first if it is unknown
+    /// (`AddrManStore` may refuse to create it, as it does for gossip).
+    fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo));
+    fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo>;
+    /// Peers we connected to recently, i.e. the ones worth handing out.
+    fn good_peers(&self) -> Vec<PeerInfo>;
+    fn all_peers(&self) -> Vec<PeerInfo>;
+    /// Forgets the peer entirely; returns whether it was known.
+    fn remove_peer(&mut self, addr: &SocketAddr) -> bool;
+    fn ban_peer(&mut self, addr: SocketAddr) {
+        self.update_status(addr, PeerStatus::Banned);
+    }
+    /// Flushes pending state to durable storage (no-op for volatile stores).
+    fn persist(&mut self) -> io::Result<()>;
+    fn len(&self) -> usize;
+}
+
+pub type SharedPeerStore = Arc<Mutex<Box<dyn PeerStore>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DbBackend {
+    /// Keep peers in memory only (lost on exit)
+    Memory,
+    /// HashMap persisted as a JSON file
+    Json,
+    /// SQLite database file
+    Sqlite,
+}
+
+impl DbBackend {
+    pub fn default_path(&self) -> PathBuf {
+        match self {
+            DbBackend::Memory => PathBuf::new(),
+            DbBackend::Json => PathBuf::from("peers.json"),
+            DbBackend::Sqlite => PathBuf::from("peers.sqlite"),
+        }
+    }
+}
+
+pub fn open_store(backend: DbBackend, path: &Path, log_tx: &Sender<LogMessage>) -> io::Result<Box<dyn PeerStore>> {
+    match backend {
+        DbBackend::Memory => Ok(Box::new(MemoryPeerStore::default())),
+        DbBackend::Json => {
+            let (store, report) = JsonPeerStore::open(path)?;
+            report_load(log_tx, path, &report);
+            Ok(Box::new(store))
+        }
+        DbBackend::Sqlite => Ok(Box::new(SqlitePeerStore::open(path)?)),
+    }
+}
+
+fn report_load(log_tx: &Sender<LogMessage>, path: &Path, report: &LoadReport) {
+    for (file, reason) in &report.corrupt {
+        log_as(log_tx, Component::Db, LogLevel::Error, Event::DbCorrupt(file.display().to_string(), reason.clone()));
+    }
+    match &report.source {
+        Some(source) if source != path => log_as(
+            log_tx,
+            Component::Db,
+            LogLevel::Warn,
+            Event::DbRestored(source.display().to_string()),
+        ),
+        Some(_) => {}
+        None => log_as(log_tx, Component::Db, LogLevel::Info, Event::DbMissing(path.display().to_string())),
+    }
+}
+
+pub fn shared(store: Box<dyn PeerStore>) -> SharedPeerStore {
+    Arc::new(Mutex::new(store))
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryPeerStore {
+    db: PeerDatabase,
+}
+
+impl PeerStore for MemoryPeerStore {
+    fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+        self.db.register_peer(addr, services, source);
+    }
+
+    fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+        self.db.update_status(addr, status);
+    }
+
+    fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+        self.db.update_peer(addr, f);
+    }
+
+    fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo> {
+        self.db.peers.get(addr).cloned()
+    }
+
+    fn good_peers(&self) -> Vec<PeerInfo> {
+        self.db.good_peers()
+    }
+
+    fn all_peers(&self) -> Vec<PeerInfo> {
+        self.db.peers.values().cloned().collect()
+    }
+
+    fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+        self.db.peers.remove(addr).is_some()
+    }
+
+    fn persist(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.db.peers.len()
+    }
+}
+
+/// The original `peers.json` format: the whole `PeerDatabase` rewritten on persist.
+#[derive(Debug)]
+pub struct JsonPeerStore {
+    inner: MemoryPeerStore,
+    path: PathBuf,
+}
+
+impl JsonPeerStore {
+    pub fn open(path: &Path) -> io::Result<(Self, LoadReport)> {
+        let (db, report) = PeerDatabase::load_from_file(path)?;
+        let store = JsonPeerStore {
+            inner: MemoryPeerStore { db },
+            path: path.to_path_buf(),
+        };
+        Ok((store, report))
+    }
+}
+
+impl PeerStore for JsonPeerStore {
+    fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+        self.inner.register_peer(addr, services, source);
+    }
+
+    fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+        self.inner.update_status(addr, status);
+    }
+
+    fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+        self.inner.update_peer(addr, f);
+    }
+
+    fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo> {
+        self.inner.get_peer(addr)
+    }
+
+    fn good_peers(&self) -> Vec<PeerInfo> {
+        self.inner.good_peers()
+    }
+
+    fn all_peers(&self) -> Vec<PeerInfo> {
+        self.inner.all_peers()
+    }
+
+    fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+        self.inner.remove_peer(addr)
+    }
+
+    fn persist(&mut self) -> io::Result<()> {
+        self.inner.db.save_to_file(&self.path)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -0,0 +1,117 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use crate::p2p::banlist::Subnet;
+use crate::p2p::database::{now_secs, PeerInfo, PeerStatus};
+use crate::p2p::log::{log, Event, LogLevel, LogMessage};
+use crate::p2p::peer_store::{PeerStore, SharedPeerStore};
+
+/// When a peer stops being worth remembering. Zero disables a rule.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Drop peers after this many failed attempts in a row.
+    pub max_attempts: u32,
+    /// Drop peers we haven't reached for this long.
+    pub max_age: Duration,
+    /// Keep at most this many peers per netgroup (/16 for IPv4, /32 for IPv6).
+    pub max_per_netgroup: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneSummary {
+    pub failed: usize,
+    pub stale: usize,
+    pub over_netgroup: usize,
+    pub remaining: usize,
+}
+
+impl PruneSummary {
+    pub fn total(&self) -> usize {
+        self.failed + self.stale + self.over_netgroup
+    }
+}
+
+/// Same grouping Bitcoin Core uses to spread its addrman buckets.
+pub fn netgroup(ip: IpAddr) -> Subnet {
+    let prefix = if ip.is_ipv4() { 16 } else { 32 };
+    Subnet::new(ip, prefix).expect("prefix fits both address families")
+}
+
+/// Peers that matter most stay when a netgroup is over its limit.
+fn keep_priority(p: &PeerInfo) -> impl Ord {
+    (p.status == PeerStatus::ConnectedRecently, p.last_connected, p.last_seen)
+}
+
+/// Start of the period `max_age` is measured over: the last successful connection.
+/// `last_seen` is only used for peers never tried, since every failed attempt
+/// refreshes it; a peer that failed without ever answering counts from when we learned of it.
+fn last_success(p: &PeerInfo) -> u64 {
+    match p.last_connected {
+        Some(at) => at,
+        None if p.status == PeerStatus::NeverTried => p.last_seen.unwrap_or(0),
+        None => p.first_seen.or(p.last_seen).unwrap_or(0),
+    }
+}
+
+/// Removes peers the policy says to forget. Banned peers are never touched,
+/// so the reason they were banned stays visible.
+pub fn prune(store: &mut dyn PeerStore, policy: &RetentionPolicy, now: u64) -> PruneSummary {
+    let mut summary = PruneSummary::default();
+    let mut evict: Vec<SocketAddr> = Vec::new();
+    let mut groups: HashMap<Subnet, Vec<PeerInfo>> = HashMap::new();
+
+    for peer in store.all_peers() {
+        if peer.status == PeerStatus::Banned {
+            continue;
+        }
+        let age = now.saturating_sub(last_success(&peer));
+        // `attempts` volta a zero a cada sucesso: são as falhas desde o último contato
+        if policy.max_attempts > 0 && peer.attempts >= policy.max_attempts {
+            summary.failed += 1;
+            evict.push(peer.address);
+        } else if !policy.max_age.is_zero() && age > policy.max_age.as_secs() {
+            summary.stale += 1;
+            evict.push(peer.address);
+        } else {
+            groups.entry(netgroup(peer.address.ip())).or_default().push(peer);
+        }
+    }
+
+    if policy.max_per_netgroup > 0 {
+        for peers in groups.values_mut() {
+            if peers.len() > policy.max_per_netgroup {
+                peers.sort_by_key(|p| Reverse(keep_priority(p)));
+                for p in peers.drain(policy.max_per_netgroup..) {
+                    summary.over_netgroup += 1;
+                    evict.push(p.address);
+                }
+            }
+        }
+    }
+
+    for addr in &evict {
+        store.remove_peer(addr);
+    }
+    summary.remaining = store.len();
+    summary
+}
+
+/// Prunes the shared store, persists it if anything changed and logs the summary.
+pub fn prune_and_log(
+    store: &SharedPeerStore,
+    policy: &RetentionPolicy,
+    log_tx: &Sender<LogMessage>,
+) -> io::Result<PruneSummary> {
+    let mut db = store.lock().unwrap();
+    let summary = prune(&mut **db, policy, now_secs());
+    if summary.total() > 0 {
+        db.persist()?;
+        log(log_tx, LogLevel::Info, Event::Pruned(
+            summary.failed, summary.stale, summary.over_netgroup, summary.remaining,
+        ));
+    }
+    Ok(summary)
+}
@@ -0,0 +1,394 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::p2p::banlist::{ban_subnet, clear_banned, parse_duration, unban_subnet, Subnet, DEFAULT_BAN_TIME, MAX_BAN_TIME};
+use crate::p2p::database::PeerStatus;
+use crate::p2p::http::{read_request, serve, write_response, write_response_with};
+use crate::p2p::log::{log, Event, LogLevel};
+use crate::p2p::peer_export::PeerFilter;
+use crate::p2p::rpc_auth::{Denied, RpcAuth, REALM};
+use crate::p2p::services::{Services, DEFAULT_CRAWL_PEERS};
+
+/// Port `addnode` assumes when given a bare IP.
+const DEFAULT_P2P_PORT: u16 = 8333;
+
+/// Page size of `listpeers` when the caller doesn't give one.
+const DEFAULT_PAGE: usize = 100;
+
+/// Pause after a wrong password, as Bitcoin Core does, to slow down guessing.
+const AUTH_FAILURE_DELAY: Duration = Duration::from_millis(250);
+
+/// The services the RPC methods act on, the same ones `InteractiveCli` uses,
+/// and who may call them over HTTP.
+#[derive(Clone)]
+pub struct RpcContext {
+    pub services: Services,
+    pub auth: RpcAuth,
+    stopping: Arc<AtomicBool>,
+}
+
+impl RpcContext {
+    pub fn new(services: Services, auth: RpcAuth) -> Self {
+        RpcContext { services, auth, stopping: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Stops the client and the crawl, writes the databases and lets the main
+    /// thread return (see `Services::shutdown`).
+    fn shutdown(&self) {
+        self.services.shutdown("stop via RPC");
+        self.auth.remove_cookie();
+    }
+}
+
+/// A JSON-RPC 2.0 error object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// Generic application error, as Bitcoin Core's `RPC_MISC_ERROR`.
+    pub const MISC_ERROR: i64 = -1;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into() }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        RpcError::new(RpcError::INVALID_PARAMS, message)
+    }
+
+    pub fn misc(message: impl Into<String>) -> Self {
+        RpcError::new(RpcError::MISC_ERROR, message)
+    }
+}
+
+impl From<io::Error> for RpcError {
+    fn from(e: io::Error) -> Self {
+        RpcError::new(RpcError::INTERNAL_ERROR, e.to_string())
+    }
+}
+
+/// Positional (`[..]`) or named (`{..}`) parameter.
+fn param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Array(values) => values.get(index),
+        Value::Object(map) => map.get(name),
+        _ => None,
+    }
+    .filter(|v| !v.is_null())
+}
+
+fn parse_node(s: &str) -> Option<SocketAddr> {
+    s.parse().ok().or_else(|| s.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, DEFAULT_P2P_PORT)))
+}
+
+/// Named-only parameter, for methods whose options have no natural order.
+fn named<'a>(params: &'a Value, name: &str) -> Option<&'a Value> {
+    params.get(name).filter(|v| !v.is_null())
+}
+
+fn str_param<'a>(params: &'a Value, index: usize, name: &str) -> Result<Option<&'a str>, RpcError> {
+    match param(params, index, name) {
+        None => Ok(None),
+        Some(v) => v.as_str().map(Some).ok_or_else(|| RpcError::invalid_params(format!("{} must be a string", name))),
+    }
+}
+
+fn as_usize(value: Option<&Value>, name: &str) -> Result<Option<usize>, RpcError> {
+    match value {
+        None => Ok(None),
+        Some(v) => v
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| RpcError::invalid_params(format!("{} must be a non-negative integer", name))),
+    }
+}
+
+/// Parameters a method takes, in positional order; `None` for unknown methods.
+fn param_names(method: &str) -> Option<&'static [&'static str]> {
+    Some(match method {
+        "addnode" => &["node"],
+        "listpeers" => &["status", "net", "services", "age", "offset", "limit"],
+        "setban" => &["subnet", "command", "bantime", "reason"],
+        "startcrawl" => &["limit"],
+        "setloglevel" => &["filter"],
+        "stop" | "getinfo" | "getpeerinfo" | "listbanned" | "clearbanned" | "stopcrawl" | "getcrawlstatus"
+        | "getdnsstats" => &[],
+        _ => return None,
+    })
+}
+
+/// Refuses names a method doesn't know and extra positional values, instead of ignoring them.
+fn check_params(method: &str, params: &Value) -> Result<(), RpcError> {
+    let Some(names) = param_names(method) else { return Ok(()) };
+    // `listpeers` só aceita parâmetros nomeados
+    let positional = if method == "listpeers" { 0 } else { names.len() };
+    match params {
+        Value::Object(map) => match map.keys().find(|k| !names.contains(&k.as_str())) {
+            Some(key) => Err(RpcError::invalid_params(format!("unknown parameter for {}: {}", method, key))),
+            None => Ok(()),
+        },
+        Value::Array(values) if values.len() > positional => {
+            Err(RpcError::invalid_params(format!("too many parameters for {}", method)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Runs one method; `stop` only flags the shutdown, which happens after the answer is sent.
+pub fn dispatch(ctx: &RpcContext, method: &str, params: &Value) -> Result<Value, RpcError> {
+    let s = &ctx.services;
+    log(&s.log_tx, LogLevel::Debug, Event::RpcCall(method.to_string()));
+    check_params(method, params)?;
+    match method {
+        "stop" => {
+            ctx.stopping.store(true, Ordering::SeqCst);
+            Ok(json!("Seeder stopping"))
+        }
+        "getinfo" => Ok(get_info(s)),
+        "addnode" => {
+            let node = str_param(params, 0, "node")?.ok_or_else(|| RpcError::invalid_params("expected node address"))?;
+            let addr = parse_node(node).ok_or_else(|| RpcError::invalid_params(format!("invalid address: {}", node)))?;
+            s.queue_node(addr).map_err(RpcError::invalid_params)?;
+            Ok(Value::Null)
+        }
+        "getpeerinfo" => Ok(get_peer_info(s)),
+        "listpeers" => list_peers(s, params),
+        "setban" => set_ban(s, params),
+        "listbanned" => {
+            let bans = s.ban_list.lock().unwrap().list()?;
+            Ok(bans
+                .into_iter()
+                .map(|b| json!({ "subnet": b.subnet.to_string(), "created": b.created, "until": b.until, "reason": b.reason }))
+                .collect())
+        }
+        "clearbanned" => Ok(json!(clear_banned(&s.ban_list, &s.peer_store)?)),
+        "startcrawl" => {
+            let limit = as_usize(param(params, 0, "limit"), "limit")?.unwrap_or(DEFAULT_CRAWL_PEERS);
+            let peers = s.start_crawl(limit).map_err(RpcError::misc)?;
+            Ok(json!({ "peers": peers }))
+        }
+        "stopcrawl" => {
+            if !s.stop_crawl() {
+                return Err(RpcError::misc("no crawl is running"));
+            }
+            Ok(Value::Null)
+        }
+        "getcrawlstatus" => Ok(get_crawl_status(s)),
+        "getdnsstats" => {
+            let (by_type, malformed) = s.metrics.dns_stats();
+            let queries: u64 = by_type.iter().map(|(_, _, n)| n).sum();
+            let by_type: Vec<_> =
+                by_type.into_iter().map(|(qtype, rcode, count)| json!({ "type": qtype, "rcode": rcode, "count": count })).collect();
+            Ok(json!({ "queries": queries, "by_type": by_type, "malformed": malformed }))
+        }
+        "setloglevel" => {
+            let filter = s.set_log_filter(str_param(params, 0, "filter")?).map_err(RpcError::invalid_params)?;
+            Ok(json!({ "filter": filter }))
+        }
+        _ => Err(RpcError::new(RpcError::METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+fn get_info(s: &Services) -> Value {
+    let peers = s.peer_store.lock().unwrap().all_peers();
+    let mut by_status = serde_json::Map::new();
+    for status in PeerStatus::ALL {
+        by_status.insert(status.name().into(), json!(peers.iter().filter(|p| p.status == status).count()));
+    }
+    let uptime = s.started.elapsed().as_secs();
+    let (attempts, successes) = s.metrics.crawl_totals();
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime": uptime,
+        "peers": peers.len(),
+        "peers_by_status": by_status,
+        "crawl": {
+            "attempts": attempts,
+            "successes": successes,
+            "per_minute": attempts as f64 * 60.0 / uptime.max(1) as f64,
+            "queued": s.crawl.queue.lock().unwrap().len(),
+        },
+        "dns_queries": s.metrics.dns_query_count(),
+    })
+}
+
+/// The client's live connection, as an array like Bitcoin Core's (empty when disconnected).
+fn get_peer_info(s: &Services) -> Value {
+    let Some((status, known)) = s.client_peer() else {
+        return json!([]);
+    };
+    let mut entry = serde_json::to_value(&status).unwrap_or_default();
+    if let (Some(peer), Value::Object(map)) = (known, &mut entry) {
+        map.insert("services".into(), json!(peer.services));
+        map.insert("protocol_version".into(), json!(peer.protocol_version));
+        map.insert("user_agent".into(), json!(peer.user_agent));
+        map.insert("start_height".into(), json!(peer.start_height));
+        map.insert("asn".into(), json!(peer.asn));
+    }
+    json!([entry])
+}
+
+/// `listpeers {status, net, services, age, offset, limit}`; filters take the `peers` command syntax.
+fn list_peers(s: &Services, params: &Value) -> Result<Value, RpcError> {
+    let mut filter = PeerFilter::default();
+    for key in ["status", "net", "services", "age"] {
+        if let Some(value) = named(params, key) {
+            let value = match value {
+                Value::String(v) => v.clone(),
+                other => other.to_string(),
+            };
+            filter.set(key, &value).map_err(RpcError::invalid_params)?;
+        }
+    }
+    let offset = as_usize(named(params, "offset"), "offset")?.unwrap_or(0);
+    let limit = as_usize(named(params, "limit"), "limit")?.unwrap_or(DEFAULT_PAGE);
+    let (total, peers) = s.list_peers(&filter, offset, limit);
+    Ok(json!({ "total": total, "offset": offset, "peers": peers }))
+}
+
+/// `setban subnet add|remove [bantime] [reason]`; bantime is seconds, a duration like `12h`, or `permanent`.
+fn set_ban(s: &Services, params: &Value) -> Result<Value, RpcError> {
+    let subnet: Subnet = str_param(params, 0, "subnet")?
+        .ok_or_else(|| RpcError::invalid_params("expected subnet"))?
+        .parse()
+        .map_err(|e| RpcError::invalid_params(format!("invalid subnet: {}", e)))?;
+    match str_param(params, 1, "command")? {
+        Some("add") => {
+            let duration = match param(params, 2, "bantime") {
+                None => Some(DEFAULT_BAN_TIME),
+                Some(Value::String(v)) if v == "permanent" => None,
+                Some(Value::String(v)) => {
+                    Some(parse_duration(v).ok_or_else(|| RpcError::invalid_params(format!("invalid bantime: {}", v)))?)
+                }
+                Some(v) => Some(Duration::from_secs(v.as_u64().ok_or_else(|| RpcError::invalid_params("invalid bantime"))?)),
+            };
+            if duration.is_some_and(|d| d.is_zero() || d > MAX_BAN_TIME) {
+                return Err(RpcError::invalid_params("bantime must be between 1 second and 100 years, or \"permanent\""));
+            }
+            let reason = str_param(params, 3, "reason")?.unwrap_or("manual");
+            Ok(json!({ "affected": ban_subnet(&s.ban_list, &s.peer_store, subnet, duration, reason)? }))
+        }
+        Some("remove") => {
+            if !unban_subnet(&s.ban_list, &s.peer_store, &subnet)? {
+                return Err(RpcError::misc(format!("{} is not banned", subnet)));
+            }
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::invalid_params("command must be \"add\" or \"remove\"")),
+    }
+}
+
+fn get_crawl_status(s: &Services) -> Value {
+    let mut status = serde_json::to_value(&*s.crawl.state.lock().unwrap()).unwrap_or_default();
+    let (attempts, successes) = s.metrics.crawl_totals();
+    if let Value::Object(map) = &mut status {
+        map.insert("queued".into(), json!(s.crawl.queue.lock().unwrap().len()));
+        map.insert("connected".into(), json!(s.crawl.connected.lock().unwrap().len()));
+        map.insert("attempts".into(), json!(attempts));
+        map.insert("successes".into(), json!(successes));
+    }
+    status
+}
+
+fn error_object(e: &RpcError) -> Value {
+    json!({ "code": e.code, "message": e.message })
+}
+
+/// Answers one request object; `None` for notifications (no `id`).
+fn handle_call(ctx: &RpcContext, call: &Value) -> Option<Value> {
+    let (Some("2.0"), Some(method)) = (call.get("jsonrpc").and_then(Value::as_str), call.get("method").and_then(Value::as_str)) else {
+        let e = RpcError::new(RpcError::INVALID_REQUEST, "Invalid Request");
+        return Some(json!({ "jsonrpc": "2.0", "error": error_object(&e), "id": call.get("id").cloned().unwrap_or(Value::Null) }));
+    };
+    let result = dispatch(ctx, method, call.get("params").unwrap_or(&Value::Null));
+    let id = call.get("id")?.clone();
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(e) => json!({ "jsonrpc": "2.0", "error": error_object(&e), "id": id }),
+    })
+}
+
+/// Handles a request body, single call or batch; `None` when there is nothing to send back.
+pub fn handle_body(ctx: &RpcContext, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(v) => v,
+        Err(e) => {
+            let e = RpcError::new(RpcError::PARSE_ERROR, e.to_string());
+            return Some(json!({ "jsonrpc": "2.0", "error": error_object(&e), "id": null }));
+        }
+    };
+    match request {
+        Value::Array(calls) if !calls.is_empty() => {
+            let answers: Vec<_> = calls.iter().filter_map(|c| handle_call(ctx, c)).collect();
+            (!answers.is_empty()).then_some(Value::Array(answers))
+        }
+        call => handle_call(ctx, &call),
+    }
+}
+
+/// Serves JSON-RPC 2.0 over HTTP POST on `addr` from a background thread.
+pub fn serve_rpc(addr: SocketAddr, ctx: RpcContext) -> io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    Ok(thread::spawn(move || {
+        serve(listener, move |stream| {
+            let _ = answer(stream, &ctx);
+            // Só uma conexão faz o desligamento, mesmo com dois `stop` ao mesmo tempo
+            if ctx.stopping.swap(false, Ordering::SeqCst) {
+                ctx.shutdown();
+            }
+        })
+    }))
+}
+
+fn answer(mut stream: TcpStream, ctx: &RpcContext) -> io::Result<()> {
+    let client = stream.peer_addr()?;
+    // Endereço fora da lista não chega a mandar nada: nem o pedido é lido
+    if !ctx.auth.allows(client.ip()) {
+        return forbidden(&mut stream, ctx, client);
+    }
+    let request = read_request(&stream)?;
+    match ctx.auth.check(client.ip(), request.header("Authorization")) {
+        Ok(()) => {}
+        Err(Denied::Forbidden) => return forbidden(&mut stream, ctx, client),
+        Err(Denied::Unauthorized) => {
+            log(&ctx.services.log_tx, LogLevel::Warn, Event::RpcDenied(client, 401));
+            // Cada conexão tem sua thread (http::serve): a espera só atrasa quem errou a senha
+            thread::sleep(AUTH_FAILURE_DELAY);
+            let challenge = format!("Basic realm=\"{}\"", REALM);
+            return write_response_with(
+                &mut stream,
+                "401 Unauthorized",
+                &[("WWW-Authenticate", &challenge)],
+                "text/plain",
+                "unauthorized\n",
+            );
+        }
+    }
+    if request.path != "/" {
+        return write_response(&mut stream, "404 Not Found", "text/plain", "not found\n");
+    }
+    if request.method != "POST" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", "JSON-RPC requires POST\n");
+    }
+    match handle_body(ctx, &request.body) {
+        Some(answer) => write_response(&mut stream, "200 OK", "application/json", &answer.to_string()),
+        None => write_response(&mut stream, "204 No Content", "application/json", ""),
+    }
+}
+
+fn forbidden(stream: &mut TcpStream, ctx: &RpcContext, client: SocketAddr) -> io::Result<()> {
+    log(&ctx.services.log_tx, LogLevel::Warn, Event::RpcDenied(client, 403));
+    write_response(stream, "403 Forbidden", "text/plain", "forbidden\n")
+}
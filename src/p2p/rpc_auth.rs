@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::p2p::atomic_file::write_private;
+use crate::p2p::banlist::Subnet;
+
+/// User name of the cookie credentials, as in Bitcoin Core.
+pub const COOKIE_USER: &str = "__cookie__";
+
+/// Realm sent with `401 Unauthorized`.
+pub const REALM: &str = "jsonrpc";
+
+/// One accepted user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credential {
+    /// `--rpc-user`/`--rpc-password` or the cookie.
+    Password { user: String, password: String },
+    /// `--rpc-auth USER:SALT$HASH`, where HASH is hex HMAC-SHA256(key = SALT, message = password).
+    Salted { user: String, salt: String, hash: Vec<u8> },
+}
+
+impl Credential {
+    /// Parses the `rpcauth` format of Bitcoin Core's `share/rpcauth/rpcauth.py`.
+    pub fn parse_rpcauth(spec: &str) -> Result<Credential, String> {
+        let invalid = || format!("invalid rpcauth (expected USER:SALT$HASH): {}", spec);
+        let (user, rest) = spec.split_once(':').ok_or_else(invalid)?;
+        let (salt, hash) = rest.split_once('$').ok_or_else(invalid)?;
+        let hash = hex::decode(hash).ok().filter(|h| h.len() == 32).ok_or_else(invalid)?;
+        if user.is_empty() || salt.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Credential::Salted { user: user.to_string(), salt: salt.to_string(), hash })
+    }
+
+    pub fn user(&self) -> &str {
+        match self {
+            Credential::Password { user, .. } | Credential::Salted { user, .. } => user,
+        }
+    }
+
+    fn accepts(&self, password: &str) -> bool {
+        match self {
+            Credential::Password { password: expected, .. } => {
+                // Compara sem atalho para não vazar o prefixo certo pelo tempo de resposta
+                expected.len() == password.len()
+                    && expected.bytes().zip(password.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+            }
+            Credential::Salted { salt, hash, .. } => salted_hash(salt, password).verify_slice(hash).is_ok(),
+        }
+    }
+}
+
+fn salted_hash(salt: &str, password: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC aceita chave de qualquer tamanho");
+    mac.update(password.as_bytes());
+    mac
+}
+
+/// Why a request was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// Source address outside `--rpc-allowip`: 403.
+    Forbidden,
+    /// Missing or wrong credentials: 401.
+    Unauthorized,
+}
+
+/// Who may call the RPC server: source subnets and credentials.
+#[derive(Debug, Clone)]
+pub struct RpcAuth {
+    credentials: Vec<Credential>,
+    allow: Vec<Subnet>,
+    cookie: Option<PathBuf>,
+}
+
+impl Default for RpcAuth {
+    /// Loopback only, no credentials (so every call is refused until some are added).
+    fn default() -> Self {
+        RpcAuth {
+            credentials: Vec::new(),
+            allow: vec!["127.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()],
+            cookie: None,
+        }
+    }
+}
+
+impl RpcAuth {
+    /// Also accept calls from `subnet`; loopback is always allowed.
+    pub fn allow(&mut self, subnet: Subnet) {
+        self.allow.push(subnet);
+    }
+
+    pub fn add(&mut self, credential: Credential) {
+        self.credentials.push(credential);
+    }
+
+    /// Writes `__cookie__:<random>` to `path` and accepts it until `remove_cookie`.
+    pub fn write_cookie(&mut self, path: &Path) -> io::Result<()> {
+        let secret: [u8; 32] = rand::random();
+        let password = hex::encode(secret);
+        write_private(path, format!("{}:{}", COOKIE_USER, password).as_bytes())?;
+        self.credentials.push(Credential::Password { user: COOKIE_USER.to_string(), password });
+        self.cookie = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Deletes the cookie file, if one was written; called on shutdown.
+    pub fn remove_cookie(&self) {
+        if let Some(path) = &self.cookie {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Whether `source` may call at all, credentials aside.
+    pub fn allows(&self, source: IpAddr) -> bool {
+        self.allow.iter().any(|s| s.contains(source))
+    }
+
+    /// Checks the source address, then the `Authorization` header.
+    pub fn check(&self, source: IpAddr, authorization: Option<&str>) -> Result<(), Denied> {
+        if !self.allows(source) {
+            return Err(Denied::Forbidden);
+        }
+        let (user, password) = authorization.and_then(parse_basic).ok_or(Denied::Unauthorized)?;
+        if self.credentials.iter().any(|c| c.user() == user && c.accepts(&password)) {
+            Ok(())
+        } else {
+            Err(Denied::Unauthorized)
+        }
+    }
+}
+
+/// `Basic base64(user:password)` -> `(user, password)`; the password may contain `:`.
+fn parse_basic(header: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
@@ -0,0 +1,223 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::p2p::banlist::SharedBanList;
+use crate::p2p::database::{now_secs, PeerInfo};
+use crate::p2p::history::SharedHistory;
+use crate::p2p::log::{log, log_as, Component, Event, LogLevel, LogMessage, SharedLogBuffer, SharedLogFilter};
+use crate::p2p::metrics::{Metrics, SharedMetrics};
+use crate::p2p::multhread::{multhread_db, run_crawlers_with_log, DbCommand, FlushPolicy, SharedCrawl};
+use crate::p2p::p2p_client::{ClientStatus, SharedClientStatus};
+use crate::p2p::peer_export::PeerFilter;
+use crate::p2p::peer_store::SharedPeerStore;
+use crate::p2p::retention::{prune_and_log, RetentionPolicy};
+
+/// Peers per crawl when the caller doesn't say.
+pub const DEFAULT_CRAWL_PEERS: usize = 4;
+
+/// How long `shutdown` waits for a running crawl to write what it found.
+const CRAWL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shared state behind both front ends, the interactive prompt and the RPC
+/// server, and the operations they have in common.
+#[derive(Clone)]
+pub struct Services {
+    pub peer_store: SharedPeerStore,
+    pub ban_list: SharedBanList,
+    pub history: SharedHistory,
+    pub metrics: SharedMetrics,
+    pub log_tx: Sender<LogMessage>,
+    pub log_filter: SharedLogFilter,
+    pub log_buffer: SharedLogBuffer,
+    pub crawl: SharedCrawl,
+    /// Connection of the interactive client, if it is running.
+    pub client: SharedClientStatus,
+    /// Message loop flag of the interactive client.
+    pub client_running: Arc<AtomicBool>,
+    pub flush_policy: FlushPolicy,
+    pub retention: RetentionPolicy,
+    pub started: Instant,
+    /// Set by `shutdown`; the prompt, the DNS server and the dump writer wind down when they see it.
+    pub stopping: Arc<AtomicBool>,
+}
+
+impl Services {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        peer_store: SharedPeerStore,
+        ban_list: SharedBanList,
+        history: SharedHistory,
+        log_tx: Sender<LogMessage>,
+        log_filter: SharedLogFilter,
+        log_buffer: SharedLogBuffer,
+        flush_policy: FlushPolicy,
+        retention: RetentionPolicy,
+    ) -> Self {
+        Services {
+            peer_store,
+            ban_list,
+            history,
+            metrics: Arc::new(Metrics::default()),
+            log_tx,
+            log_filter,
+            log_buffer,
+            crawl: SharedCrawl::default(),
+            client: SharedClientStatus::default(),
+            client_running: Arc::new(AtomicBool::new(false)),
+            flush_policy,
+            retention,
+            started: Instant::now(),
+            stopping: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Crawls queued addresses first, then known ones, up to `limit` in total.
+    /// Returns how many peers the crawl started with.
+    pub fn start_crawl(&self, limit: usize) -> Result<usize, String> {
+        if self.crawl.state.lock().unwrap().running {
+            return Err("crawl já está em andamento".into());
+        }
+        let peers = {
+            let bans = self.ban_list.lock().unwrap();
+            let mut peers: Vec<SocketAddr> =
+                self.crawl.queue.lock().unwrap().drain(..).filter(|a| !bans.is_banned(a)).collect();
+            let known: Vec<SocketAddr> = self
+                .peer_store
+                .lock()
+                .unwrap()
+                .all_peers()
+                .into_iter()
+                .map(|p| p.address)
+                .filter(|addr| !bans.is_banned(addr) && !peers.contains(addr))
+                .collect();
+            peers.extend(known.into_iter().take(limit.saturating_sub(peers.len())));
+            peers
+        };
+        if peers.is_empty() {
+            return Err("nenhum peer conhecido para crawl".into());
+        }
+        if !self.crawl.begin(peers.len()) {
+            return Err("crawl já está em andamento".into());
+        }
+        let count = peers.len();
+
+        let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
+        *self.crawl.db_tx.lock().unwrap() = Some(db_tx.downgrade());
+        let services = self.clone();
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("Falha ao criar runtime tokio");
+            let s = &services;
+            rt.block_on(multhread_db(
+                s.peer_store.clone(), db_rx, s.log_tx.clone(), s.flush_policy, s.ban_list.clone(), s.history.clone(),
+            ));
+            // O crawl pode ter trazido uma enxurrada de endereços; aplica os limites
+            if let Err(e) = prune_and_log(&s.peer_store, &s.retention, &s.log_tx) {
+                log_as(&s.log_tx, Component::Db, LogLevel::Error, Event::PruneFailed(e.to_string()));
+            }
+            s.crawl.finish();
+            log(&s.log_tx, LogLevel::Info, Event::CrawlFinished(count, s.crawl.stop_requested()));
+        });
+
+        let services = self.clone();
+        thread::spawn(move || {
+            let s = services;
+            match tokio::runtime::Runtime::new() {
+                Ok(rt) => {
+                    let crawl = run_crawlers_with_log(peers, db_tx, s.log_tx.clone(), s.crawl.clone(), s.ban_list.clone(), s.metrics.clone());
+                    if let Err(e) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rt.block_on(crawl))) {
+                        log(&s.log_tx, LogLevel::Error, Event::CrawlerPanicked(panic_message(&e)));
+                    }
+                }
+                Err(e) => log(&s.log_tx, LogLevel::Error, Event::RuntimeFailed(e.to_string())),
+            }
+        });
+        Ok(count)
+    }
+
+    /// Stops the running crawl; `false` if there was none.
+    pub fn stop_crawl(&self) -> bool {
+        self.crawl.request_stop()
+    }
+
+    /// Registers `addr` and puts it on the queue for the next crawl.
+    pub fn queue_node(&self, addr: SocketAddr) -> Result<(), String> {
+        if self.ban_list.lock().unwrap().is_banned(&addr) {
+            return Err(format!("{} está banido", addr));
+        }
+        self.peer_store.lock().unwrap().register_peer(addr, None, None);
+        let mut queue = self.crawl.queue.lock().unwrap();
+        if !queue.contains(&addr) {
+            queue.push_back(addr);
+        }
+        log(&self.log_tx, LogLevel::Info, Event::NodeQueued(addr));
+        Ok(())
+    }
+
+    /// Applies `spec` (see `LogFilter::apply`) if given; returns the filter in effect.
+    pub fn set_log_filter(&self, spec: Option<&str>) -> Result<String, String> {
+        let mut filter = self.log_filter.lock().unwrap();
+        if let Some(spec) = spec {
+            filter.apply(spec)?;
+        }
+        Ok(filter.to_string())
+    }
+
+    /// Matching peers ordered by address, and how many matched before paging.
+    pub fn list_peers(&self, filter: &PeerFilter, offset: usize, limit: usize) -> (usize, Vec<PeerInfo>) {
+        let now = now_secs();
+        let mut peers: Vec<PeerInfo> =
+            self.peer_store.lock().unwrap().all_peers().into_iter().filter(|p| filter.matches(p, now)).collect();
+        peers.sort_by_key(|p| p.address);
+        let total = peers.len();
+        (total, peers.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// The interactive client's connection and what the database knows about its peer.
+    pub fn client_peer(&self) -> Option<(ClientStatus, Option<PeerInfo>)> {
+        let status = self.client.lock().unwrap().clone();
+        let addr = status.peer?;
+        Some((status, self.peer_store.lock().unwrap().get_peer(&addr)))
+    }
+
+    /// Stops the client and any crawl, lets the crawl's DB task apply and flush
+    /// what it has, then writes everything and tells the long-running threads to end.
+    pub fn shutdown(&self, reason: &str) {
+        self.client_running.store(false, Ordering::SeqCst);
+        if self.stop_crawl() {
+            let db_tx = self.crawl.db_tx.lock().unwrap().take().and_then(|tx| tx.upgrade());
+            if let Some(db_tx) = db_tx {
+                let _ = db_tx.blocking_send(DbCommand::Shutdown);
+            }
+            self.crawl.wait_finished(CRAWL_SHUTDOWN_TIMEOUT);
+        }
+        self.persist();
+        log(&self.log_tx, LogLevel::Info, Event::ShuttingDown(reason.to_string()));
+        self.stopping.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Writes the crawl history and the peer database.
+    pub fn persist(&self) {
+        if let Err(e) = self.history.lock().unwrap().flush() {
+            log(&self.log_tx, LogLevel::Error, Event::StorageError("histórico".into(), e.to_string()));
+        }
+        if let Err(e) = self.peer_store.lock().unwrap().persist() {
+            log(&self.log_tx, LogLevel::Error, Event::StorageError("banco de dados".into(), e.to_string()));
+        }
+    }
+}
+
+/// Text of a panic payload, for logging.
+fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "pânico sem mensagem".to_string())
+}
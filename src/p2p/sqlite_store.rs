@@ -0,0 +1,169 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::p2p::database::{now_secs, PeerInfo, PeerStatus};
+use crate::p2p::peer_store::PeerStore;
+
+/// Durable `PeerStore` backed by SQLite.
+///
+/// Each row keeps the status in its own column (so good peers can be queried
+/// directly) and the full `PeerInfo` as JSON, so new fields don't need a new column.
+/// Writes go into one open transaction that `persist` commits, so a batch of
+/// updates costs one sync instead of one per row.
+pub struct SqlitePeerStore {
+    conn: Connection,
+    /// First write that failed since the last `persist`, reported by it.
+    write_error: Option<rusqlite::Error>,
+}
+
+/// Bumped (with a step in `with_connection`) whenever the table layout changes.
+/// Stored in SQLite's `user_version`; new `PeerInfo` fields don't need a bump,
+/// they live in the JSON column and fall back to their serde defaults.
+const SCHEMA_VERSION: i64 = 1;
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+impl SqlitePeerStore {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::with_connection(Connection::open(path).map_err(to_io)?)
+    }
+
+    pub fn with_connection(conn: Connection) -> io::Result<Self> {
+        let version: i64 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(to_io)?;
+        if version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("peer database schema {} is newer than supported {}", version, SCHEMA_VERSION),
+            ));
+        }
+        if version < 1 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS peers (
+                    address TEXT PRIMARY KEY,
+                    status  TEXT NOT NULL,
+                    info    TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS peers_status ON peers(status);
+                PRAGMA user_version = 1;",
+            )
+            .map_err(to_io)?;
+        }
+        Ok(SqlitePeerStore { conn, write_error: None })
+    }
+
+    /// Runs a write inside the pending transaction, opening it if needed.
+    fn write(&mut self, sql: &str, args: &[&dyn rusqlite::ToSql]) -> usize {
+        let begin = if self.conn.is_autocommit() { self.conn.execute_batch("BEGIN") } else { Ok(()) };
+        let result = begin.and_then(|_| self.conn.execute(sql, args));
+        match result {
+            Ok(n) => n,
+            Err(e) => {
+                // Guarda o primeiro erro para o `persist`; os seguintes costumam ser o mesmo
+                self.write_error.get_or_insert(e);
+                0
+            }
+        }
+    }
+
+    fn save(&mut self, info: &PeerInfo) {
+        if let Ok(json) = serde_json::to_string(info) {
+            self.write(
+                "INSERT INTO peers (address, status, info) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(address) DO UPDATE SET status = excluded.status, info = excluded.info",
+                params![info.address.to_string(), info.status.name(), json],
+            );
+        }
+    }
+
+    fn query(&self, sql: &str, args: &[&dyn rusqlite::ToSql]) -> Vec<PeerInfo> {
+        let Ok(mut stmt) = self.conn.prepare(sql) else {
+            return Vec::new();
+        };
+        let rows = stmt.query_map(args, |row| row.get::<_, String>(0));
+        match rows {
+            Ok(rows) => rows
+                .filter_map(|r| r.ok())
+                .filter_map(|json| serde_json::from_str(&json).ok())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl PeerStore for SqlitePeerStore {
+    fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+        let now = now_secs();
+        let mut info = self
+            .get_peer(&addr)
+            .unwrap_or_else(|| PeerInfo { source, ..PeerInfo::new(addr, PeerStatus::NeverTried, now) });
+        info.touch(services, now);
+        self.save(&info);
+    }
+
+    fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+        let mut info = self
+            .get_peer(&addr)
+            .unwrap_or_else(|| PeerInfo::new(addr, PeerStatus::NeverTried, now_secs()));
+        f(&mut info);
+        self.save(&info);
+    }
+
+    fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+        let now = now_secs();
+        let mut info = self
+            .get_peer(&addr)
+            .unwrap_or_else(|| PeerInfo::new(addr, PeerStatus::NeverTried, now));
+        info.set_status(status, now);
+        self.save(&info);
+    }
+
+    fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo> {
+        self.conn
+            .query_row(
+                "SELECT info FROM peers WHERE address = ?1",
+                params![addr.to_string()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .ok()
+            .flatten()
+            .and_then(|json| serde_json::from_str(&json).ok())
+    }
+
+    fn good_peers(&self) -> Vec<PeerInfo> {
+        self.query(
+            "SELECT info FROM peers WHERE status = ?1",
+            &[&PeerStatus::ConnectedRecently.name()],
+        )
+    }
+
+    fn all_peers(&self) -> Vec<PeerInfo> {
+        self.query("SELECT info FROM peers", &[])
+    }
+
+    fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+        self.write("DELETE FROM peers WHERE address = ?1", params![addr.to_string()]) > 0
+    }
+
+    fn persist(&mut self) -> io::Result<()> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT").map_err(to_io)?;
+        }
+        match self.write_error.take() {
+            Some(e) => Err(to_io(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM peers", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .unwrap_or(0)
+    }
+}
@@ -134,74 +134,88 @@ pub fn build_version_payload(peer_addr: SocketAddr) -> Vec<u8> {
     payload
 }
 
-pub fn parse_addr_message(payload: &[u8]) -> Vec<SocketAddr> {
-    let mut addresses = Vec::new();
-    
-    if payload.is_empty() {
-        println!("   ⚠️  Empty address payload");
-        return addresses;
+/// One entry of an `addr` message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddrEntry {
+    pub timestamp: u32,
+    pub services: u64,
+    pub addr: SocketAddr,
+}
+
+/// Decodes the 16-byte network address used by `addr` and `version` messages.
+pub fn parse_net_addr(ip_bytes: &[u8; 16], port: u16) -> SocketAddr {
+    if ip_bytes[0..12] == [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF] {
+        let ipv4 = std::net::Ipv4Addr::new(ip_bytes[12], ip_bytes[13], ip_bytes[14], ip_bytes[15]);
+        SocketAddr::V4(std::net::SocketAddrV4::new(ipv4, port))
+    } else {
+        let ipv6 = std::net::Ipv6Addr::from(*ip_bytes);
+        SocketAddr::V6(std::net::SocketAddrV6::new(ipv6, port, 0, 0))
     }
-    
-    // Parse compact size integer for count
+}
+
+/// Parses every entry of an `addr` payload, stopping at the first truncated one.
+pub fn parse_addr_entries(payload: &[u8]) -> Vec<AddrEntry> {
+    let mut addresses = Vec::new();
     let (count, mut offset) = parse_compact_size(payload);
-    println!("   📊 Address count: {}", count);
-    
-    if count == 0 {
-        return addresses;
-    }
-    
-    let addresses_to_show = count.min(10); // Show first 10
-    
-    for i in 0..addresses_to_show {
+
+    for _ in 0..count {
         if offset + 30 > payload.len() {
-            println!("   ⚠️  Insufficient data for address {}", i + 1);
             break;
         }
         
-        // Skip timestamp (4 bytes)
+        // Timestamp (4 bytes, little endian)
+        let timestamp = u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
         offset += 4;
         
-        // Skip services (8 bytes)  
+        // Services (8 bytes, little endian)
+        let services = u64::from_le_bytes(payload[offset..offset + 8].try_into().unwrap());
         offset += 8;
         
         // Read IP address (16 bytes)
-        let ip_bytes = &payload[offset..offset + 16];
+        let ip_bytes = <[u8; 16]>::try_from(&payload[offset..offset + 16]).unwrap();
         offset += 16;
         
         // Read port (2 bytes, big endian)
-        if offset + 2 > payload.len() {
-            break;
-        }
         let port = u16::from_be_bytes([payload[offset], payload[offset + 1]]);
         offset += 2;
         
-        // Parse IP address
-        if ip_bytes[0..12] == [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF] {
-            // IPv4 address
-            let ipv4 = std::net::Ipv4Addr::new(
-                ip_bytes[12], ip_bytes[13], ip_bytes[14], ip_bytes[15]
-            );
-            let addr = SocketAddr::V4(std::net::SocketAddrV4::new(ipv4, port));
-            println!("     {}: {} (IPv4)", i + 1, addr);
-            addresses.push(addr);
-        } else {
-            // IPv6 address
-            let ipv6 = std::net::Ipv6Addr::from(
-                *<&[u8; 16]>::try_from(ip_bytes).unwrap()
-            );
-            let addr = SocketAddr::V6(std::net::SocketAddrV6::new(ipv6, port, 0, 0));
-            println!("     {}: {} (IPv6)", i + 1, addr);
-            addresses.push(addr);
-        }
+        addresses.push(AddrEntry { timestamp, services, addr: parse_net_addr(&ip_bytes, port) });
     }
-    
-    if count > addresses_to_show {
-        println!("     ... and {} more addresses", count - addresses_to_show);
-    }
-    
+
     addresses
 }
 
+/// Fields of a peer's `version` message that we keep about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    pub version: u32,
+    pub services: u64,
+    pub user_agent: String,
+    pub start_height: i32,
+}
+
+pub fn parse_version_message(payload: &[u8]) -> Option<VersionInfo> {
+    // version(4) + services(8) + timestamp(8) + addr_recv(26) + addr_from(26) + nonce(8)
+    const FIXED: usize = 80;
+    if payload.len() < FIXED + 1 {
+        return None;
+    }
+    let version = u32::from_le_bytes(payload[0..4].try_into().ok()?);
+    let services = u64::from_le_bytes(payload[4..12].try_into().ok()?);
+
+    let (ua_len, ua_offset) = parse_compact_size(&payload[FIXED..]);
+    let ua_start = FIXED + ua_offset;
+    // O tamanho vem do peer: um 0xFF pode pedir até u64::MAX bytes
+    let height_at = ua_start.checked_add(ua_len)?;
+    if height_at.checked_add(4)? > payload.len() {
+        return None;
+    }
+    let user_agent = String::from_utf8_lossy(&payload[ua_start..height_at]).to_string();
+    let start_height = i32::from_le_bytes(payload[height_at..height_at + 4].try_into().ok()?);
+
+    Some(VersionInfo { version, services, user_agent, start_height })
+}
+
 pub fn parse_compact_size(data: &[u8]) -> (usize, usize) {
     if data.is_empty() {
         return (0, 0);
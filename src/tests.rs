@@ -1,8 +1,78 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::p2p::utils::*;
     use crate::p2p::inventory::{InventoryItem, InventoryType};
+    use crate::p2p::database::{PeerStatus, PeerDatabase, PeerInfo, FailureStage, PeerFailure, UptimeStats, DB_BACKUPS};
+    use crate::p2p::multhread::{multhread_db, crawl_peer_with_log, DbCommand, FlushPolicy};
+    use crate::p2p::messageheader::MessageHeader;
+    use crate::p2p::log::{
+        log, log_as, Component, Event, LogBuffer, LogFileConfig, LogFilter, LogFormat, LogLevel, LogMessage, LogQuery, Logger,
+        RotatingFile,
+    };
+    use crate::p2p::misbehavior::{inspect_addr, report_misbehavior, Misbehavior, BAN_THRESHOLD};
+    use crate::p2p::banlist::{ban_subnet, unban_subnet, parse_duration, BanList, SharedBanList, Subnet};
+    use crate::p2p::atomic_file::backup_path;
+    use crate::p2p::migrations::{version_of, DB_VERSION};
+    use crate::p2p::peer_store::{PeerStore, MemoryPeerStore, JsonPeerStore, SharedPeerStore, shared};
+    use crate::p2p::sqlite_store::SqlitePeerStore;
+    use crate::p2p::peer_export::{export_peers, import_peers, ExportFormat, NetworkKind, PeerFilter};
+    use crate::p2p::addrman::{AddOutcome, AddrMan, AddrManStore, BUCKET_SIZE, NEW_BUCKETS_PER_SOURCE_GROUP, TRIED_BUCKETS_PER_GROUP};
+    use crate::p2p::history::{plot_reachable, HistoryLog, Observation, SharedHistory};
+    use crate::p2p::asmap::{assemble, assemble_bits, Asmap, Op};
+    use crate::p2p::dns_server::pick_diverse;
+    use crate::p2p::metrics::{failure_reason, serve_metrics, Metrics, SharedMetrics};
+    use crate::p2p::http::read_request_within;
+    use crate::p2p::rpc::{handle_body, serve_rpc, RpcContext, RpcError};
+    use crate::p2p::rpc_auth::{Credential, Denied, RpcAuth, COOKIE_USER};
+    use crate::p2p::services::Services;
+    use crate::p2p::census::{build_census, render_table, service_names, CensusRow};
+    use crate::p2p::retention::{netgroup, prune, RetentionPolicy};
+    use crate::p2p::dnsseed_dump::{format_dump, write_dump};
+    use crate::p2p::core_import::{import_core_file, parse_anchors_dat, parse_peers_dat};
     use std::net::{SocketAddr, Ipv4Addr};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("seeder-test-{}-{}-{}", std::process::id(), nanos, name))
+    }
+
+    fn peer(last_octet: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last_octet], 8333))
+    }
+
+    // Mesmo cenário para todos os backends de PeerStore
+    fn exercise_store(store: &mut dyn PeerStore) {
+        store.register_peer(peer(1), Some(1), None);
+        store.register_peer(peer(2), None, Some(peer(1)));
+        store.register_peer(peer(1), None, None);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get_peer(&peer(1)).unwrap().services, Some(1));
+        assert_eq!(store.get_peer(&peer(2)).unwrap().source, Some(peer(1)));
+        store.update_peer(peer(2), &mut |p| p.user_agent = Some("/test/".into()));
+        assert_eq!(store.get_peer(&peer(2)).unwrap().user_agent.as_deref(), Some("/test/"));
+
+        store.update_status(peer(1), PeerStatus::ConnectedRecently);
+        store.update_status(peer(2), PeerStatus::Unreachable);
+        let good = store.good_peers();
+        assert_eq!(good.len(), 1);
+        assert_eq!(good[0].address, peer(1));
+        assert!(good[0].last_connected.is_some());
+
+        store.ban_peer(peer(1));
+        assert!(store.good_peers().is_empty());
+        assert_eq!(store.get_peer(&peer(1)).unwrap().status, PeerStatus::Banned);
+        assert_eq!(store.all_peers().len(), 2);
+        store.persist().unwrap();
+    }
 
 
 
@@ -12,7 +82,7 @@ mod tests {
             inv_type: InventoryType::Transaction,
             hash: [1u8; 32],
         };
-        let payload = build_getdata_payload(&[item.clone()]);
+        let payload = build_getdata_payload(std::slice::from_ref(&item));
         let _parsed = parse_inv_message(&payload[1..]); // skip compact size
         // Como build_getdata_payload não gera um payload de inv, só testamos o tamanho
         assert!(payload.len() > 32);
@@ -30,7 +100,7 @@ mod tests {
     fn test_build_version_payload_ipv4() {
         let addr = SocketAddr::V4(std::net::SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333));
         let payload = build_version_payload(addr);
-        assert!(payload.len() > 0);
+        assert!(!payload.is_empty());
     }
 
     #[test]
@@ -92,4 +162,1773 @@ mod tests {
         let items = parse_inv_message(&payload);
         assert!(items.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_memory_peer_store() {
+        exercise_store(&mut MemoryPeerStore::default());
+    }
+
+    #[test]
+    fn test_sqlite_peer_store() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        exercise_store(&mut SqlitePeerStore::with_connection(conn).unwrap());
+    }
+
+    #[test]
+    fn test_json_peer_store_roundtrip() {
+        let path = temp_path("peers.json");
+        let (mut store, _) = JsonPeerStore::open(&path).unwrap();
+        exercise_store(&mut store);
+
+        let (reopened, _) = JsonPeerStore::open(&path).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.get_peer(&peer(2)).unwrap().status, PeerStatus::Unreachable);
+        remove_db_files(&path);
+    }
+
+    fn remove_db_files(path: &std::path::Path) {
+        let _ = std::fs::remove_file(path);
+        for n in 1..=DB_BACKUPS {
+            let _ = std::fs::remove_file(backup_path(path, n));
+        }
+    }
+
+    #[test]
+    fn test_save_keeps_rotating_backups() {
+        let path = temp_path("rotate.json");
+        let mut db = PeerDatabase::default();
+        for i in 1..=4 {
+            db.register_peer(peer(i), None, None);
+            db.save_to_file(&path).unwrap();
+        }
+        let (newest, _) = PeerDatabase::load_from_file(&backup_path(&path, 1)).unwrap();
+        let (oldest, _) = PeerDatabase::load_from_file(&backup_path(&path, 2)).unwrap();
+        assert_eq!(newest.peers.len(), 3);
+        assert_eq!(oldest.peers.len(), 2);
+        assert!(!backup_path(&path, 3).exists());
+        remove_db_files(&path);
+    }
+
+    #[test]
+    fn test_load_falls_back_to_backup_when_corrupt() {
+        let path = temp_path("corrupt.json");
+        let mut db = PeerDatabase::default();
+        db.register_peer(peer(1), None, None);
+        db.save_to_file(&path).unwrap();
+        db.register_peer(peer(2), None, None);
+        db.save_to_file(&path).unwrap();
+
+        // Simula um crash no meio de uma escrita antiga (arquivo truncado)
+        std::fs::write(&path, "{\"peers\": {\"10.0.0.1:83").unwrap();
+        let (loaded, report) = PeerDatabase::load_from_file(&path).unwrap();
+        assert_eq!(loaded.peers.len(), 1);
+        assert_eq!(report.source, Some(backup_path(&path, 1)));
+        assert_eq!(report.corrupt.len(), 1);
+
+        // Se nada for legível, não começamos vazio
+        std::fs::write(backup_path(&path, 1), "lixo").unwrap();
+        assert!(PeerDatabase::load_from_file(&path).is_err());
+        remove_db_files(&path);
+    }
+
+    #[test]
+    fn test_load_missing_database_starts_empty() {
+        let (db, report) = PeerDatabase::load_from_file(&temp_path("missing.json")).unwrap();
+        assert!(db.peers.is_empty());
+        assert!(report.source.is_none());
+        assert!(report.corrupt.is_empty());
+    }
+
+    // Conta quantas vezes o banco foi gravado; as primeiras `fail_first` gravações falham
+    struct CountingStore {
+        inner: MemoryPeerStore,
+        persists: Arc<AtomicUsize>,
+        fail_first: usize,
+    }
+
+    impl PeerStore for CountingStore {
+        fn register_peer(&mut self, addr: SocketAddr, services: Option<u64>, source: Option<SocketAddr>) {
+            self.inner.register_peer(addr, services, source)
+        }
+        fn update_peer(&mut self, addr: SocketAddr, f: &mut dyn FnMut(&mut PeerInfo)) {
+            self.inner.update_peer(addr, f)
+        }
+        fn update_status(&mut self, addr: SocketAddr, status: PeerStatus) {
+            self.inner.update_status(addr, status)
+        }
+        fn get_peer(&self, addr: &SocketAddr) -> Option<PeerInfo> {
+            self.inner.get_peer(addr)
+        }
+        fn good_peers(&self) -> Vec<PeerInfo> {
+            self.inner.good_peers()
+        }
+        fn all_peers(&self) -> Vec<PeerInfo> {
+            self.inner.all_peers()
+        }
+        fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+            self.inner.remove_peer(addr)
+        }
+        fn persist(&mut self) -> std::io::Result<()> {
+            if self.persists.fetch_add(1, Ordering::SeqCst) < self.fail_first {
+                return Err(std::io::Error::other("disco cheio"));
+            }
+            Ok(())
+        }
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multhread_db_retries_failed_flush() {
+        let persists = Arc::new(AtomicUsize::new(0));
+        let store = shared(Box::new(CountingStore { inner: MemoryPeerStore::default(), persists: persists.clone(), fail_first: 1 }));
+        let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
+        let (log_tx, _log_rx) = std::sync::mpsc::channel();
+        let policy = FlushPolicy { interval: Duration::from_secs(3600), max_dirty: 4 };
+        let bans = Arc::new(Mutex::new(BanList::default()));
+        let history: SharedHistory = Arc::new(Mutex::new(HistoryLog::default()));
+        let task = tokio::spawn(multhread_db(store.clone(), db_rx, log_tx, policy, bans, history));
+
+        for i in 1..=4 {
+            db_tx.send(DbCommand::UpdatePeerStatus(peer(i), PeerStatus::Unreachable)).await.unwrap();
+        }
+        drop(db_tx);
+        task.await.unwrap();
+
+        // A gravação pelo limite falhou; o flush final tenta de novo mesmo sem mudanças novas
+        assert_eq!(persists.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_multhread_db_coalesces_writes() {
+        let persists = Arc::new(AtomicUsize::new(0));
+        let store = shared(Box::new(CountingStore { inner: MemoryPeerStore::default(), persists: persists.clone(), fail_first: 0 }));
+        let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
+        let (log_tx, _log_rx) = std::sync::mpsc::channel();
+        let policy = FlushPolicy { interval: Duration::from_secs(3600), max_dirty: 4 };
+        let bans = Arc::new(Mutex::new(BanList::default()));
+        let history: SharedHistory = Arc::new(Mutex::new(HistoryLog::default()));
+        let task = tokio::spawn(multhread_db(store.clone(), db_rx, log_tx, policy, bans, history.clone()));
+
+        for i in 1..=10 {
+            db_tx.send(DbCommand::UpdatePeerStatus(peer(i), PeerStatus::Unreachable)).await.unwrap();
+        }
+        drop(db_tx);
+        task.await.unwrap();
+
+        // 4 + 4 pelo limite, e os 2 restantes no flush final
+        assert_eq!(persists.load(Ordering::SeqCst), 3);
+        assert_eq!(store.lock().unwrap().len(), 10);
+        let observed = history.lock().unwrap().observations().unwrap();
+        assert_eq!(observed.len(), 10);
+        assert!(observed.iter().all(|o| !o.success && o.latency_ms.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_multhread_db_shutdown_drains_and_flushes() {
+        let persists = Arc::new(AtomicUsize::new(0));
+        let store = shared(Box::new(CountingStore { inner: MemoryPeerStore::default(), persists: persists.clone(), fail_first: 0 }));
+        let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
+        let (log_tx, _log_rx) = std::sync::mpsc::channel();
+        let policy = FlushPolicy { interval: Duration::from_secs(3600), max_dirty: 100 };
+        let bans = Arc::new(Mutex::new(BanList::default()));
+        let history: SharedHistory = Arc::new(Mutex::new(HistoryLog::default()));
+        let task = tokio::spawn(multhread_db(store.clone(), db_rx, log_tx, policy, bans, history));
+
+        // Um crawler ainda segura o canal: o task termina pelo Shutdown, não pelo fechamento
+        let crawler = db_tx.clone();
+        for i in 1..=3 {
+            db_tx.send(DbCommand::UpdatePeerStatus(peer(i), PeerStatus::Unreachable)).await.unwrap();
+        }
+        db_tx.send(DbCommand::Shutdown).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+
+        assert_eq!(store.lock().unwrap().len(), 3);
+        assert_eq!(persists.load(Ordering::SeqCst), 1);
+        assert!(crawler.send(DbCommand::UpdatePeerStatus(peer(4), PeerStatus::Unreachable)).await.is_err());
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn test_load_v1_fixture_without_version() {
+        let (db, _) = PeerDatabase::load_from_file(&fixture("peers_v1.json")).unwrap();
+        assert_eq!(db.peers.len(), 3);
+        let addr: SocketAddr = "203.0.113.7:8333".parse().unwrap();
+        assert_eq!(db.peers[&addr].services, Some(1033));
+        assert_eq!(db.peers[&addr].status, PeerStatus::ConnectedRecently);
+    }
+
+    #[test]
+    fn test_load_v2_fixture_with_missing_optional_fields() {
+        let (db, _) = PeerDatabase::load_from_file(&fixture("peers_v2.json")).unwrap();
+        let addr: SocketAddr = "198.51.100.20:8333".parse().unwrap();
+        assert_eq!(db.peers[&addr].status, PeerStatus::Banned);
+        assert_eq!(db.peers[&addr].last_seen, None);
+    }
+
+    #[test]
+    fn test_migrated_database_is_saved_with_current_version() {
+        let (db, _) = PeerDatabase::load_from_file(&fixture("peers_v1.json")).unwrap();
+        let path = temp_path("migrated.json");
+        db.save_to_file(&path).unwrap();
+        let doc: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(version_of(&doc).unwrap(), DB_VERSION);
+        assert_eq!(PeerDatabase::from_json(&doc.to_string()).unwrap().peers.len(), 3);
+        remove_db_files(&path);
+    }
+
+    #[test]
+    fn test_refuses_newer_database_version() {
+        let data = format!("{{\"version\": {}, \"peers\": {{}}}}", DB_VERSION + 1);
+        assert!(PeerDatabase::from_json(&data).is_err());
+    }
+
+    fn fake_version_payload(version: u32, services: u64, user_agent: &str, height: i32) -> Vec<u8> {
+        let mut p = Vec::new();
+        p.extend(version.to_le_bytes());
+        p.extend(services.to_le_bytes());
+        p.extend(0i64.to_le_bytes());
+        p.extend([0u8; 26]);
+        p.extend([0u8; 26]);
+        p.extend(7u64.to_le_bytes());
+        p.push(user_agent.len() as u8);
+        p.extend(user_agent.as_bytes());
+        p.extend(height.to_le_bytes());
+        p.push(1);
+        p
+    }
+
+    fn fake_addr_payload(entries: &[(SocketAddr, u64)]) -> Vec<u8> {
+        let mut p = vec![entries.len() as u8];
+        for (addr, services) in entries {
+            p.extend(1_700_000_000u32.to_le_bytes());
+            p.extend(services.to_le_bytes());
+            match addr {
+                SocketAddr::V4(v4) => {
+                    p.extend([0u8; 10]);
+                    p.extend([0xFF, 0xFF]);
+                    p.extend(v4.ip().octets());
+                }
+                SocketAddr::V6(v6) => p.extend(v6.ip().octets()),
+            }
+            p.extend(addr.port().to_be_bytes());
+        }
+        p
+    }
+
+    fn frame(command: &str, payload: &[u8]) -> Vec<u8> {
+        let mut msg = MessageHeader::new(command, payload).to_bytes();
+        msg.extend_from_slice(payload);
+        msg
+    }
+
+    /// Nó falso que responde version/verack, ping e getaddr como um nó real.
+    async fn spawn_fake_node(gossip: Vec<(SocketAddr, u64)>) -> SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            loop {
+                let mut header = [0u8; 24];
+                if stream.read_exact(&mut header).await.is_err() {
+                    return;
+                }
+                let header = MessageHeader::from_bytes(&header).unwrap();
+                let mut payload = vec![0u8; header.payload_size as usize];
+                stream.read_exact(&mut payload).await.unwrap();
+                let reply = match header.command_str().as_str() {
+                    "version" => {
+                        let mut out = frame("version", &fake_version_payload(70016, 0x409, "/Satoshi:27.0.0/", 850_000));
+                        out.extend(frame("verack", &[]));
+                        out
+                    }
+                    "ping" => frame("pong", &payload),
+                    "getaddr" => frame("addr", &fake_addr_payload(&gossip)),
+                    _ => continue,
+                };
+                stream.write_all(&reply).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    async fn crawl_into_store(addr: SocketAddr) -> SharedPeerStore {
+        crawl_into_store_with_bans(addr, Arc::new(Mutex::new(BanList::default()))).await
+    }
+
+    async fn crawl_into_store_with_bans(addr: SocketAddr, bans: SharedBanList) -> SharedPeerStore {
+        crawl_into_store_with(addr, bans, Arc::new(Metrics::default())).await
+    }
+
+    async fn crawl_into_store_with(addr: SocketAddr, bans: SharedBanList, metrics: SharedMetrics) -> SharedPeerStore {
+        let store = shared(Box::new(MemoryPeerStore::default()));
+        let (db_tx, db_rx) = tokio::sync::mpsc::channel(32);
+        let (log_tx, _log_rx) = std::sync::mpsc::channel();
+        let policy = FlushPolicy { interval: Duration::from_secs(3600), max_dirty: 1000 };
+        let history = Arc::new(Mutex::new(HistoryLog::default()));
+        let db_task = tokio::spawn(multhread_db(store.clone(), db_rx, log_tx.clone(), policy, bans.clone(), history));
+        crawl_peer_with_log(addr, db_tx, log_tx, Arc::new(Mutex::new(HashSet::new())), bans, metrics).await;
+        db_task.await.unwrap();
+        store
+    }
+
+    #[test]
+    fn test_parse_version_message() {
+        let payload = fake_version_payload(70016, 0x409, "/Satoshi:27.0.0/", 850_000);
+        let info = parse_version_message(&payload).unwrap();
+        assert_eq!(info.version, 70016);
+        assert_eq!(info.services, 0x409);
+        assert_eq!(info.user_agent, "/Satoshi:27.0.0/");
+        assert_eq!(info.start_height, 850_000);
+        assert!(parse_version_message(&payload[..60]).is_none());
+
+        // Tamanho de user agent 0xFF absurdo: recusa em vez de estourar a soma
+        for huge in [u64::MAX, u64::MAX - 90, u32::MAX as u64 + 1] {
+            let mut hostile = payload[..80].to_vec();
+            hostile.push(0xFF);
+            hostile.extend_from_slice(&huge.to_le_bytes());
+            hostile.extend_from_slice(&850_000i32.to_le_bytes());
+            assert!(parse_version_message(&hostile).is_none());
+        }
+    }
+
+    #[test]
+    fn test_parse_addr_entries_keeps_services_and_all_entries() {
+        let entries: Vec<(SocketAddr, u64)> = (1..=12).map(|i| (peer(i), i as u64)).collect();
+        let parsed = parse_addr_entries(&fake_addr_payload(&entries));
+        assert_eq!(parsed.len(), 12);
+        assert_eq!(parsed[11].addr, peer(12));
+        assert_eq!(parsed[11].services, 12);
+        assert_eq!(parsed[0].timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn test_v2_migration_backfills_first_seen() {
+        let (db, _) = PeerDatabase::load_from_file(&fixture("peers_v2.json")).unwrap();
+        let addr: SocketAddr = "203.0.113.7:8333".parse().unwrap();
+        assert_eq!(db.peers[&addr].first_seen, Some(1719000000));
+        assert_eq!(db.peers[&addr].user_agent, None);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_records_peer_metadata() {
+        let gossiped = SocketAddr::from(([192, 0, 2, 1], 8333));
+        let node = spawn_fake_node(vec![(gossiped, 0x9), (peer(9), 0x1)]).await;
+        let store = crawl_into_store(node).await;
+        let db = store.lock().unwrap();
+
+        let info = db.get_peer(&node).unwrap();
+        assert_eq!(info.status, PeerStatus::ConnectedRecently);
+        assert_eq!(info.user_agent.as_deref(), Some("/Satoshi:27.0.0/"));
+        assert_eq!(info.protocol_version, Some(70016));
+        assert_eq!(info.start_height, Some(850_000));
+        assert_eq!(info.services, Some(0x409));
+        assert!(info.ping_ms.is_some());
+        assert!(info.last_failure.is_none());
+
+        let learned = db.get_peer(&gossiped).unwrap();
+        assert_eq!(learned.source, Some(node));
+        assert_eq!(learned.services, Some(0x9));
+        assert_eq!(learned.status, PeerStatus::NeverTried);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_records_connect_failure() {
+        let closed = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap()
+        };
+        let store = crawl_into_store(closed).await;
+        let info = store.lock().unwrap().get_peer(&closed).unwrap();
+        assert_eq!(info.status, PeerStatus::Unreachable);
+        assert_eq!(info.last_failure.unwrap().stage, FailureStage::Connect);
+    }
+
+    #[test]
+    fn test_subnet_parse_and_contains() {
+        let net: Subnet = "10.0.7.9/16".parse().unwrap();
+        assert_eq!(net.to_string(), "10.0.0.0/16");
+        assert!(net.contains("10.0.255.1".parse().unwrap()));
+        assert!(!net.contains("10.1.0.1".parse().unwrap()));
+        assert!(!net.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        let v6: Subnet = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:ffff::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db9::1".parse().unwrap()));
+
+        let single: Subnet = "192.0.2.1".parse().unwrap();
+        assert_eq!(single.to_string(), "192.0.2.1/32");
+        assert!("10.0.0.0/33".parse::<Subnet>().is_err());
+    }
+
+    #[test]
+    fn test_parse_ban_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("7d"), Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("spam"), None);
+        assert_eq!(parse_duration("99999999999999999d"), None);
+        assert_eq!(parse_duration("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_banlist_expiry_and_persistence() {
+        let path = temp_path("banlist.json");
+        let mut bans = BanList::load(&path).unwrap();
+        bans.ban("10.0.0.0/24".parse().unwrap(), None, "spam").unwrap();
+        bans.ban("192.0.2.1".parse().unwrap(), Some(Duration::from_secs(0)), "expirado").unwrap();
+        assert!(bans.is_banned(&peer(5)));
+        assert!(!bans.is_banned(&SocketAddr::from(([192, 0, 2, 1], 8333))));
+
+        let mut reloaded = BanList::load(&path).unwrap();
+        let active = reloaded.list().unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].reason, "spam");
+        assert_eq!(BanList::load(&path).unwrap().list().unwrap().len(), 1);
+
+        // Durações enormes saturam em vez de estourar
+        reloaded.ban("192.0.2.2".parse().unwrap(), Some(Duration::MAX), "longo").unwrap();
+        assert!(reloaded.is_banned(&SocketAddr::from(([192, 0, 2, 2], 8333))));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_ban_subnet_updates_peer_store() {
+        let store = shared(Box::new(MemoryPeerStore::default()));
+        let bans: SharedBanList = Arc::new(Mutex::new(BanList::default()));
+        store.lock().unwrap().update_status(peer(1), PeerStatus::ConnectedRecently);
+        store.lock().unwrap().register_peer(SocketAddr::from(([192, 0, 2, 1], 8333)), None, None);
+
+        let subnet: Subnet = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(ban_subnet(&bans, &store, subnet, None, "teste").unwrap(), 1);
+        assert_eq!(store.lock().unwrap().get_peer(&peer(1)).unwrap().status, PeerStatus::Banned);
+        assert!(store.lock().unwrap().good_peers().is_empty());
+
+        assert!(unban_subnet(&bans, &store, &subnet).unwrap());
+        assert_eq!(store.lock().unwrap().get_peer(&peer(1)).unwrap().status, PeerStatus::NeverTried);
+        assert!(!unban_subnet(&bans, &store, &subnet).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_crawler_skips_banned_and_drops_banned_gossip() {
+        let banned_gossip = SocketAddr::from(([198, 51, 100, 1], 8333));
+        let node = spawn_fake_node(vec![(banned_gossip, 1), (peer(3), 1)]).await;
+        let bans: SharedBanList = Arc::new(Mutex::new(BanList::default()));
+        bans.lock().unwrap().ban("198.51.100.0/24".parse().unwrap(), None, "teste").unwrap();
+
+        let store = crawl_into_store_with_bans(node, bans.clone()).await;
+        assert!(store.lock().unwrap().get_peer(&banned_gossip).is_none());
+        assert!(store.lock().unwrap().get_peer(&peer(3)).is_some());
+
+        bans.lock().unwrap().ban(Subnet::single(node.ip()), None, "teste").unwrap();
+        let store = crawl_into_store_with_bans(node, bans).await;
+        assert!(store.lock().unwrap().get_peer(&node).is_none());
+    }
+
+    /// Nó que responde ao version com bytes arbitrários (para simular violações).
+    async fn spawn_hostile_node(reply: Vec<u8>) -> SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(&reply).await;
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_misbehavior_survives_io_error_roundtrip() {
+        let e = Misbehavior::BadChecksum.into_io();
+        assert_eq!(Misbehavior::from_io(&e), Some(Misbehavior::BadChecksum));
+        assert_eq!(Misbehavior::from_io(&std::io::Error::other("timeout")), None);
+    }
+
+    #[test]
+    fn test_inspect_addr_flags_flood_and_truncation() {
+        let entries: Vec<(SocketAddr, u64)> = (0..3).map(|i| (peer(i), 1)).collect();
+        let payload = fake_addr_payload(&entries);
+        assert_eq!(inspect_addr(&payload).1, None);
+        assert_eq!(inspect_addr(&payload[..payload.len() - 5]).1, Some(Misbehavior::MalformedAddr));
+
+        let mut flood = vec![0xFD];
+        flood.extend(1001u16.to_le_bytes());
+        assert_eq!(inspect_addr(&flood).1, Some(Misbehavior::AddrFlood));
+    }
+
+    #[test]
+    fn test_misbehavior_bans_at_threshold() {
+        let store = shared(Box::new(MemoryPeerStore::default()));
+        let bans: SharedBanList = Arc::new(Mutex::new(BanList::default()));
+        let (log_tx, log_rx) = std::sync::mpsc::channel();
+
+        let mut score = 0;
+        while score < BAN_THRESHOLD {
+            assert!(!bans.lock().unwrap().is_banned(&peer(1)));
+            score = report_misbehavior(&store, &bans, &log_tx, peer(1), Misbehavior::BadChecksum);
+        }
+        assert_eq!(score, BAN_THRESHOLD);
+        assert!(bans.lock().unwrap().is_banned(&peer(1)));
+        assert_eq!(store.lock().unwrap().get_peer(&peer(1)).unwrap().status, PeerStatus::Banned);
+        let reason = &bans.lock().unwrap().list().unwrap()[0].reason;
+        assert!(reason.contains("Invalid checksum"));
+        assert!(log_rx.try_iter().any(|m| matches!(m.event, Event::AutoBanned(..))));
+
+        // O ban zera o placar: quando expirar, uma violação isolada não bane de novo
+        assert_eq!(store.lock().unwrap().get_peer(&peer(1)).unwrap().misbehavior_score, 0);
+        bans.lock().unwrap().unban(&Subnet::single(peer(1).ip())).unwrap();
+        let score = report_misbehavior(&store, &bans, &log_tx, peer(1), Misbehavior::BadChecksum);
+        assert_eq!(score, Misbehavior::BadChecksum.score());
+        assert!(!bans.lock().unwrap().is_banned(&peer(1)));
+    }
+
+    #[tokio::test]
+    async fn test_crawler_scores_bad_checksum_and_oversized_payload() {
+        let mut bad = frame("version", &fake_version_payload(70016, 1, "/x/", 1));
+        bad[20] ^= 0xFF;
+        let node = spawn_hostile_node(bad).await;
+        let store = crawl_into_store(node).await;
+        let info = store.lock().unwrap().get_peer(&node).unwrap();
+        assert_eq!(info.misbehavior_score, Misbehavior::BadChecksum.score());
+        assert_eq!(info.last_failure.unwrap().stage, FailureStage::Handshake);
+
+        let mut huge = MessageHeader::new("version", &[]).to_bytes();
+        huge[16..20].copy_from_slice(&(64u32 << 20).to_le_bytes());
+        let node = spawn_hostile_node(huge).await;
+        let bans: SharedBanList = Arc::new(Mutex::new(BanList::default()));
+        let store = crawl_into_store_with_bans(node, bans.clone()).await;
+        assert!(bans.lock().unwrap().is_banned(&node));
+        assert_eq!(store.lock().unwrap().get_peer(&node).unwrap().status, PeerStatus::Banned);
+    }
+
+    // Serializa no formato de disco do Bitcoin Core (magic + corpo + sha256d)
+    fn core_file(body: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(body);
+        let checksum = sha256d(&data);
+        data.extend_from_slice(&checksum);
+        data
+    }
+
+    /// CAddress in addrv2 disk form: network id 1 = IPv4, 2 = IPv6, 4 = Tor v3.
+    fn core_addr_v2(network: u8, ip: &[u8], port: u16, time: u32, services: u8) -> Vec<u8> {
+        let mut out = (220_000i32 | 1 << 29).to_le_bytes().to_vec();
+        out.extend_from_slice(&time.to_le_bytes());
+        out.push(services); // compactsize
+        out.push(network);
+        out.push(ip.len() as u8);
+        out.extend_from_slice(ip);
+        out.extend_from_slice(&port.to_be_bytes());
+        out
+    }
+
+    fn core_addr_info_v2(addr: Vec<u8>, last_success: i64) -> Vec<u8> {
+        let mut out = addr;
+        out.extend_from_slice(&[1, 4, 127, 0, 0, 1]); // source
+        out.extend_from_slice(&last_success.to_le_bytes());
+        out.extend_from_slice(&3i32.to_le_bytes()); // attempts
+        out
+    }
+
+    fn synthetic_peers_dat() -> Vec<u8> {
+        let mut body = vec![3, 32 + 3]; // formato V3_BIP155, compat V3
+        body.extend_from_slice(&[7; 32]); // nKey
+        body.extend_from_slice(&3i32.to_le_bytes());
+        body.extend_from_slice(&1i32.to_le_bytes());
+        body.extend_from_slice(&(1024i32 ^ (1 << 30)).to_le_bytes());
+        body.extend(core_addr_info_v2(core_addr_v2(1, &[203, 0, 113, 5], 8333, 1_700_000_000, 9), 0));
+        body.extend(core_addr_info_v2(core_addr_v2(4, &[0xAB; 32], 9050, 1_700_000_001, 1), 0));
+        body.extend(core_addr_info_v2(core_addr_v2(1, &[198, 51, 100, 9], 8333, 1_700_000_002, 1), 0));
+        let ipv6 = "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets();
+        body.extend(core_addr_info_v2(core_addr_v2(2, &ipv6, 18333, 1_690_000_000, 0x0d), 1_699_999_000));
+        body.extend_from_slice(&[0; 64]); // buckets, ignorados pelo importador
+        core_file(&body)
+    }
+
+    #[test]
+    fn test_parse_core_peers_dat() {
+        let (new, tried) = parse_peers_dat(&synthetic_peers_dat()).unwrap();
+        assert_eq!(new.len(), 3);
+        assert_eq!(tried.len(), 1);
+        assert_eq!(new[0].addr, Some("203.0.113.5:8333".parse().unwrap()));
+        assert_eq!(new[0].services, 9);
+        assert_eq!(new[1].addr, None); // Tor v3
+        assert_eq!(tried[0].addr, Some("[2001:db8::1]:18333".parse().unwrap()));
+        assert_eq!(tried[0].last_success, 1_699_999_000);
+
+        // Checksum errado é rejeitado
+        let mut corrupt = synthetic_peers_dat();
+        corrupt[50] ^= 1;
+        assert!(parse_peers_dat(&corrupt).is_err());
+
+        // Formato antigo (pré-BIP155): endereços de 16 bytes e services em u64
+        let mut body = vec![1, 32];
+        body.extend_from_slice(&[0; 32]);
+        body.extend_from_slice(&1i32.to_le_bytes());
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&(1i32 << 30).to_le_bytes());
+        body.extend_from_slice(&220_000i32.to_le_bytes());
+        body.extend_from_slice(&1_600_000_000u32.to_le_bytes());
+        body.extend_from_slice(&1u64.to_le_bytes());
+        body.extend_from_slice(&Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped().octets());
+        body.extend_from_slice(&8333u16.to_be_bytes());
+        body.extend_from_slice(&[0; 16]); // source
+        body.extend_from_slice(&0i64.to_le_bytes());
+        body.extend_from_slice(&0i32.to_le_bytes());
+        let (new, _) = parse_peers_dat(&core_file(&body)).unwrap();
+        assert_eq!(new[0].addr, Some("192.0.2.1:8333".parse().unwrap()));
+        assert_eq!(new[0].time, 1_600_000_000);
+    }
+
+    #[test]
+    fn test_import_core_files_into_store() {
+        let dir = temp_path("core");
+        std::fs::create_dir_all(&dir).unwrap();
+        let peers_dat = dir.join("peers.dat");
+        std::fs::write(&peers_dat, synthetic_peers_dat()).unwrap();
+
+        let mut bans = BanList::default();
+        bans.ban("198.51.100.0/24".parse().unwrap(), None, "test").unwrap();
+        let mut store = MemoryPeerStore::default();
+        let summary = import_core_file(&peers_dat, &mut store, &bans).unwrap();
+        assert_eq!((summary.new, summary.tried), (3, 1));
+        assert_eq!((summary.imported, summary.skipped, summary.banned), (2, 1, 1));
+
+        let p = store.get_peer(&"203.0.113.5:8333".parse().unwrap()).unwrap();
+        assert_eq!(p.status, PeerStatus::NeverTried);
+        assert_eq!(p.services, Some(9));
+        assert_eq!(p.last_seen, Some(1_700_000_000));
+        let p = store.get_peer(&"[2001:db8::1]:18333".parse().unwrap()).unwrap();
+        assert_eq!(p.last_connected, Some(1_699_999_000));
+
+        let mut body = vec![2]; // compactsize
+        body.extend(core_addr_v2(1, &[192, 0, 2, 44], 8333, 1_700_000_100, 1));
+        body.extend(core_addr_v2(5, &[0xCD; 32], 0, 1_700_000_100, 1)); // I2P
+        let anchors = core_file(&body);
+        assert_eq!(parse_anchors_dat(&anchors).unwrap().len(), 2);
+        let anchors_dat = dir.join("anchors.dat");
+        std::fs::write(&anchors_dat, anchors).unwrap();
+        let summary = import_core_file(&anchors_dat, &mut store, &bans).unwrap();
+        assert_eq!((summary.anchors, summary.imported, summary.skipped), (2, 1, 1));
+        assert_eq!(store.len(), 3);
+
+        // Reimportar um peers.dat mais velho não faz os horários voltarem
+        let v6: SocketAddr = "[2001:db8::1]:18333".parse().unwrap();
+        store.update_peer(v6, &mut |p| {
+            p.last_seen = Some(1_800_000_000);
+            p.last_connected = Some(1_800_000_000);
+        });
+        import_core_file(&peers_dat, &mut store, &bans).unwrap();
+        let p = store.get_peer(&v6).unwrap();
+        assert_eq!((p.last_seen, p.last_connected), (Some(1_800_000_000), Some(1_800_000_000)));
+
+        // Endereços que o addrman recusa não entram pela importação
+        let (mut capped, _) = AddrManStore::new(Box::new(MemoryPeerStore::default()), [5; 32], None);
+        capped.set_netgroup_limit(1);
+        capped.register_peer("203.0.113.77:8333".parse().unwrap(), None, None);
+        let summary = import_core_file(&peers_dat, &mut capped, &bans).unwrap();
+        assert_eq!((summary.imported, summary.refused), (1, 1));
+        assert!(capped.get_peer(&"203.0.113.5:8333".parse().unwrap()).is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_uptime_stats_decay() {
+        let mut stats = UptimeStats::default();
+        let t0 = 1_700_000_000;
+        stats.record(true, t0);
+        // Sem idade a primeira tentativa não pesa nada, como no bitcoin-seeder
+        assert_eq!(stats.h2.reliability, 0.0);
+        assert_eq!(stats.h2.count, 1.0);
+
+        for i in 1..=10 {
+            stats.record(true, t0 + i * 3600);
+        }
+        assert!(stats.h2.reliability > 0.99);
+        assert!(stats.m1.reliability > 0.01 && stats.m1.reliability < 0.02);
+
+        // Uma falha derruba mais a janela curta que a longa
+        let before = stats.clone();
+        stats.record(false, t0 + 11 * 3600);
+        assert!(stats.h2.reliability < 0.65);
+        assert!(before.h2.reliability - stats.h2.reliability > 10.0 * (before.d1.reliability - stats.d1.reliability));
+        assert_eq!(stats.last_try, Some(t0 + 11 * 3600));
+
+        // Ban e unban não contam como tentativa
+        let mut p = PeerInfo::new(peer(1), PeerStatus::NeverTried, t0);
+        p.set_status(PeerStatus::Banned, t0 + 10);
+        assert_eq!(p.uptime.last_try, None);
+        p.set_status(PeerStatus::Unreachable, t0 + 20);
+        assert_eq!(p.uptime.h2.count, 1.0);
+    }
+
+    #[test]
+    fn test_dnsseed_dump_format() {
+        let t0 = 1_700_000_000;
+        let mut good = PeerInfo::new(SocketAddr::from(([203, 0, 113, 5], 8333)), PeerStatus::NeverTried, t0);
+        for i in 0..5 {
+            good.set_status(PeerStatus::ConnectedRecently, t0 + i * 600);
+        }
+        good.services = Some(0x409);
+        good.protocol_version = Some(70016);
+        good.user_agent = Some("/Satoshi:27.0.0/".into());
+        good.start_height = Some(850_000);
+        let bad = PeerInfo::new(SocketAddr::from(([198, 51, 100, 1], 8333)), PeerStatus::Unreachable, t0);
+
+        let dump = format_dump(vec![bad, good]);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("# address"));
+        let fields: Vec<&str> = lines[1].split_whitespace().collect();
+        assert_eq!(fields[0], "203.0.113.5:8333");
+        assert_eq!(fields[1], "1");
+        assert_eq!(fields[2], (t0 + 2400).to_string());
+        assert!(fields[3].ends_with('%'));
+        assert_eq!(&fields[8..], ["850000", "00000409", "70016", "\"/Satoshi:27.0.0/\""]);
+        assert_eq!(&lines[1][47..49], "  ");
+        assert!(lines[2].starts_with("198.51.100.1:8333"));
+        assert_eq!(lines[2].split_whitespace().nth(1), Some("0"));
+
+        let store = shared(Box::new(MemoryPeerStore::default()));
+        store.lock().unwrap().register_peer(peer(1), None, None);
+        let path = temp_path("dnsseed.dump");
+        assert_eq!(write_dump(&store, &path).unwrap(), 1);
+        assert!(std::fs::read_to_string(&path).unwrap().contains("10.0.0.1:8333"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    fn export_sample() -> Vec<PeerInfo> {
+        let now = crate::p2p::database::now_secs();
+        let mut good = PeerInfo::new(SocketAddr::from(([203, 0, 113, 5], 8333)), PeerStatus::ConnectedRecently, now);
+        good.services = Some(0x409);
+        good.user_agent = Some("/Satoshi:27.0.0/,\"odd\"".into());
+        good.start_height = Some(850_000);
+        good.asn = Some(64496);
+        good.attempts = 2;
+        good.uptime.record(true, now);
+        good.last_failure = Some(PeerFailure { at: now - 60, stage: FailureStage::Connect, reason: "timeout".into() });
+        let v6 = PeerInfo::new("[2001:db8::1]:8333".parse().unwrap(), PeerStatus::NeverTried, now);
+        let stale = PeerInfo::new(SocketAddr::from(([198, 51, 100, 1], 8333)), PeerStatus::Unreachable, now - 30 * 86400);
+        vec![good, v6, stale]
+    }
+
+    #[test]
+    fn test_export_filters_and_roundtrip() {
+        let peers = export_sample();
+        for format in [ExportFormat::Csv, ExportFormat::Jsonl] {
+            let mut out = Vec::new();
+            assert_eq!(export_peers(&peers, format, &PeerFilter::default(), &mut out).unwrap(), 3);
+
+            let mut store = MemoryPeerStore::default();
+            let summary = import_peers(&mut out.as_slice(), format, &PeerFilter::default(), &mut store, &BanList::default()).unwrap();
+            assert_eq!((summary.added, summary.updated, summary.skipped), (3, 0, 0));
+            let p = store.get_peer(&peers[0].address).unwrap();
+            assert_eq!(p.user_agent, peers[0].user_agent);
+            assert_eq!(p.services, Some(0x409));
+            assert_eq!(p.last_seen, peers[0].last_seen);
+            assert_eq!(p.status, PeerStatus::ConnectedRecently);
+            assert_eq!(serde_json::to_value(&p).unwrap(), serde_json::to_value(&peers[0]).unwrap(), "{:?}", format);
+        }
+
+        // User agent vem do peer: quebra de linha não pode partir o registro CSV
+        let mut hostile = peers.clone();
+        hostile[0].user_agent = Some("/evil\nagent,\"x\"/\r".into());
+        let mut out = Vec::new();
+        export_peers(&hostile, ExportFormat::Csv, &PeerFilter::default(), &mut out).unwrap();
+        let mut store = MemoryPeerStore::default();
+        let summary = import_peers(&mut out.as_slice(), ExportFormat::Csv, &PeerFilter::default(), &mut store, &BanList::default()).unwrap();
+        assert_eq!(summary.added, 3);
+        assert_eq!(store.get_peer(&peers[0].address).unwrap().user_agent.as_deref(), Some("/evilagent,\"x\"/"));
+
+        let count = |filter: PeerFilter| export_peers(&peers, ExportFormat::Jsonl, &filter, &mut Vec::new()).unwrap();
+        assert_eq!(count(PeerFilter { network: Some(NetworkKind::Ipv6), ..Default::default() }), 1);
+        assert_eq!(count(PeerFilter { services: Some(0x408), ..Default::default() }), 1);
+        assert_eq!(count(PeerFilter { status: Some(PeerStatus::Unreachable), ..Default::default() }), 1);
+        assert_eq!(count(PeerFilter { max_age: Some(Duration::from_secs(7 * 86400)), ..Default::default() }), 2);
+    }
+
+    #[test]
+    fn test_import_merges_and_respects_bans() {
+        let peers = export_sample();
+        let mut out = Vec::new();
+        export_peers(&peers, ExportFormat::Jsonl, &PeerFilter::default(), &mut out).unwrap();
+
+        // Nossa cópia do peer é mais antiga e está banida localmente
+        let mut store = MemoryPeerStore::default();
+        store.update_peer(peers[0].address, &mut |p| {
+            p.status = PeerStatus::Banned;
+            p.first_seen = Some(1);
+            p.last_seen = Some(2);
+            p.services = Some(1);
+        });
+        let mut bans = BanList::default();
+        bans.ban("2001:db8::/32".parse().unwrap(), None, "test").unwrap();
+
+        let summary = import_peers(&mut out.as_slice(), ExportFormat::Jsonl, &PeerFilter::default(), &mut store, &bans).unwrap();
+        assert_eq!((summary.added, summary.updated, summary.skipped), (1, 1, 1));
+        let p = store.get_peer(&peers[0].address).unwrap();
+        assert_eq!(p.status, PeerStatus::Banned);
+        assert_eq!(p.first_seen, Some(1));
+        assert_eq!(p.last_seen, peers[0].last_seen);
+        assert_eq!(p.services, Some(0x409));
+
+        let bad = b"{\"address\": \"not an address\"}\n";
+        let err = import_peers(&mut &bad[..], ExportFormat::Jsonl, &PeerFilter::default(), &mut store, &bans).unwrap_err();
+        assert!(err.to_string().starts_with("line 1"));
+    }
+
+    #[test]
+    fn test_prune_failed_stale_and_netgroup_flood() {
+        let now = 1_700_000_000;
+        let policy = RetentionPolicy { max_attempts: 3, max_age: Duration::from_secs(30 * 86400), max_per_netgroup: 4 };
+        let mut store = MemoryPeerStore::default();
+
+        // Nunca respondeu em 3 tentativas
+        let dead = peer(1);
+        store.update_peer(dead, &mut |p| { p.last_seen = Some(now); p.attempts = 3; });
+        // Respondeu no passado, mas falhou 5 vezes seguidas desde então
+        let flaky = peer(2);
+        store.update_peer(flaky, &mut |p| { p.last_seen = Some(now); p.attempts = 5; p.last_connected = Some(now - 10); });
+        // Falhou uma vez ontem: ainda dentro do limite
+        let blip = peer(3);
+        store.update_peer(blip, &mut |p| {
+            p.status = PeerStatus::Unreachable;
+            p.last_seen = Some(now);
+            p.attempts = 1;
+            p.last_connected = Some(now - 86400);
+        });
+        // Nunca tentado e não anunciado há 60 dias
+        let stale = SocketAddr::from(([172, 16, 0, 1], 8333));
+        store.update_peer(stale, &mut |p| p.last_seen = Some(now - 60 * 86400));
+        // Conectou há 60 dias; cada falha renova last_seen, mas não conta como contato
+        let gone = SocketAddr::from(([172, 18, 0, 1], 8333));
+        store.update_peer(gone, &mut |p| {
+            p.status = PeerStatus::Unreachable;
+            p.last_seen = Some(now);
+            p.attempts = 1;
+            p.last_connected = Some(now - 60 * 86400);
+        });
+        // Banido e antigo: continua
+        let banned = SocketAddr::from(([172, 17, 0, 1], 8333));
+        store.update_peer(banned, &mut |p| { p.last_seen = Some(0); p.status = PeerStatus::Banned; });
+
+        // Enxurrada de uma única /16; o peer bom dela tem que sobreviver
+        for i in 0..50u8 {
+            store.update_peer(SocketAddr::from(([203, 0, 113, i], 8333)), &mut |p| p.last_seen = Some(now - i as u64));
+        }
+        let good = SocketAddr::from(([203, 0, 200, 1], 8333));
+        store.update_peer(good, &mut |p| { p.last_seen = Some(now - 1000); p.status = PeerStatus::ConnectedRecently; });
+
+        let summary = prune(&mut store, &policy, now);
+        assert_eq!((summary.failed, summary.stale, summary.over_netgroup), (2, 2, 47));
+        assert_eq!(summary.remaining, store.len());
+        assert!(store.get_peer(&dead).is_none());
+        assert!(store.get_peer(&flaky).is_none());
+        assert!(store.get_peer(&blip).is_some());
+        assert!(store.get_peer(&stale).is_none());
+        assert!(store.get_peer(&gone).is_none());
+        assert!(store.get_peer(&banned).is_some());
+        assert!(store.get_peer(&good).is_some());
+        let flood: Vec<_> = store.all_peers().into_iter().filter(|p| netgroup(p.address.ip()) == netgroup(good.ip())).collect();
+        assert_eq!(flood.len(), 4);
+        assert!(flood.iter().any(|p| p.address == SocketAddr::from(([203, 0, 113, 0], 8333))));
+
+        // Zero desliga todas as regras
+        let off = RetentionPolicy { max_attempts: 0, max_age: Duration::ZERO, max_per_netgroup: 0 };
+        assert_eq!(prune(&mut store, &off, now + 365 * 86400).total(), 0);
+
+        // O limite por rede também vale na chegada, sem esperar a próxima limpeza
+        let (mut capped, _) = AddrManStore::new(Box::new(MemoryPeerStore::default()), [5; 32], None);
+        capped.set_netgroup_limit(4);
+        let source = SocketAddr::from(([66, 66, 1, 1], 8333));
+        for i in 0..50u8 {
+            capped.register_peer(SocketAddr::from(([203, 0, 113, i], 8333)), None, Some(source));
+        }
+        assert_eq!(capped.len(), 4);
+        // Quem já está no banco continua recebendo atualizações; removido um, abre vaga
+        let first = capped.all_peers()[0].address;
+        capped.register_peer(first, Some(0x409), Some(source));
+        assert_eq!(capped.get_peer(&first).unwrap().services, Some(0x409));
+        assert!(capped.remove_peer(&first));
+        capped.register_peer(SocketAddr::from(([203, 0, 114, 1], 8333)), None, Some(source));
+        capped.register_peer(SocketAddr::from(([198, 51, 100, 1], 8333)), None, Some(source));
+        assert_eq!(capped.len(), 5);
+    }
+
+    #[test]
+    fn test_attempts_count_and_sqlite_remove() {
+        let mut p = PeerInfo::new(peer(1), PeerStatus::NeverTried, 0);
+        p.set_status(PeerStatus::Unreachable, 1);
+        p.set_status(PeerStatus::Unreachable, 2);
+        assert_eq!(p.attempts, 2);
+        p.set_status(PeerStatus::ConnectedRecently, 3);
+        assert_eq!(p.attempts, 0);
+
+        let mut store = SqlitePeerStore::with_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        store.register_peer(peer(1), None, None);
+        assert!(store.remove_peer(&peer(1)));
+        assert!(!store.remove_peer(&peer(1)));
+        assert_eq!(store.len(), 0);
+
+        // As escritas ficam numa transação até o persist, e erros de escrita aparecem nele
+        let path = temp_path("peers.sqlite");
+        let mut store = SqlitePeerStore::open(&path).unwrap();
+        store.register_peer(peer(2), None, None);
+        assert_eq!(SqlitePeerStore::open(&path).unwrap().len(), 0);
+        store.persist().unwrap();
+        assert_eq!(SqlitePeerStore::open(&path).unwrap().len(), 1);
+        drop(store);
+        let read_only = rusqlite::Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let mut store = SqlitePeerStore::with_connection(read_only).unwrap();
+        store.register_peer(peer(3), None, None);
+        assert!(store.persist().is_err());
+        assert!(store.persist().is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn spread_addr(i: u32) -> SocketAddr {
+        // Cada endereço numa /16 diferente, como faria um atacante esperto
+        SocketAddr::from(([(i >> 16) as u8 + 1, (i >> 8) as u8, i as u8, 1], 8333))
+    }
+
+    #[test]
+    fn test_addrman_single_source_cannot_dominate() {
+        let mut addrman = AddrMan::new([7; 32]);
+        let attacker = SocketAddr::from(([66, 66, 1, 1], 8333));
+        let mut accepted = 0;
+        for i in 0..20_000 {
+            if addrman.add(spread_addr(i), attacker, &|_| false) == AddOutcome::Added {
+                accepted += 1;
+            }
+        }
+        let buckets: HashSet<usize> = addrman.new_buckets().collect();
+        assert!(buckets.len() as u64 <= NEW_BUCKETS_PER_SOURCE_GROUP);
+        assert!(accepted <= NEW_BUCKETS_PER_SOURCE_GROUP as usize * BUCKET_SIZE);
+
+        // Mais fontes na mesma /16 não ajudam: continua sendo um grupo só
+        for s in 0..200u8 {
+            let sybil = SocketAddr::from(([66, 66, s, 2], 8333));
+            for i in 0..100 {
+                addrman.add(spread_addr(100_000 + s as u32 * 100 + i), sybil, &|_| false);
+            }
+        }
+        let buckets: HashSet<usize> = addrman.new_buckets().collect();
+        assert!(buckets.len() as u64 <= NEW_BUCKETS_PER_SOURCE_GROUP);
+
+        // Fontes honestas em outras redes ainda encontram espaço
+        let mut honest = 0;
+        for s in 0..100u8 {
+            let source = SocketAddr::from(([100, s, 0, 1], 8333));
+            for i in 0..5u8 {
+                let addr = SocketAddr::from(([150, s, i, 7], 8333));
+                if addrman.add(addr, source, &|_| false) == AddOutcome::Added {
+                    honest += 1;
+                }
+            }
+        }
+        // Algumas colidem entre si no mesmo bucket, mas a grande maioria entra
+        assert!(honest >= 400, "only {} honest addresses accepted", honest);
+    }
+
+    #[test]
+    fn test_addrman_tried_limits_one_netgroup() {
+        let mut addrman = AddrMan::new([9; 32]);
+        for i in 0..2000u32 {
+            let addr = SocketAddr::from(([203, 0, (i >> 8) as u8, i as u8], 8333));
+            let source = SocketAddr::from(([(i % 200) as u8 + 1, 1, 1, 1], 8333));
+            if addrman.add(addr, source, &|_| false) == AddOutcome::Added {
+                addrman.mark_good(addr, 0);
+            }
+        }
+        let buckets: HashSet<usize> = addrman.tried_buckets().collect();
+        assert!(buckets.len() as u64 <= TRIED_BUCKETS_PER_GROUP);
+        assert!(addrman.len_tried() <= TRIED_BUCKETS_PER_GROUP as usize * BUCKET_SIZE);
+    }
+
+    #[test]
+    fn test_addrman_collision_keeps_good_entry_and_replaces_terrible() {
+        let mut addrman = AddrMan::new([3; 32]);
+        let source = SocketAddr::from(([1, 2, 3, 4], 8333));
+        let first = SocketAddr::from(([10, 1, 0, 1], 8333));
+        assert_eq!(addrman.add(first, source, &|_| false), AddOutcome::Added);
+        assert_eq!(addrman.add(first, source, &|_| false), AddOutcome::Known);
+
+        // Procura outro endereço que caia no mesmo slot
+        let mut rival = None;
+        for i in 0..200_000u32 {
+            let candidate = SocketAddr::from(([10, 1, (i >> 8) as u8, i as u8], (i >> 16) as u16 + 1));
+            if candidate == first {
+                continue;
+            }
+            match addrman.add(candidate, source, &|_| false) {
+                AddOutcome::Rejected => {
+                    rival = Some(candidate);
+                    break;
+                }
+                _ => {
+                    addrman.remove(&candidate);
+                }
+            }
+        }
+        let rival = rival.expect("no colliding address found");
+        assert_eq!(addrman.add(rival, source, &|_| false), AddOutcome::Rejected);
+        assert_eq!(addrman.add(rival, source, &|a| *a == first), AddOutcome::Replaced(first));
+        assert!(!addrman.contains(&first));
+
+        addrman.mark_good(rival, 0);
+        assert!(addrman.is_tried(&rival));
+        assert_eq!(addrman.len_new(), 0);
+    }
+
+    #[test]
+    fn test_addrman_tried_collision_tests_before_evict() {
+        let mut addrman = AddrMan::new([4; 32]);
+        let source = SocketAddr::from(([1, 2, 3, 4], 8333));
+        let occupant = SocketAddr::from(([10, 2, 0, 1], 8333));
+        addrman.add(occupant, source, &|_| false);
+        assert!(addrman.mark_good(occupant, 0));
+
+        // Procura um endereço que caia na mesma vaga da tried
+        let newcomer = (1..5000u32)
+            .map(|i| SocketAddr::from(([10, 2, (i >> 8) as u8, i as u8], 8334)))
+            .find(|&candidate| {
+                addrman.add(candidate, source, &|_| false);
+                if addrman.mark_good(candidate, 100) {
+                    addrman.remove(&candidate);
+                    return false;
+                }
+                addrman.contains(&candidate)
+            })
+            .expect("no tried collision found");
+        assert!(addrman.is_tried(&occupant));
+        assert!(!addrman.is_tried(&newcomer));
+
+        // Sem veredito nada muda; ocupante bom fica; ocupante que falhou volta para a new
+        assert!(addrman.resolve_collisions(&|_, _| None).is_empty());
+        assert!(addrman.is_tried(&occupant));
+        addrman.resolve_collisions(&|_, _| Some(true));
+        addrman.resolve_collisions(&|_, _| Some(false));
+        assert!(addrman.is_tried(&occupant));
+        addrman.mark_good(newcomer, 200);
+        assert!(addrman.resolve_collisions(&|old, since| (*old == occupant && since == 200).then_some(false)).is_empty());
+        assert!(addrman.is_tried(&newcomer));
+        assert!(addrman.contains(&occupant) && !addrman.is_tried(&occupant));
+    }
+
+    #[test]
+    fn test_addrman_store_filters_gossip_and_rebuilds() {
+        let mut inner = MemoryPeerStore::default();
+        let good = SocketAddr::from(([198, 51, 100, 1], 8333));
+        inner.update_status(good, PeerStatus::ConnectedRecently);
+        let (mut store, dropped) = AddrManStore::new(Box::new(inner), [5; 32], None);
+        assert_eq!(dropped, 0);
+        assert!(store.addrman().is_tried(&good));
+
+        let attacker = SocketAddr::from(([66, 66, 1, 1], 8333));
+        for i in 0..20_000 {
+            store.register_peer(spread_addr(i), None, Some(attacker));
+        }
+        assert!(store.len() <= 1 + NEW_BUCKETS_PER_SOURCE_GROUP as usize * BUCKET_SIZE);
+        assert_eq!(store.len(), store.addrman().len_new() + store.addrman().len_tried());
+
+        let victim = store.all_peers().into_iter().find(|p| p.address != good).unwrap().address;
+        assert!(store.remove_peer(&victim));
+        assert!(!store.addrman().contains(&victim));
+    }
+
+    #[test]
+    fn test_addrman_store_keeps_and_updates_peers_without_slot() {
+        // Banco antigo com mais peers de uma origem do que as tabelas comportam
+        let mut inner = MemoryPeerStore::default();
+        let attacker = SocketAddr::from(([66, 66, 1, 1], 8333));
+        for i in 0..5000 {
+            inner.register_peer(spread_addr(i), None, Some(attacker));
+        }
+        let (mut store, unindexed) = AddrManStore::new(Box::new(inner), [5; 32], None);
+        assert!(unindexed > 0);
+        assert_eq!(store.len(), 5000);
+        assert_eq!(store.addrman().len_new() + unindexed, 5000);
+
+        // Atualizações de um endereço nomeado valem mesmo sem vaga
+        let homeless = (0..5000).map(spread_addr).find(|a| !store.addrman().contains(a)).unwrap();
+        store.update_status(homeless, PeerStatus::Banned);
+        store.update_peer(homeless, &mut |p| p.misbehavior_score = 100);
+        let p = store.get_peer(&homeless).unwrap();
+        assert_eq!(p.status, PeerStatus::Banned);
+        assert_eq!(p.misbehavior_score, 100);
+
+        // Mas uma atualização não cria peer que a fofoca não poderia criar
+        store.set_netgroup_limit(1);
+        let crowded = SocketAddr::from(([1, 0, 9, 9], 8333)); // mesma /16 de spread_addr(0)
+        store.update_status(crowded, PeerStatus::ConnectedRecently);
+        store.update_peer(crowded, &mut |p| p.misbehavior_score = 1);
+        assert!(store.get_peer(&crowded).is_none());
+        let mut out = Vec::new();
+        let mut row = PeerInfo::new(crowded, PeerStatus::ConnectedRecently, 1);
+        row.last_seen = Some(1);
+        export_peers(&[row], ExportFormat::Jsonl, &PeerFilter::default(), &mut out).unwrap();
+        let summary = import_peers(&mut out.as_slice(), ExportFormat::Jsonl, &PeerFilter::default(), &mut store, &BanList::default()).unwrap();
+        assert_eq!((summary.added, summary.skipped), (0, 1));
+        assert!(store.get_peer(&crowded).is_none());
+    }
+
+    fn obs(time: u64, last_octet: u8, success: bool) -> Observation {
+        Observation {
+            time,
+            address: peer(last_octet),
+            success,
+            latency_ms: success.then_some(42),
+            version: success.then_some(70016),
+            height: success.then_some(850_000),
+        }
+    }
+
+    #[test]
+    fn test_history_rollups_queries_and_reload() {
+        let path = temp_path("history.log");
+        let t0 = 1_700_002_800; // início de uma hora
+        {
+            let mut history = HistoryLog::open(&path).unwrap();
+            history.record(obs(t0, 1, true)).unwrap();
+            history.record(obs(t0 + 10, 2, true)).unwrap();
+            history.record(obs(t0 + 20, 1, true)).unwrap(); // mesmo nó, conta uma vez
+            history.record(obs(t0 + 30, 3, false)).unwrap();
+            history.record(obs(t0 + 3600, 1, false)).unwrap();
+            history.record(obs(t0 + 3700, 2, true)).unwrap();
+            history.flush().unwrap();
+            // Escrito depois do flush: só existe no log
+            history.record(obs(t0 + 7200, 2, true)).unwrap();
+        }
+
+        // O que ficou só no log (queda antes do flush) entra nos rollups ao reabrir
+        let history = HistoryLog::open(&path).unwrap();
+        let hours = history.rollups(t0, t0 + 3 * 3600);
+        assert_eq!(hours.len(), 3);
+        assert_eq!((hours[0].1.attempts, hours[0].1.successes, hours[0].1.reachable), (4, 3, 2));
+        assert_eq!((hours[2].1.attempts, hours[2].1.reachable), (1, 1));
+        assert_eq!(history.reachable_at(t0 + 3650), Some(1));
+        assert_eq!(history.reachable_at(t0 - 3600), None);
+
+        let p1 = history.peer_history(&peer(1)).unwrap();
+        assert_eq!(p1.len(), 3);
+        assert_eq!(p1[0], obs(t0, 1, true));
+        assert_eq!(history.went_offline(&peer(1)).unwrap(), Some(t0 + 20));
+        assert_eq!(history.went_offline(&peer(2)).unwrap(), None);
+        assert_eq!(history.peer_history(&peer(2)).unwrap().len(), 3);
+
+        // Reabrir de novo não conta a cauda duas vezes
+        drop(history);
+        let mut history = HistoryLog::open(&path).unwrap();
+        history.flush().unwrap();
+        drop(history);
+        let history = HistoryLog::open(&path).unwrap();
+        assert_eq!(history.rollups(t0, t0 + 3 * 3600)[2].1.attempts, 1);
+
+        // Sem o arquivo de rollups, tudo é reconstruído a partir do log
+        drop(history);
+        std::fs::remove_file(path.with_file_name(format!("{}.rollup.json", path.file_name().unwrap().to_string_lossy()))).unwrap();
+        let history = HistoryLog::open(&path).unwrap();
+        let hours = history.rollups(t0, t0 + 3 * 3600);
+        assert_eq!(hours.len(), 3);
+        assert_eq!(hours[0].1.reachable, 2);
+
+        let plot = plot_reachable(&hours, 10);
+        let lines: Vec<&str> = plot.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("██████████ 2 (4 tentativas)"));
+        assert!(lines[1].contains("█████      1 (2 tentativas)"));
+
+        // Uma escrita cortada pela metade é descartada ao abrir; o próximo registro não gruda nela
+        drop(history);
+        use std::io::Write;
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"1700010000\t10.0.0").unwrap();
+        let mut history = HistoryLog::open(&path).unwrap();
+        history.record(obs(t0 + 7300, 3, true)).unwrap();
+        drop(history);
+        let history = HistoryLog::open(&path).unwrap();
+        assert_eq!(history.observations().unwrap().len(), 8);
+        assert_eq!(history.peer_history(&peer(3)).unwrap(), vec![obs(t0 + 30, 3, false), obs(t0 + 7300, 3, true)]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_census_counts_networks_agents_services_and_ports() {
+        let now = 1_700_000_000;
+        let mut a = PeerInfo::new(peer(1), PeerStatus::ConnectedRecently, now);
+        a.user_agent = Some("/Satoshi:27.0.0/".to_string());
+        a.protocol_version = Some(70016);
+        a.services = Some(0x409);
+        let mut b = PeerInfo::new(SocketAddr::from(([203, 0, 113, 5], 18444)), PeerStatus::Unreachable, now);
+        b.user_agent = Some("/Satoshi:27.0.0/".to_string());
+        b.services = Some(0x409);
+        let c = PeerInfo::new("[2001:db8::1]:8333".parse().unwrap(), PeerStatus::NeverTried, now);
+        let d = PeerInfo::new("[fd87:d87e:eb43::1]:8333".parse().unwrap(), PeerStatus::ConnectedRecently, now);
+
+        let census = build_census(&[a, b, c, d]);
+        assert_eq!((census.total, census.reachable), (4, 2));
+        let row = |rows: &[CensusRow], key: &str| rows.iter().find(|r| r.key == key).map(|r| (r.total, r.reachable));
+        assert_eq!(census.networks[0], CensusRow { key: "IPv4".to_string(), total: 2, reachable: 1 });
+        assert_eq!(row(&census.networks, "IPv6"), Some((1, 0)));
+        assert_eq!(row(&census.networks, "Onion"), Some((1, 1)));
+        assert_eq!(row(&census.user_agents, "/Satoshi:27.0.0/"), Some((2, 1)));
+        assert_eq!(row(&census.protocol_versions, "70016"), Some((1, 1)));
+        assert_eq!(row(&census.services, "NETWORK|WITNESS|NETWORK_LIMITED (0x409)"), Some((2, 1)));
+        assert_eq!(row(&census.ports, "8333 (padrão)"), Some((3, 2)));
+        assert_eq!(row(&census.ports, "18444"), Some((1, 0)));
+        assert_eq!(service_names(0), "NONE (0x0)");
+
+        let table = render_table(&census, 1);
+        assert!(table.starts_with("Peers: 4 (2 alcançáveis)"));
+        assert!(table.contains("(outros 2)"));
+    }
+
+    // Primeiro bit do IP 0 (IPv4 mapeado, 2001:db8::) vai para AS 64500, 1 para AS 64501
+    fn split_asmap() -> Vec<u8> {
+        let skip = assemble_bits(&[Op::Return(64500)]).len() as u32;
+        assemble(&[Op::Jump(skip), Op::Return(64500), Op::Return(64501)])
+    }
+
+    #[test]
+    fn test_asmap_decodes_core_bytecode() {
+        let all = Asmap::from_bytes(&assemble(&[Op::Return(13335)])).unwrap();
+        assert_eq!(all.lookup("1.1.1.1".parse().unwrap()), Some(13335));
+        assert_eq!(all.lookup("fd87:d87e:eb43::1".parse().unwrap()), None);
+
+        let split = Asmap::from_bytes(&split_asmap()).unwrap();
+        assert_eq!(split.lookup("10.0.0.1".parse().unwrap()), Some(64500));
+        assert_eq!(split.lookup("2001:db8::1".parse().unwrap()), Some(64500));
+        assert_eq!(split.lookup("8000::1".parse().unwrap()), Some(64501));
+
+        // ::ffff:0:0/96 é só zeros e uns: 80 zeros não batem com o MATCH de um 1
+        let matched = assemble(&[Op::Default(7), Op::Match(0b11), Op::Return(9)]);
+        let matched = Asmap::from_bytes(&matched).unwrap();
+        assert_eq!(matched.lookup("8000::1".parse().unwrap()), Some(9));
+        assert_eq!(matched.lookup("10.0.0.1".parse().unwrap()), Some(7));
+        // Sem DEFAULT um MATCH que falha dá ASN 0, ou seja, "sem mapeamento"
+        let zero = assemble(&[Op::Match(0b11), Op::Return(9)]);
+        assert_eq!(Asmap::from_bytes(&zero).unwrap().lookup("10.0.0.1".parse().unwrap()), None);
+
+        // Arquivos que o Core rejeita
+        let mut truncated = split_asmap();
+        truncated.pop();
+        assert!(Asmap::from_bytes(&truncated).is_err());
+        let mut padded = assemble(&[Op::Return(13335)]);
+        padded.push(0);
+        assert!(Asmap::from_bytes(&padded).is_err());
+        assert!(Asmap::from_bytes(&assemble(&[Op::Default(1), Op::Return(2)])).is_err());
+        assert!(Asmap::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_asmap_attaches_asn_and_spreads_dns_answers() {
+        let asmap = || Some(Asmap::from_bytes(&split_asmap()).unwrap());
+        let mut inner = MemoryPeerStore::default();
+        inner.register_peer(peer(1), None, None);
+        let (mut store, _) = AddrManStore::new(Box::new(inner), [5; 32], asmap());
+        assert_eq!(store.get_peer(&peer(1)).unwrap().asn, Some(64500));
+
+        let far: SocketAddr = "[8000::1]:8333".parse().unwrap();
+        store.register_peer(far, None, Some(peer(1)));
+        assert_eq!(store.get_peer(&far).unwrap().asn, Some(64501));
+
+        // Sem asmap o ASN gravado fica como está
+        let (store, _) = AddrManStore::new(Box::new(store), [5; 32], None);
+        assert_eq!(store.get_peer(&far).unwrap().asn, Some(64501));
+
+        let mut good: Vec<PeerInfo> = (1..=8)
+            .map(|i| PeerInfo { asn: Some(64500), ..PeerInfo::new(peer(i), PeerStatus::ConnectedRecently, 0) })
+            .collect();
+        good.push(PeerInfo { asn: Some(64501), ..PeerInfo::new(far, PeerStatus::ConnectedRecently, 0) });
+        for _ in 0..20 {
+            let picked = pick_diverse(good.clone(), 2);
+            assert_eq!(picked.len(), 2);
+            assert!(picked.contains(&far));
+        }
+        assert_eq!(pick_diverse(good, 20).len(), 9);
+    }
+
+    #[test]
+    fn test_log_file_rotates_by_size_and_age() {
+        let path = temp_path("seeder.log");
+        let config = LogFileConfig { path: path.clone(), max_size: 20, max_age: Duration::ZERO, keep: 2 };
+        let mut file = RotatingFile::open(config.clone()).unwrap();
+        for line in ["linha 1 .....", "linha 2 .....", "linha 3 .....", "linha 4 ....."] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "linha 4 .....\n");
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 1)).unwrap(), "linha 3 .....\n");
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 2)).unwrap(), "linha 2 .....\n");
+        assert!(!backup_path(&path, 3).exists());
+
+        // Reaberto, continua anexando; por idade gira mesmo abaixo do tamanho
+        let aged = LogFileConfig { max_size: 0, max_age: Duration::from_millis(10), ..config };
+        let mut file = RotatingFile::open(aged).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        file.write_line("linha 5").unwrap();
+        file.write_line("linha 6").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "linha 5\nlinha 6\n");
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 1)).unwrap(), "linha 4 .....\n");
+
+        for n in 0..=2 {
+            std::fs::remove_file(if n == 0 { path.clone() } else { backup_path(&path, n) }).ok();
+        }
+
+        // Se girar falha, a linha vai para o arquivo atual e a próxima tentativa espera
+        let path = temp_path("stuck.log");
+        std::fs::create_dir_all(backup_path(&path, 1).join("ocupado")).unwrap();
+        let stuck = LogFileConfig { path: path.clone(), max_size: 10, max_age: Duration::ZERO, keep: 1 };
+        let mut file = RotatingFile::open(stuck).unwrap();
+        file.write_line("linha a ...").unwrap();
+        assert!(file.write_line("linha b ...").is_err());
+        file.write_line("linha c ...").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "linha a ...\nlinha b ...\nlinha c ...\n");
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_dir_all(backup_path(&path, 1)).ok();
+        let msg = LogMessage::new(LogLevel::Info, Event::DnsAnswered(peer(1), 4));
+        assert!(msg.to_text().starts_with("[INFO ] dns: "));
+
+        // Tamanhos que não cabem em u64 são recusados pelo parser, sem estourar
+        use clap::Parser;
+        let size = |arg: &str| crate::cli::Cli::try_parse_from(["seeder", "--log-max-size", arg]).map(|c| c.log_max_size);
+        assert_eq!(size("512K").unwrap(), 512 << 10);
+        assert!(size("99999999999G").is_err());
+        assert!(size("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_log_message_json_has_typed_fields() {
+        let msg = LogMessage::new(LogLevel::Warn, Event::Misbehavior(peer(1), "Invalid checksum".into(), 40));
+        let json = msg.to_json("2024-01-01T00:00:00Z");
+        assert_eq!(json["timestamp"], "2024-01-01T00:00:00Z");
+        assert_eq!(json["level"], "warn");
+        assert_eq!(json["component"], "crawler");
+        assert_eq!(json["event"], "misbehavior");
+        assert_eq!(json["peer"], "10.0.0.1:8333");
+        assert_eq!(json["violation"], "Invalid checksum");
+        assert_eq!(json["score"], 40);
+        assert_eq!(json["message"], msg.event.to_string());
+
+        let json = LogMessage::new(LogLevel::Info, Event::DnsAnswered(peer(2), 7)).to_json("t");
+        assert_eq!((json["component"].as_str(), json["event"].as_str()), (Some("dns"), Some("dns_answered")));
+        assert_eq!((json["client"].as_str(), json["peers"].as_u64()), (Some("10.0.0.2:8333"), Some(7)));
+        let json = LogMessage::new(LogLevel::Debug, Event::SavedToDisk(3, 10, Duration::from_millis(1500))).to_json("t");
+        assert_eq!(json["elapsed_ms"], 1500);
+
+        let json = LogMessage::new(LogLevel::Error, Event::CoreImportFailed("peers.dat".into(), "bad magic".into())).to_json("t");
+        assert_eq!((json["component"].as_str(), json["event"].as_str()), (Some("db"), Some("core_import_failed")));
+        assert_eq!((json["file"].as_str(), json["error"].as_str()), (Some("peers.dat"), Some("bad magic")));
+        let json = LogMessage::new(LogLevel::Info, Event::Banned("10.0.0.0/24".into(), None, "spam".into())).to_json("t");
+        assert_eq!((json["subnet"].as_str(), json["reason"].as_str()), (Some("10.0.0.0/24"), Some("spam")));
+        assert!(json["duration_secs"].is_null());
+    }
+
+    #[test]
+    fn test_log_filter_per_component() {
+        let mut filter = LogFilter::parse("info,dns=trace,crawler=warn").unwrap();
+        assert!(filter.enabled(Component::Dns, LogLevel::Trace));
+        assert!(!filter.enabled(Component::Crawler, LogLevel::Info));
+        assert!(filter.enabled(Component::Crawler, LogLevel::Error));
+        assert!(filter.enabled(Component::Db, LogLevel::Info));
+        assert!(!filter.enabled(Component::Db, LogLevel::Debug));
+        assert_eq!(filter.to_string(), "info,crawler=warn,dns=trace");
+
+        // Em tempo de execução: só muda o que foi pedido, e nada se algo for inválido
+        filter.apply("debug, crawler=trace").unwrap();
+        assert_eq!(filter.to_string(), "debug,crawler=trace,dns=trace");
+        assert!(filter.apply("db=error,p2p=info").is_err());
+        assert!(filter.apply("dns=loud").is_err());
+        assert_eq!(filter.to_string(), "debug,crawler=trace,dns=trace");
+        assert!(LogFilter::parse("verbose").is_err());
+
+        // Connected é do crawler por padrão, mas o cliente interativo marca como seu
+        let (tx, rx) = std::sync::mpsc::channel();
+        log_as(&tx, Component::Client, LogLevel::Info, Event::Connected(peer(1)));
+        assert_eq!(rx.recv().unwrap().component, Component::Client);
+        assert_eq!(LogMessage::new(LogLevel::Info, Event::Connected(peer(1))).component, Component::Crawler);
+    }
+
+    #[test]
+    fn test_client_events_are_leveled_and_typed() {
+        // O tráfego por mensagem fica em debug; o resumo em info
+        let filter = LogFilter::parse("info").unwrap();
+        let received = LogMessage::new(LogLevel::Debug, Event::MessageReceived(peer(1), "inv".into(), 37));
+        assert_eq!(received.component, Component::Client);
+        assert!(!filter.enabled(received.component, received.level));
+        assert!(filter.enabled(Component::Client, LogLevel::Info));
+
+        let json = LogMessage::new(LogLevel::Info, Event::PingMeasured(peer(1), 42)).to_json("t");
+        assert_eq!(json["event"], "ping_measured");
+        assert_eq!(json["ping_ms"], 42);
+        let json = received.to_json("t");
+        assert_eq!(json["command"], "inv");
+        assert_eq!(json["bytes"], 37);
+    }
+
+    #[test]
+    fn test_log_buffer_keeps_last_lines_and_filters() {
+        let mut buffer = LogBuffer::new(3);
+        buffer.push("t0", &LogMessage::new(LogLevel::Info, Event::DnsAnswered(peer(1), 4)));
+        buffer.push("t1", &LogMessage::new(LogLevel::Debug, Event::MessageReceived(peer(2), "inv".into(), 37)));
+        buffer.push("t2", &LogMessage::new(LogLevel::Warn, Event::DnsMalformed(peer(3))));
+        buffer.push("t3", &LogMessage::new(LogLevel::Info, Event::HandshakeComplete(peer(4))));
+        assert_eq!(buffer.next_seq(), 4);
+
+        // O mais antigo saiu do buffer
+        let all = buffer.query(&LogQuery::default());
+        assert_eq!(all.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let query = LogQuery::parse(&["info", "dns"]).unwrap();
+        let dns = buffer.query(&query);
+        assert_eq!(dns.len(), 1);
+        assert_eq!(dns[0].to_string(), format!("t2 [WARN ] dns: Consulta DNS malformada de {}", peer(3)));
+
+        let last = buffer.query(&LogQuery::parse(&["1"]).unwrap());
+        assert_eq!(last[0].component, Component::Client);
+        assert_eq!(last[0].seq, 3);
+        assert_eq!(buffer.since(2, &LogQuery::parse(&["client"]).unwrap()).len(), 1);
+        assert!(LogQuery::parse(&["loud"]).is_none());
+
+        // O buffer guarda o que --verbosity esconde, até o seu próprio nível mínimo
+        let buffer = Arc::new(Mutex::new(LogBuffer::new(10).with_min_level(LogLevel::Debug)));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let filter = Arc::new(Mutex::new(LogFilter::parse("info").unwrap()));
+        let logger = Logger::spawn(filter, rx, LogFormat::Text, false, None, buffer.clone());
+        log(&tx, LogLevel::Debug, Event::MessageReceived(peer(5), "inv".into(), 37));
+        log(&tx, LogLevel::Trace, Event::DnsAnswered(peer(6), 4));
+        drop(tx);
+        logger.join().unwrap();
+        let kept = buffer.lock().unwrap().query(&LogQuery::parse(&["trace"]).unwrap());
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].level, LogLevel::Debug);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_reports_crawl_and_peer_gauges() {
+        use std::io::{Read, Write};
+
+        let node = spawn_fake_node(vec![(peer(7), 0x9), (peer(8), 0x1)]).await;
+        let metrics = Arc::new(Metrics::default());
+        let store = crawl_into_store_with(node, Arc::new(Mutex::new(BanList::default())), metrics.clone()).await;
+        metrics.dns_query(1, "NOERROR");
+        metrics.dns_query(28, "NOTIMP");
+        metrics.handshake_failed(failure_reason(&Misbehavior::BadMagic.into_io()));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        serve_metrics(addr, metrics, store).unwrap();
+        let scrape = |path: &str| {
+            let mut conn = std::net::TcpStream::connect(addr).unwrap();
+            write!(conn, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut body = String::new();
+            conn.read_to_string(&mut body).unwrap();
+            body
+        };
+
+        let body = scrape("/metrics");
+        assert!(body.starts_with("HTTP/1.1 200 OK"));
+        for line in [
+            "seeder_crawl_attempts_total 1",
+            "seeder_crawl_successes_total 1",
+            "seeder_addr_messages_total 1",
+            "seeder_addresses_learned_total 2",
+            "seeder_handshake_failures_total{reason=\"bad_magic\"} 1",
+            "seeder_dns_queries_total{type=\"A\",rcode=\"NOERROR\"} 1",
+            "seeder_dns_queries_total{type=\"AAAA\",rcode=\"NOTIMP\"} 1",
+            "seeder_peers{status=\"never_tried\"} 2",
+            "seeder_peers{status=\"connected_recently\"} 1",
+            "seeder_good_nodes{filter=\"x409\"} 1",
+            "seeder_good_nodes{filter=\"xd\"} 0",
+            "seeder_handshake_latency_seconds_count 1",
+            "seeder_ping_latency_seconds_bucket{le=\"+Inf\"} 1",
+        ] {
+            assert!(body.lines().any(|l| l == line), "faltando: {}", line);
+        }
+        assert!(scrape("/").starts_with("HTTP/1.1 404"));
+    }
+
+    fn rpc_auth() -> RpcAuth {
+        let mut auth = RpcAuth::default();
+        auth.add(Credential::Password { user: "teste".into(), password: "senha".into() });
+        auth
+    }
+
+    fn basic(user: &str, password: &str) -> String {
+        use base64::Engine;
+        format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password)))
+    }
+
+    fn rpc_context() -> RpcContext {
+        let (log_tx, _log_rx) = std::sync::mpsc::channel();
+        RpcContext::new(Services::new(
+            shared(Box::new(MemoryPeerStore::default())),
+            Arc::new(Mutex::new(BanList::default())),
+            Arc::new(Mutex::new(HistoryLog::default())),
+            log_tx,
+            Arc::new(Mutex::new(LogFilter::parse("info").unwrap())),
+            Arc::new(Mutex::new(LogBuffer::new(10))),
+            FlushPolicy { interval: Duration::from_secs(3600), max_dirty: 1000 },
+            RetentionPolicy { max_attempts: 0, max_age: Duration::ZERO, max_per_netgroup: 0 },
+        ), rpc_auth())
+    }
+
+    fn rpc(ctx: &RpcContext, body: &str) -> Option<serde_json::Value> {
+        handle_body(ctx, body.as_bytes())
+    }
+
+    #[test]
+    fn test_rpc_addnode_and_getinfo() {
+        let ctx = rpc_context();
+        ctx.services.ban_list.lock().unwrap().ban("198.51.100.0/24".parse::<Subnet>().unwrap(), None, "teste").unwrap();
+
+        let r = rpc(&ctx, r#"{"jsonrpc":"2.0","method":"addnode","params":["203.0.113.5"],"id":1}"#).unwrap();
+        assert_eq!(r["result"], serde_json::Value::Null);
+        assert_eq!(r["id"], 1);
+        let r = rpc(&ctx, r#"{"jsonrpc":"2.0","method":"addnode","params":{"node":"198.51.100.9:8333"},"id":2}"#).unwrap();
+        assert_eq!(r["error"]["code"], RpcError::INVALID_PARAMS);
+        let queued: Vec<_> = ctx.services.crawl.queue.lock().unwrap().iter().copied().collect();
+        assert_eq!(queued, vec![SocketAddr::from(([203, 0, 113, 5], 8333))]);
+
+        let r = rpc(&ctx, r#"{"jsonrpc":"2.0","method":"getinfo","id":"x"}"#).unwrap();
+        assert_eq!(r["id"], "x");
+        assert_eq!(r["result"]["peers"], 1);
+        assert_eq!(r["result"]["peers_by_status"]["never_tried"], 1);
+        assert_eq!(r["result"]["crawl"]["queued"], 1);
+        assert_eq!(r["result"]["dns_queries"], 0);
+
+        // Notificação não tem resposta; erros seguem o JSON-RPC 2.0
+        assert!(rpc(&ctx, r#"{"jsonrpc":"2.0","method":"getinfo"}"#).is_none());
+        assert_eq!(rpc(&ctx, "{oops").unwrap()["error"]["code"], RpcError::PARSE_ERROR);
+        assert_eq!(rpc(&ctx, r#"{"method":"getinfo","id":3}"#).unwrap()["error"]["code"], RpcError::INVALID_REQUEST);
+        let batch = rpc(&ctx, r#"[{"jsonrpc":"2.0","method":"nope","id":4},{"jsonrpc":"2.0","method":"getinfo","id":5}]"#).unwrap();
+        assert_eq!(batch[0]["error"]["code"], RpcError::METHOD_NOT_FOUND);
+        assert_eq!(batch[1]["result"]["peers"], 1);
+    }
+
+    #[test]
+    fn test_rpc_peer_ban_crawl_and_log_methods() {
+        let ctx = rpc_context();
+        {
+            let mut store = ctx.services.peer_store.lock().unwrap();
+            for i in 1..=5u8 {
+                store.register_peer(SocketAddr::from(([10, 0, 0, i], 8333)), Some(if i % 2 == 0 { 0x9 } else { 0x1 }), None);
+            }
+            store.update_status(SocketAddr::from(([10, 0, 0, 2], 8333)), PeerStatus::ConnectedRecently);
+        }
+        let call = |method: &str, params: &str| {
+            let body = format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{},"id":1}}"#, method, params);
+            rpc(&ctx, &body).unwrap()
+        };
+
+        // Filtros e paginação, ordenado por endereço
+        let r = call("listpeers", r#"{"services":"0x9"}"#);
+        assert_eq!(r["result"]["total"], 2);
+        assert_eq!(r["result"]["peers"][0]["address"], "10.0.0.2:8333");
+        let r = call("listpeers", r#"{"offset":3,"limit":5}"#);
+        assert_eq!(r["result"]["total"], 5);
+        assert_eq!(r["result"]["peers"].as_array().unwrap().len(), 2);
+        let r = call("listpeers", r#"{"status":"connected_recently"}"#);
+        assert_eq!(r["result"]["total"], 1);
+        assert_eq!(call("listpeers", r#"{"net":"tor"}"#)["error"]["code"], RpcError::INVALID_PARAMS);
+
+        // Ban, listagem e limpeza
+        let r = call("setban", r#"["10.0.0.0/30","add","12h","teste"]"#);
+        assert_eq!(r["result"]["affected"], 3);
+        let r = call("listbanned", "[]");
+        assert_eq!(r["result"][0]["subnet"], "10.0.0.0/30");
+        assert_eq!(r["result"][0]["reason"], "teste");
+        assert!(r["result"][0]["until"].is_u64());
+        assert_eq!(call("listpeers", r#"{"status":"banned"}"#)["result"]["total"], 3);
+        assert_eq!(call("setban", r#"["10.0.0.9","remove"]"#)["error"]["code"], RpcError::MISC_ERROR);
+        assert_eq!(call("setban", r#"["10.0.0.9","drop"]"#)["error"]["code"], RpcError::INVALID_PARAMS);
+        for bantime in ["0", "18446744073709551615", r#""0s""#, r#""36600d""#] {
+            let params = format!(r#"["10.0.0.9","add",{}]"#, bantime);
+            assert_eq!(call("setban", &params)["error"]["code"], RpcError::INVALID_PARAMS, "{}", bantime);
+        }
+        assert_eq!(call("clearbanned", "[]")["result"], 1);
+        assert_eq!(call("listbanned", "[]")["result"], serde_json::json!([]));
+        assert_eq!(call("listpeers", r#"{"status":"banned"}"#)["result"]["total"], 0);
+
+        // Sem cliente conectado e sem crawl em andamento
+        assert_eq!(call("getpeerinfo", "[]")["result"], serde_json::json!([]));
+        let r = call("getcrawlstatus", "[]");
+        assert_eq!(r["result"]["running"], false);
+        assert_eq!(r["result"]["queued"], 0);
+        assert_eq!(call("stopcrawl", "[]")["error"]["code"], RpcError::MISC_ERROR);
+        let r = call("getdnsstats", "[]");
+        assert_eq!(r["result"]["queries"], 0);
+        assert_eq!(r["result"]["malformed"], 0);
+
+        assert_eq!(call("setloglevel", r#"["crawler=debug"]"#)["result"]["filter"], "info,crawler=debug");
+        assert_eq!(call("setloglevel", r#"["loud"]"#)["error"]["code"], RpcError::INVALID_PARAMS);
+        assert_eq!(call("setloglevel", "[]")["result"]["filter"], "info,crawler=debug");
+        // Nomes desconhecidos e posicionais a mais são erro, não silêncio
+        let r = call("setloglevel", r#"{"dns":"trace"}"#);
+        assert_eq!(r["error"]["code"], RpcError::INVALID_PARAMS);
+        assert_eq!(r["error"]["message"], "unknown parameter for setloglevel: dns");
+        assert_eq!(call("setloglevel", r#"{"filter":"dns=trace"}"#)["result"]["filter"], "info,crawler=debug,dns=trace");
+        assert_eq!(call("getinfo", r#"["x"]"#)["error"]["code"], RpcError::INVALID_PARAMS);
+        assert_eq!(call("listpeers", "[5]")["error"]["code"], RpcError::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_rpc_server_answers_post_only() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        serve_rpc(addr, rpc_context()).unwrap();
+        let send = |request: &str| {
+            let mut conn = std::net::TcpStream::connect(addr).unwrap();
+            conn.write_all(request.as_bytes()).unwrap();
+            let mut answer = String::new();
+            conn.read_to_string(&mut answer).unwrap();
+            answer
+        };
+
+        let body = r#"{"jsonrpc":"2.0","method":"getinfo","id":1}"#;
+        let post = |authorization: &str| {
+            send(&format!(
+                "POST / HTTP/1.1\r\nAuthorization: {}\r\nContent-Length: {}\r\n\r\n{}",
+                authorization, body.len(), body
+            ))
+        };
+        // Um cliente parado não segura os outros
+        let _stalled = std::net::TcpStream::connect(addr).unwrap();
+        let started = std::time::Instant::now();
+        let answer = post(&basic("teste", "senha"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(answer.starts_with("HTTP/1.1 200 OK"));
+        let json: serde_json::Value = serde_json::from_str(answer.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["result"]["peers"], 0);
+        // A pausa depois da senha errada não atrasa as outras conexões
+        let wrong = std::thread::spawn(move || {
+            let mut conn = std::net::TcpStream::connect(addr).unwrap();
+            write!(conn, "POST / HTTP/1.1\r\nAuthorization: {}\r\nContent-Length: 0\r\n\r\n", basic("teste", "errada")).unwrap();
+            let mut answer = String::new();
+            conn.read_to_string(&mut answer).unwrap();
+            (answer, std::time::Instant::now())
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(post(&basic("teste", "senha")).starts_with("HTTP/1.1 200 OK"));
+        let answered = std::time::Instant::now();
+        let (denied, denied_at) = wrong.join().unwrap();
+        assert!(answered < denied_at);
+        assert!(denied.starts_with("HTTP/1.1 401"));
+        assert!(denied.contains("WWW-Authenticate: Basic realm=\"jsonrpc\""));
+        let get = format!("GET / HTTP/1.1\r\nAuthorization: {}\r\n\r\n", basic("teste", "senha"));
+        assert!(send(&get).starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn test_http_request_limits() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let read = |request: Vec<u8>, trickle: bool| {
+            let writer = std::thread::spawn(move || {
+                let mut conn = std::net::TcpStream::connect(addr).unwrap();
+                for chunk in request.chunks(if trickle { 1 } else { request.len().max(1) }) {
+                    if conn.write_all(chunk).is_err() {
+                        break;
+                    }
+                    if trickle {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                conn
+            });
+            let (stream, _) = listener.accept().unwrap();
+            let result = read_request_within(&stream, Duration::from_millis(300));
+            drop(stream);
+            let _ = writer.join();
+            result
+        };
+
+        let ok = read(b"GET /metrics?x=1 HTTP/1.1\r\nHost: a\r\n\r\n".to_vec(), false).unwrap();
+        assert_eq!((ok.method.as_str(), ok.path.as_str(), ok.header("host")), ("GET", "/metrics", Some("a")));
+
+        let huge = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "a".repeat(10_000));
+        assert_eq!(read(huge.into_bytes(), false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        let many = format!("GET / HTTP/1.1\r\n{}\r\n", "X: 1\r\n".repeat(100));
+        assert_eq!(read(many.into_bytes(), false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        // Cada byte chega antes do timeout de leitura, mas o pedido inteiro não
+        let slow = read(b"GET / HTTP/1.1\r\nHost: devagar\r\n\r\n".to_vec(), true).unwrap_err();
+        assert_eq!(slow.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_rpc_auth_cookie_rpcauth_and_allowip() {
+        use hmac::{Hmac, Mac};
+
+        let local: std::net::IpAddr = "127.0.0.1".parse().unwrap();
+        let remote: std::net::IpAddr = "192.0.2.7".parse().unwrap();
+        let mut auth = RpcAuth::default();
+        assert_eq!(auth.check(local, None), Err(Denied::Unauthorized));
+
+        // Cookie aleatório, legível só pelo dono, apagado no encerramento
+        let path = temp_path("cookie");
+        // Um .tmp esquecido e legível por todos não pode emprestar suas permissões ao cookie
+        let stale = std::path::PathBuf::from(format!("{}.tmp", path.display()));
+        std::fs::write(&stale, "velho").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&stale, std::fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        auth.write_cookie(&path).unwrap();
+        assert!(!stale.exists());
+        let cookie = std::fs::read_to_string(&path).unwrap();
+        let (user, password) = cookie.split_once(':').unwrap();
+        assert_eq!(user, COOKIE_USER);
+        assert_eq!(password.len(), 64);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(auth.check(local, Some(&basic(user, password))), Ok(()));
+        assert_eq!(auth.check(local, Some(&basic(user, "x"))), Err(Denied::Unauthorized));
+        assert_eq!(auth.check(local, Some("Bearer abc")), Err(Denied::Unauthorized));
+
+        // rpcauth: HMAC-SHA256 com o salt como chave
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"cafebabe").unwrap();
+        mac.update(b"p:ss");
+        let spec = format!("ops:cafebabe${}", hex::encode(mac.finalize().into_bytes()));
+        auth.add(Credential::parse_rpcauth(&spec).unwrap());
+        assert_eq!(auth.check(local, Some(&basic("ops", "p:ss"))), Ok(()));
+        assert_eq!(auth.check(local, Some(&basic("ops", "pass"))), Err(Denied::Unauthorized));
+        assert!(Credential::parse_rpcauth("ops:cafebabe$zz").is_err());
+        assert!(Credential::parse_rpcauth("ops").is_err());
+
+        // Fora da loopback só com --rpc-allowip, mesmo com a senha certa
+        assert_eq!(auth.check(remote, Some(&basic("ops", "p:ss"))), Err(Denied::Forbidden));
+        assert!(auth.allows(local) && !auth.allows(remote));
+        auth.allow("192.0.2.0/24".parse().unwrap());
+        assert!(auth.allows(remote));
+        assert_eq!(auth.check(remote, Some(&basic("ops", "p:ss"))), Ok(()));
+        assert_eq!(auth.check("198.51.100.1".parse().unwrap(), Some(&basic("ops", "p:ss"))), Err(Denied::Forbidden));
+
+        auth.remove_cookie();
+        assert!(!path.exists());
+
+        // Escutar fora da loopback exige dizer quem pode chamar
+        use clap::Parser;
+        let listen = |args: &[&str]| crate::cli::Cli::try_parse_from([&["seeder"], args].concat()).unwrap().validate();
+        assert!(listen(&["--rpc-listen", "127.0.0.1:8337"]).is_ok());
+        assert!(listen(&["--rpc-listen", "[::1]:8337"]).is_ok());
+        assert!(listen(&["--rpc-listen", "0.0.0.0:8337"]).is_err());
+        assert!(listen(&["--rpc-listen", "0.0.0.0:8337", "--rpc-allowip", "192.0.2.0/24"]).is_ok());
+    }
+}
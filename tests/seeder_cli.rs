@@ -0,0 +1,78 @@
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+fn temp_path(name: &str) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("seeder_cli_test_{}_{}", name, nanos))
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(10), "esperando {}", what);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn cli(addr: &str, cookie: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_seeder-cli"))
+        .args(["--rpc-connect", addr, "--rpc-cookie-file"])
+        .arg(cookie)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "falhou: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_seeder_cli_drives_a_daemonized_seeder() {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let cookie = temp_path("cookie");
+    // Sem stdin, como um daemon: o prompt não pode encerrar nem girar em falso
+    let mut seeder = Command::new(env!("CARGO_BIN_EXE_bitcoin_rust_seeder"))
+        .args(["--db", "memory", "--rpc-listen", &addr, "--rpc-cookie-file"])
+        .arg(&cookie)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for("o cookie", || cookie.exists());
+
+    let info: serde_json::Value = serde_json::from_str(&stdout(&cli(&addr, &cookie, &["getinfo"]))).unwrap();
+    assert_eq!(info["peers"], 0);
+
+    assert_eq!(stdout(&cli(&addr, &cookie, &["addnode", "203.0.113.5:8333"])), "");
+    let page: serde_json::Value =
+        serde_json::from_str(&stdout(&cli(&addr, &cookie, &["listpeers", "status=never_tried", "limit=5"]))).unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["peers"][0]["address"], "203.0.113.5:8333");
+
+    // Erros do RPC, credenciais erradas e parâmetros misturados falham com mensagem
+    let unknown = cli(&addr, &cookie, &["nope"]);
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("error code: -32601"));
+    let wrong = Command::new(env!("CARGO_BIN_EXE_seeder-cli"))
+        .args(["--rpc-connect", &addr, "--rpc-user", "x", "--rpc-password", "y", "getinfo"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&wrong.stderr).contains("credenciais RPC incorretas"));
+    assert!(!cli(&addr, &cookie, &["listpeers", "limit=5", "extra"]).status.success());
+    let level = cli(&addr, &cookie, &["setloglevel", "dns=trace"]);
+    assert!(!level.status.success());
+    assert!(String::from_utf8_lossy(&level.stderr).contains("unknown parameter for setloglevel: dns"));
+    assert!(stdout(&cli(&addr, &cookie, &["setloglevel", "filter=dns=trace"])).contains("dns=trace"));
+
+    assert_eq!(stdout(&cli(&addr, &cookie, &["stop"])), "Seeder stopping\n");
+    wait_for("o seeder encerrar", || seeder.try_wait().unwrap().is_some());
+    assert!(!cookie.exists());
+}